@@ -8,8 +8,9 @@ yellow='\033[1;33m'
 green='\033[0;32m'
 no_color='\033[0m'
 
-for app_root in "$backend_dir"/*; do
-    package=$(basename "$app_root")
+# embedded wasm first: share_ledger goes into icrc7, icrc7 into factory
+for package in share_ledger icrc7 factory test_ledger test_receiver; do
+    app_root="$backend_dir/$package"
     did_file="$app_root/$package.did"
 
     echo "${green}Building $package in $app_root${no_color}"
@@ -39,6 +40,9 @@ for app_root in "$backend_dir"/*; do
         echo "${yellow}ic-wasm not found. Skipping shrinking $package.${no_color}"
     fi
 
-    dfx generate "$package"
+    # spawned by its collection, not deployed on its own
+    if [ "$package" != "share_ledger" ]; then
+        dfx generate "$package"
+    fi
 
 done
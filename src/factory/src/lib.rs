@@ -8,6 +8,13 @@ use ic_cdk::{
     update,
 };
 
+#[derive(CandidType, Deserialize)]
+pub enum Transferability {
+    Transferable,
+    Soulbound,
+    BurnOnlyByIssuer,
+}
+
 #[derive(CandidType, Deserialize)]
 pub struct Config {
     pub name: String,
@@ -20,6 +27,14 @@ pub struct Config {
     pub supply_cap: Option<u128>,
     pub tx_window: u64,
     pub permitted_drift: u64,
+    pub transferability: Option<Transferability>,
+    pub payment_ledger: Option<Principal>,
+}
+
+/// Init variant of the collection's canister argument.
+#[derive(CandidType)]
+pub enum CollectionArgs {
+    Init(Config),
 }
 
 #[derive(CandidType, Deserialize)]
@@ -31,6 +46,8 @@ pub struct CreateArg {
     pub description: Option<String>,
     pub logo: Option<String>,
     pub supply_cap: Option<u128>,
+    pub transferability: Option<Transferability>,
+    pub payment_ledger: Option<Principal>,
 }
 
 impl From<(Principal, CreateArg)> for Config {
@@ -46,6 +63,8 @@ impl From<(Principal, CreateArg)> for Config {
             supply_cap: arg.supply_cap,
             tx_window: 0,
             permitted_drift: 0,
+            transferability: arg.transferability,
+            payment_ledger: arg.payment_ledger,
         }
     }
 }
@@ -53,13 +72,18 @@ impl From<(Principal, CreateArg)> for Config {
 const WASM: &[u8] =
     std::include_bytes!("./../../../target/wasm32-unknown-unknown/release/icrc7.wasm");
 
+/// Seconds of idle burn a collection keeps in reserve before it freezes, the
+/// system default of 30 days. Zero let a canister run dry and be deleted
+/// without any warning.
+const FREEZING_THRESHOLD: u64 = 2_592_000;
+
 pub async fn get_an_address(caller: &Principal) -> Principal {
     ic_cdk::println!("{}", caller.clone());
     let canister_setting = CanisterSettings {
         controllers: Some(vec![caller.clone(), ic_cdk::id()]),
         compute_allocation: Some(Nat::from(0_u64)),
         memory_allocation: Some(Nat::from(0_u64)),
-        freezing_threshold: Some(Nat::from(0_u64)),
+        freezing_threshold: Some(Nat::from(FREEZING_THRESHOLD)),
     };
     let args = CreateCanisterArgument {
         settings: Some(canister_setting),
@@ -111,7 +135,7 @@ pub async fn create_icrc7_collection(arg: CreateArg) -> Principal {
     if address == Principal::anonymous() {
         ic_cdk::trap("Failed to get an address")
     }
-    let arg = Encode!(&arg).unwrap();
+    let arg = Encode!(&CollectionArgs::Init(arg)).unwrap();
     match install_wasm(WASM.to_vec(), address, arg).await {
         true => address,
         false => ic_cdk::trap("Failed to install code"),
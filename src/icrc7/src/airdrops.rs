@@ -0,0 +1,415 @@
+use crate::{
+    errors::AirdropError,
+    market::move_token,
+    migration::budget_exhausted,
+    pause::pause_state,
+    reveal::TokenMetadata,
+    state::{
+        get_total_supply, insert_minted, Token, AIRDROPS, AIRDROP_FAILURES, AIRDROP_RECIPIENTS,
+        CONFIG, TOKENS,
+    },
+};
+use b3_utils::{
+    ledger::ICRCAccount,
+    memory::types::{Bound, Storable},
+};
+use candid::{CandidType, Decode, Encode, Principal};
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Delay before a job held up by a pause is looked at again.
+const PAUSED_RETRY: Duration = Duration::from_secs(60);
+/// Most recipients a single upload may add.
+pub const MAX_UPLOAD: usize = 10_000;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum AirdropMode {
+    /// Mints each token, named `"{name} #{id}"` after the template.
+    Mint { template: TokenMetadata },
+    /// Transfers each token out of an account of the job creator.
+    Transfer { from: ICRCAccount },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AirdropStatus {
+    /// Recipients are still being uploaded.
+    Uploading,
+    Running,
+    Completed,
+    Cancelled,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct AirdropRecipient {
+    pub to: ICRCAccount,
+    pub token_id: u128,
+}
+
+impl Storable for AirdropRecipient {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct AirdropFailure {
+    /// Position of the recipient in the job.
+    pub index: u64,
+    pub recipient: AirdropRecipient,
+    pub reason: String,
+    pub at: u64,
+}
+
+impl Storable for AirdropFailure {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct Airdrop {
+    pub id: u64,
+    pub creator: Principal,
+    pub mode: AirdropMode,
+    pub status: AirdropStatus,
+    /// Recipients uploaded, including those queued again by a retry.
+    pub total: u64,
+    /// Recipients handled so far, the job resumes from here.
+    pub processed: u64,
+    pub succeeded: u64,
+    /// Failures not retried yet.
+    pub failed: u64,
+    pub retries: u32,
+    pub created_at: u64,
+    pub started_at: Option<u64>,
+    pub finished_at: Option<u64>,
+}
+
+impl Storable for Airdrop {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+pub fn airdrop(id: &u64) -> Option<Airdrop> {
+    AIRDROPS.with(|a| a.borrow().get(id))
+}
+
+pub fn airdrops(prev: Option<u64>, take: Option<u128>) -> Vec<Airdrop> {
+    let take = take.unwrap_or(100) as usize;
+
+    AIRDROPS.with(|a| {
+        let a = a.borrow();
+        let end = prev.unwrap_or(a.len()).min(a.len());
+        (0..end)
+            .rev()
+            .filter_map(|id| a.get(&id))
+            .take(take)
+            .collect()
+    })
+}
+
+pub fn failures(id: u64, prev: Option<u64>, take: Option<u128>) -> Vec<AirdropFailure> {
+    let take = take.unwrap_or(100) as usize;
+    let start = prev.map_or(0, |prev| prev + 1);
+
+    AIRDROP_FAILURES.with(|f| {
+        f.borrow()
+            .range((id, start)..=(id, u64::MAX))
+            .map(|(_, failure)| failure)
+            .take(take)
+            .collect()
+    })
+}
+
+fn save(airdrop: Airdrop) {
+    AIRDROPS.with(|a| a.borrow_mut().insert(airdrop.id, airdrop));
+}
+
+/// Job the caller created, the only kind they may change.
+fn own_airdrop(id: u64) -> Result<Airdrop, AirdropError> {
+    let airdrop = airdrop(&id).ok_or(AirdropError::NoAirdrop)?;
+
+    if airdrop.creator != ic_cdk::caller() {
+        return Err(AirdropError::Unauthorized);
+    }
+
+    Ok(airdrop)
+}
+
+pub fn create(mode: AirdropMode) -> Result<u64, AirdropError> {
+    let creator = ic_cdk::caller();
+
+    if let AirdropMode::Transfer { from } = &mode {
+        if from.owner() != creator {
+            return Err(AirdropError::Unauthorized);
+        }
+    }
+
+    let id = AIRDROPS.with(|a| a.borrow().len());
+    save(Airdrop {
+        id,
+        creator,
+        mode,
+        status: AirdropStatus::Uploading,
+        total: 0,
+        processed: 0,
+        succeeded: 0,
+        failed: 0,
+        retries: 0,
+        created_at: ic_cdk::api::time(),
+        started_at: None,
+        finished_at: None,
+    });
+
+    Ok(id)
+}
+
+/// Appends recipients, returns how many the job holds.
+pub fn upload(id: u64, recipients: Vec<AirdropRecipient>) -> Result<u64, AirdropError> {
+    let mut airdrop = own_airdrop(id)?;
+
+    if airdrop.status != AirdropStatus::Uploading {
+        return Err(AirdropError::NotUploading);
+    }
+    if recipients.len() > MAX_UPLOAD {
+        return Err(AirdropError::TooManyRecipients {
+            max: MAX_UPLOAD as u64,
+        });
+    }
+
+    AIRDROP_RECIPIENTS.with(|r| {
+        let mut r = r.borrow_mut();
+        for recipient in recipients {
+            r.insert((id, airdrop.total), recipient);
+            airdrop.total += 1;
+        }
+    });
+
+    let total = airdrop.total;
+    save(airdrop);
+
+    Ok(total)
+}
+
+pub fn start(id: u64) -> Result<(), AirdropError> {
+    let mut airdrop = own_airdrop(id)?;
+
+    if airdrop.status != AirdropStatus::Uploading {
+        return Err(AirdropError::NotUploading);
+    }
+
+    airdrop.status = AirdropStatus::Running;
+    airdrop.started_at = Some(ic_cdk::api::time());
+    save(airdrop);
+    schedule(Duration::ZERO);
+
+    Ok(())
+}
+
+/// Stops the job where it is, recipients already handled keep their tokens.
+pub fn cancel(id: u64) -> Result<(), AirdropError> {
+    let mut airdrop = own_airdrop(id)?;
+
+    if matches!(
+        airdrop.status,
+        AirdropStatus::Completed | AirdropStatus::Cancelled
+    ) {
+        return Err(AirdropError::Finished);
+    }
+
+    airdrop.status = AirdropStatus::Cancelled;
+    airdrop.finished_at = Some(ic_cdk::api::time());
+    save(airdrop);
+
+    Ok(())
+}
+
+/// Queues the failed recipients again at the end of a finished job.
+pub fn retry(id: u64) -> Result<u64, AirdropError> {
+    let mut airdrop = own_airdrop(id)?;
+
+    if airdrop.status != AirdropStatus::Completed {
+        return Err(AirdropError::NotCompleted);
+    }
+
+    let failed: Vec<AirdropFailure> = AIRDROP_FAILURES.with(|f| {
+        f.borrow()
+            .range((id, 0)..=(id, u64::MAX))
+            .map(|(_, failure)| failure)
+            .collect()
+    });
+    if failed.is_empty() {
+        return Ok(0);
+    }
+
+    AIRDROP_FAILURES.with(|f| {
+        let mut f = f.borrow_mut();
+        for failure in failed.iter() {
+            f.remove(&(id, failure.index));
+        }
+    });
+    AIRDROP_RECIPIENTS.with(|r| {
+        let mut r = r.borrow_mut();
+        for failure in failed.iter() {
+            r.insert((id, airdrop.total), failure.recipient.clone());
+            airdrop.total += 1;
+        }
+    });
+
+    airdrop.failed = 0;
+    airdrop.retries += 1;
+    airdrop.status = AirdropStatus::Running;
+    airdrop.finished_at = None;
+    save(airdrop);
+    schedule(Duration::ZERO);
+
+    Ok(failed.len() as u64)
+}
+
+fn mint(template: &TokenMetadata, recipient: &AirdropRecipient) -> Result<(), String> {
+    let supply_cap = CONFIG.with(|c| c.borrow().get().supply_cap);
+    if supply_cap.is_some_and(|cap| get_total_supply() >= cap) {
+        return Err("Supply Cap Reached".into());
+    }
+    if TOKENS.with(|tokens| tokens.borrow().contains_key(&recipient.token_id)) {
+        return Err("Id Exist".into());
+    }
+
+    insert_minted(Token {
+        id: recipient.token_id,
+        owner: recipient.to.clone(),
+        name: format!("{} #{}", template.name, recipient.token_id),
+        image: template.image.clone(),
+        description: template.description.clone(),
+        approvals: Vec::new(),
+        transferability: None,
+        user: None,
+    });
+
+    Ok(())
+}
+
+fn transfer(from: &ICRCAccount, recipient: &AirdropRecipient) -> Result<(), String> {
+    match TOKENS.with(|tokens| tokens.borrow().get(&recipient.token_id)) {
+        None => return Err("Invalid Id".into()),
+        Some(token) if token.owner == recipient.to => return Err("Self Transfer".into()),
+        Some(_) => {}
+    }
+
+    move_token(recipient.token_id, from, recipient.to.clone())
+        .map(|_| ())
+        .map_err(|e| format!("{:?}", e))
+}
+
+/// Works through the recipients of a running job until it is done or the
+/// slice runs out of instructions. Returns whether the job is still running.
+fn process(mut airdrop: Airdrop) -> bool {
+    while airdrop.processed < airdrop.total && !budget_exhausted() {
+        let index = airdrop.processed;
+        let recipient = AIRDROP_RECIPIENTS
+            .with(|r| r.borrow().get(&(airdrop.id, index)))
+            .unwrap();
+
+        let result = match &airdrop.mode {
+            AirdropMode::Mint { template } => mint(template, &recipient),
+            AirdropMode::Transfer { from } => transfer(from, &recipient),
+        };
+
+        match result {
+            Ok(()) => airdrop.succeeded += 1,
+            Err(reason) => {
+                airdrop.failed += 1;
+                AIRDROP_FAILURES.with(|f| {
+                    f.borrow_mut().insert(
+                        (airdrop.id, index),
+                        AirdropFailure {
+                            index,
+                            recipient,
+                            reason,
+                            at: ic_cdk::api::time(),
+                        },
+                    )
+                });
+            }
+        }
+        airdrop.processed += 1;
+    }
+
+    let running = airdrop.processed < airdrop.total;
+    if !running {
+        airdrop.status = AirdropStatus::Completed;
+        airdrop.finished_at = Some(ic_cdk::api::time());
+    }
+    save(airdrop);
+
+    running
+}
+
+fn schedule(delay: Duration) {
+    ic_cdk_timers::set_timer(delay, run_pending);
+}
+
+/// Timer slice, handles running jobs oldest first.
+fn run_pending() {
+    let running: Vec<Airdrop> = AIRDROPS.with(|a| {
+        a.borrow()
+            .iter()
+            .map(|(_, airdrop)| airdrop)
+            .filter(|airdrop| airdrop.status == AirdropStatus::Running)
+            .collect()
+    });
+
+    let pause = pause_state();
+    let mut held = false;
+
+    for airdrop in running {
+        let paused = match airdrop.mode {
+            AirdropMode::Mint { .. } => pause.mints,
+            AirdropMode::Transfer { .. } => pause.transfers,
+        };
+        if paused {
+            held = true;
+            continue;
+        }
+
+        if process(airdrop) || budget_exhausted() {
+            return schedule(Duration::ZERO);
+        }
+    }
+
+    if held {
+        schedule(PAUSED_RETRY);
+    }
+}
+
+/// Timers do not survive upgrades, running jobs are picked up again.
+pub fn schedule_all() {
+    let running = AIRDROPS.with(|a| {
+        a.borrow()
+            .iter()
+            .any(|(_, airdrop)| airdrop.status == AirdropStatus::Running)
+    });
+
+    if running {
+        schedule(Duration::ZERO);
+    }
+}
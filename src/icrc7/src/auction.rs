@@ -0,0 +1,399 @@
+use crate::{
+    errors::MarketError,
+    locks::{lock_token, unlock_token, Lock},
+    market::{remove_listing, sellable, settle_sale},
+    payments::{collect, ledger_fee, pay_out, payment_ledger},
+    state::{AUCTIONS, TOKENS},
+    types::{BidArgs, CreateAuctionArgs},
+};
+use b3_utils::{
+    ledger::ICRCAccount,
+    memory::types::{Bound, Storable},
+};
+use candid::{CandidType, Decode, Encode, Principal};
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Longest an auction may run, 30 days in nanoseconds.
+pub const MAX_AUCTION_DURATION: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+/// Delay before a settlement that failed on the ledger is retried.
+const SETTLEMENT_RETRY: Duration = Duration::from_secs(60);
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum AuctionKind {
+    /// Ascending bids, the highest bid at or above `reserve_price` wins.
+    /// A bid placed within `extension` of the end pushes the end back to
+    /// `extension` after the bid. `extension` is at most
+    /// `MAX_AUCTION_DURATION`.
+    English {
+        reserve_price: u128,
+        min_increment: u128,
+        extension: u64,
+    },
+    /// The price falls linearly from `start_price` to `reserve_price` over the
+    /// auction, the first bid at the current price wins.
+    Dutch {
+        start_price: u128,
+        reserve_price: u128,
+    },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct Bid {
+    pub bidder: ICRCAccount,
+    pub amount: u128,
+    pub at: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct Auction {
+    pub token_id: u128,
+    pub seller: ICRCAccount,
+    pub kind: AuctionKind,
+    /// Ledger holding the escrowed bids, fixed for the life of the auction.
+    pub ledger: Principal,
+    pub start_at: u64,
+    pub end_at: u64,
+    pub highest_bid: Option<Bid>,
+}
+
+impl Storable for Auction {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Auction {
+    /// Smallest amount the next bid has to offer at `now`.
+    pub fn price(&self, now: u64) -> u128 {
+        match self.kind {
+            AuctionKind::English {
+                reserve_price,
+                min_increment,
+                ..
+            } => match &self.highest_bid {
+                None => reserve_price,
+                Some(bid) => bid.amount.saturating_add(min_increment.max(1)),
+            },
+            AuctionKind::Dutch {
+                start_price,
+                reserve_price,
+            } => {
+                let duration = (self.end_at - self.start_at) as u128;
+                let elapsed = (now.clamp(self.start_at, self.end_at) - self.start_at) as u128;
+                let drop = start_price - reserve_price;
+
+                start_price - drop / duration * elapsed - drop % duration * elapsed / duration
+            }
+        }
+    }
+
+    pub fn has_ended(&self, now: u64) -> bool {
+        now >= self.end_at
+    }
+}
+
+pub fn auction(token_id: &u128) -> Option<Auction> {
+    AUCTIONS.with(|a| a.borrow().get(token_id))
+}
+
+pub fn auctions(prev: Option<u128>, take: Option<u128>) -> Vec<Auction> {
+    let take = take.unwrap_or(100) as usize;
+
+    AUCTIONS.with(|a| {
+        let a = a.borrow();
+        match prev {
+            None => a.iter().map(|(_, auction)| auction).take(take).collect(),
+            Some(prev) => a
+                .range(prev..)
+                .filter(|(id, _)| *id != prev)
+                .map(|(_, auction)| auction)
+                .take(take)
+                .collect(),
+        }
+    })
+}
+
+fn save(auction: Auction) {
+    AUCTIONS.with(|a| a.borrow_mut().insert(auction.token_id, auction));
+}
+
+fn remove(token_id: &u128) {
+    AUCTIONS.with(|a| a.borrow_mut().remove(token_id));
+}
+
+pub fn create(arg: CreateAuctionArgs) -> Result<(), MarketError> {
+    let seller = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let ledger = payment_ledger().ok_or(MarketError::NoPaymentLedger)?;
+
+    if arg.duration == 0 || arg.duration > MAX_AUCTION_DURATION {
+        return Err(MarketError::InvalidAuction);
+    }
+    if let AuctionKind::English { extension, .. } = arg.kind {
+        if extension > MAX_AUCTION_DURATION {
+            return Err(MarketError::InvalidAuction);
+        }
+    }
+    let valid_price = match arg.kind {
+        AuctionKind::English {
+            reserve_price,
+            min_increment,
+            ..
+        } => reserve_price > 0 && min_increment > 0,
+        AuctionKind::Dutch {
+            start_price,
+            reserve_price,
+        } => reserve_price > 0 && start_price > reserve_price,
+    };
+    if !valid_price {
+        return Err(MarketError::InvalidPrice);
+    }
+
+    let token = match TOKENS.with(|tokens| tokens.borrow().get(&arg.token_id)) {
+        None => ic_cdk::trap("Invalid Id"),
+        Some(token) => token,
+    };
+    if token.owner != seller {
+        return Err(MarketError::Unauthorized {
+            tokens_ids: vec![token.id],
+        });
+    }
+    sellable(&token)?;
+
+    lock_token(token.id, Lock::Auction);
+    remove_listing(&token.id);
+
+    let now = ic_cdk::api::time();
+    let auction = Auction {
+        token_id: token.id,
+        seller,
+        kind: arg.kind,
+        ledger,
+        start_at: now,
+        end_at: now + arg.duration,
+        highest_bid: None,
+    };
+    schedule(&auction);
+    save(auction);
+
+    Ok(())
+}
+
+/// Only the seller may cancel, and only before anyone has bid.
+pub fn cancel(token_id: u128) -> Result<(), MarketError> {
+    let auction = auction(&token_id).ok_or(MarketError::NoAuction)?;
+
+    if auction.seller.owner() != ic_cdk::caller() {
+        return Err(MarketError::Unauthorized {
+            tokens_ids: vec![token_id],
+        });
+    }
+    if auction.highest_bid.is_some() {
+        return Err(MarketError::HasBids);
+    }
+
+    remove(&token_id);
+    unlock_token(&token_id);
+
+    Ok(())
+}
+
+/// Escrows the bid on the auction ledger via ICRC-2 `transfer_from`.
+/// An English bid refunds the bid it outbids, a Dutch bid buys the token at
+/// the current price.
+pub async fn bid(arg: BidArgs) -> Result<(), MarketError> {
+    let bidder = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let auction = auction(&arg.token_id).ok_or(MarketError::NoAuction)?;
+    let now = ic_cdk::api::time();
+
+    if auction.has_ended(now) {
+        return Err(MarketError::AuctionEnded);
+    }
+    if auction.seller == bidder {
+        return Err(MarketError::Unauthorized {
+            tokens_ids: vec![arg.token_id],
+        });
+    }
+    let min_bid = auction.price(now);
+    if arg.amount < min_bid {
+        return Err(MarketError::BidTooLow { min_bid });
+    }
+
+    match auction.kind {
+        AuctionKind::English { .. } => bid_english(auction, bidder, arg.amount).await,
+        AuctionKind::Dutch { .. } => bid_dutch(auction, bidder, arg.amount).await,
+    }
+}
+
+async fn bid_english(
+    auction: Auction,
+    bidder: ICRCAccount,
+    amount: u128,
+) -> Result<(), MarketError> {
+    let ledger = auction.ledger;
+
+    if let Err(msg) = collect(ledger, bidder.clone(), amount).await {
+        return Err(MarketError::PaymentFailed { msg });
+    }
+
+    // other bids may have landed while the payment was in flight
+    let now = ic_cdk::api::time();
+    let rejection = match self::auction(&auction.token_id) {
+        None => Some(MarketError::AuctionEnded),
+        Some(current) if current.has_ended(now) => Some(MarketError::AuctionEnded),
+        Some(current) if amount < current.price(now) => Some(MarketError::BidTooLow {
+            min_bid: current.price(now),
+        }),
+        Some(mut current) => {
+            let outbid = current.highest_bid.replace(Bid {
+                bidder: bidder.clone(),
+                amount,
+                at: now,
+            });
+            if let AuctionKind::English { extension, .. } = current.kind {
+                if current.end_at - now < extension {
+                    current.end_at = now.saturating_add(extension);
+                }
+            }
+            save(current);
+
+            if let Some(outbid) = outbid {
+                refund(ledger, outbid.bidder, outbid.amount).await;
+            }
+            None
+        }
+    };
+
+    match rejection {
+        None => Ok(()),
+        Some(e) => {
+            refund(ledger, bidder, amount).await;
+            Err(e)
+        }
+    }
+}
+
+async fn bid_dutch(auction: Auction, bidder: ICRCAccount, amount: u128) -> Result<(), MarketError> {
+    let ledger = auction.ledger;
+
+    let fee = ledger_fee(ledger)
+        .await
+        .map_err(|msg| MarketError::PaymentFailed { msg })?;
+
+    // the auction may have sold or ended while the fee was looked up, and the
+    // price kept dropping
+    let now = ic_cdk::api::time();
+    let auction = match self::auction(&auction.token_id) {
+        Some(current) if !current.has_ended(now) => current,
+        _ => return Err(MarketError::AuctionEnded),
+    };
+    let price = auction.price(now);
+    if amount < price {
+        return Err(MarketError::BidTooLow { min_bid: price });
+    }
+    // taken off the book so a second buyer cannot pay for the same token
+    remove(&auction.token_id);
+
+    if let Err(msg) = collect(ledger, bidder.clone(), price).await {
+        schedule(&auction);
+        save(auction);
+        return Err(MarketError::PaymentFailed { msg });
+    }
+
+    unlock_token(&auction.token_id);
+
+    settle_sale(ledger, fee, auction.token_id, auction.seller, bidder, price)
+        .await
+        .map(|_| ())
+}
+
+async fn refund(ledger: Principal, to: ICRCAccount, amount: u128) {
+    match ledger_fee(ledger).await {
+        Ok(fee) => pay_out(ledger, fee, to, amount).await,
+        // queued like any failed payout, the retry looks the fee up again
+        Err(_) => pay_out(ledger, 0, to, amount).await,
+    }
+}
+
+/// Closes an ended auction: the highest bid buys the token and the proceeds
+/// are split with the royalty recipient, without bids the token is released.
+pub async fn settle(token_id: u128) -> Result<(), MarketError> {
+    let auction = auction(&token_id).ok_or(MarketError::NoAuction)?;
+
+    if !auction.has_ended(ic_cdk::api::time()) {
+        return Err(MarketError::AuctionNotEnded);
+    }
+
+    let bid = match auction.highest_bid.clone() {
+        None => {
+            remove(&token_id);
+            unlock_token(&token_id);
+            return Ok(());
+        }
+        Some(bid) => bid,
+    };
+
+    remove(&token_id);
+
+    let fee = match ledger_fee(auction.ledger).await {
+        Ok(fee) => fee,
+        Err(msg) => {
+            save(auction);
+            return Err(MarketError::PaymentFailed { msg });
+        }
+    };
+
+    unlock_token(&token_id);
+
+    settle_sale(
+        auction.ledger,
+        fee,
+        token_id,
+        auction.seller,
+        bid.bidder,
+        bid.amount,
+    )
+    .await
+    .map(|_| ())
+}
+
+fn schedule(auction: &Auction) {
+    let token_id = auction.token_id;
+    let delay = auction.end_at.saturating_sub(ic_cdk::api::time());
+
+    ic_cdk_timers::set_timer(Duration::from_nanos(delay), move || {
+        ic_cdk::spawn(settle_due(token_id))
+    });
+}
+
+/// Timer callback, follows anti-sniping extensions and retries ledger failures.
+async fn settle_due(token_id: u128) {
+    match settle(token_id).await {
+        Err(MarketError::AuctionNotEnded) => {
+            if let Some(auction) = auction(&token_id) {
+                schedule(&auction);
+            }
+        }
+        Err(MarketError::PaymentFailed { .. }) => {
+            ic_cdk_timers::set_timer(SETTLEMENT_RETRY, move || {
+                ic_cdk::spawn(settle_due(token_id))
+            });
+        }
+        _ => {}
+    }
+}
+
+/// Timers do not survive upgrades, every open auction is scheduled again.
+pub fn schedule_all() {
+    AUCTIONS.with(|a| {
+        for (_, auction) in a.borrow().iter() {
+            schedule(&auction);
+        }
+    });
+}
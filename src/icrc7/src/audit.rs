@@ -0,0 +1,129 @@
+use crate::{
+    cycles::TopUp,
+    pause::PauseState,
+    roles::Role,
+    sale::DropWindow,
+    staking::StakingConfig,
+    state::{Features, AUDIT_LOG},
+    vouchers::MinterKey,
+};
+use b3_utils::{
+    ledger::ICRCAccount,
+    memory::types::{Bound, Storable},
+};
+use candid::{CandidType, Decode, Encode, Principal};
+use serde_bytes::ByteBuf;
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub enum AuditEvent {
+    RoleGranted {
+        principal: Principal,
+        role: Role,
+    },
+    RoleRevoked {
+        principal: Principal,
+        role: Role,
+    },
+    FeaturesUpdated(Features),
+    PauseUpdated(PauseState),
+    TokensFrozen {
+        token_ids: Vec<u128>,
+    },
+    TokensUnfrozen {
+        token_ids: Vec<u128>,
+    },
+    StakingUpdated(StakingConfig),
+    MintPhaseAdded {
+        phase_id: u32,
+    },
+    ActivePhaseSet {
+        phase_id: Option<u32>,
+    },
+    AllowlistUpdated {
+        phase_id: u32,
+        added: u64,
+        removed: u64,
+    },
+    TreasurySet {
+        treasury: Option<ICRCAccount>,
+    },
+    DropSet {
+        windows: Option<Vec<DropWindow>>,
+    },
+    RevealCommitted {
+        commitment: ByteBuf,
+        size: u64,
+    },
+    Revealed {
+        offset: u64,
+    },
+    MinterKeyAdded {
+        key_id: u32,
+        key: MinterKey,
+    },
+    MinterKeyRemoved {
+        key_id: u32,
+    },
+    CyclesConfigSet {
+        low_water_mark: Option<u128>,
+        top_up: Option<TopUp>,
+    },
+    /// The balance dropped below the low-water mark.
+    CyclesLow {
+        balance: u128,
+        low_water_mark: u128,
+    },
+    CyclesToppedUp {
+        amount: u128,
+        block: u128,
+    },
+    CyclesTopUpFailed {
+        msg: String,
+    },
+    MigrationCompleted {
+        version: u32,
+    },
+    /// A payout the ledger fee would swallow, kept by the canister.
+    PayoutBelowFee {
+        ledger: Principal,
+        to: ICRCAccount,
+        amount: u128,
+        fee: u128,
+    },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct AuditLog {
+    pub at: u64,
+    pub caller: Principal,
+    pub event: AuditEvent,
+}
+
+impl Storable for AuditLog {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+/// Appends an administrative event to the audit log and returns its index.
+pub fn log_event(event: AuditEvent) -> u64 {
+    let log = AuditLog {
+        at: ic_cdk::api::time(),
+        caller: ic_cdk::caller(),
+        event,
+    };
+
+    AUDIT_LOG.with(|logs| {
+        let mut logs = logs.borrow_mut();
+        let index = logs.len();
+        logs.insert(index, log);
+        index
+    })
+}
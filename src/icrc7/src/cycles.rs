@@ -0,0 +1,209 @@
+use crate::{
+    audit::{log_event, AuditEvent},
+    errors::CyclesError,
+    state::CYCLES,
+    types::SetCyclesConfigArgs,
+};
+use b3_utils::{
+    ledger::ICRCAccount,
+    memory::types::{Bound, Storable},
+    Subaccount,
+};
+use candid::{CandidType, Decode, Encode, Nat, Principal};
+use serde_derive::{Deserialize, Serialize};
+use std::{cell::Cell, time::Duration};
+
+/// How often the balance is checked against the low-water mark.
+const CHECK_INTERVAL: Duration = Duration::from_secs(3_600);
+
+thread_local! {
+    /// Set while a withdrawal is out, so overlapping checks do not top up twice.
+    static TOPPING_UP: Cell<bool> = Cell::default();
+}
+
+/// Standing order to refill the canister from a cycles ledger, out of an
+/// ICRC-2 allowance granted to it.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TopUp {
+    pub ledger: Principal,
+    pub from: ICRCAccount,
+    /// Cycles withdrawn per top-up, the ledger fee is paid on top.
+    pub amount: u128,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TopUpAttempt {
+    pub at: u64,
+    pub amount: u128,
+    /// Ledger block of the withdrawal, `None` when it failed.
+    pub block: Option<u128>,
+    pub error: Option<String>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Default)]
+pub struct CyclesState {
+    pub low_water_mark: Option<u128>,
+    pub top_up: Option<TopUp>,
+    /// When the balance dropped below the low-water mark, `None` while it is
+    /// above. An alert is raised once per drop.
+    pub low_since: Option<u64>,
+    pub last_check_at: Option<u64>,
+    pub last_top_up: Option<TopUpAttempt>,
+}
+
+impl Storable for CyclesState {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+/// `withdraw_from` arguments of the cycles ledger.
+#[derive(CandidType)]
+struct WithdrawFromArgs {
+    spender_subaccount: Option<Subaccount>,
+    from: ICRCAccount,
+    to: Principal,
+    amount: Nat,
+    created_at_time: Option<u64>,
+}
+
+/// `withdraw_from` errors of the cycles ledger, fields not needed here are
+/// skipped when decoding.
+#[derive(CandidType, Deserialize, Debug)]
+enum WithdrawFromError {
+    GenericError { message: String, error_code: Nat },
+    TemporarilyUnavailable,
+    FailedToWithdrawFrom { rejection_reason: String },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    InvalidReceiver { receiver: Principal },
+}
+
+pub fn cycles_state() -> CyclesState {
+    CYCLES.with(|c| c.borrow().get().clone())
+}
+
+fn update_cycles(f: impl FnOnce(&mut CyclesState)) {
+    CYCLES.with(|c| {
+        let mut c = c.borrow_mut();
+        let mut state = c.get().clone();
+        f(&mut state);
+        c.set(state).unwrap();
+    });
+}
+
+pub fn balance() -> u128 {
+    ic_cdk::api::canister_balance128()
+}
+
+pub fn set_config(arg: SetCyclesConfigArgs) -> Result<(), CyclesError> {
+    if arg.top_up.as_ref().is_some_and(|top_up| top_up.amount == 0) {
+        return Err(CyclesError::InvalidAmount);
+    }
+
+    update_cycles(|state| {
+        state.low_water_mark = arg.low_water_mark;
+        state.top_up = arg.top_up.clone();
+    });
+    log_event(AuditEvent::CyclesConfigSet {
+        low_water_mark: arg.low_water_mark,
+        top_up: arg.top_up,
+    });
+
+    Ok(())
+}
+
+/// Compares the balance with the low-water mark, raising an alert when it
+/// drops below and topping up for as long as it stays there.
+pub async fn check() {
+    let state = cycles_state();
+    let balance = balance();
+    let now = ic_cdk::api::time();
+
+    let low_water_mark = match state.low_water_mark {
+        Some(mark) if balance < mark => mark,
+        _ => {
+            update_cycles(|state| {
+                state.last_check_at = Some(now);
+                state.low_since = None;
+            });
+            return;
+        }
+    };
+
+    update_cycles(|state| {
+        state.last_check_at = Some(now);
+        state.low_since.get_or_insert(now);
+    });
+    if state.low_since.is_none() {
+        log_event(AuditEvent::CyclesLow {
+            balance,
+            low_water_mark,
+        });
+    }
+
+    if let Some(top_up) = state.top_up {
+        top_up_from(top_up).await;
+    }
+}
+
+async fn top_up_from(top_up: TopUp) {
+    if TOPPING_UP.with(|t| t.replace(true)) {
+        return;
+    }
+
+    let result = withdraw_from(&top_up).await;
+
+    TOPPING_UP.with(|t| t.set(false));
+
+    let attempt = TopUpAttempt {
+        at: ic_cdk::api::time(),
+        amount: top_up.amount,
+        block: result.as_ref().ok().copied(),
+        error: result.as_ref().err().cloned(),
+    };
+    match result {
+        Ok(block) => log_event(AuditEvent::CyclesToppedUp {
+            amount: top_up.amount,
+            block,
+        }),
+        Err(msg) => log_event(AuditEvent::CyclesTopUpFailed { msg }),
+    };
+    update_cycles(|state| state.last_top_up = Some(attempt));
+}
+
+/// Has the ledger send `amount` cycles from the approving account to this
+/// canister.
+async fn withdraw_from(top_up: &TopUp) -> Result<u128, String> {
+    let args = WithdrawFromArgs {
+        spender_subaccount: None,
+        from: top_up.from.clone(),
+        to: ic_cdk::id(),
+        amount: Nat::from(top_up.amount),
+        created_at_time: None,
+    };
+
+    let result: Result<(Result<Nat, WithdrawFromError>,), _> =
+        ic_cdk::call(top_up.ledger, "withdraw_from", (args,)).await;
+
+    match result {
+        Ok((Ok(block),)) => Ok(block.0.try_into().unwrap_or(u128::MAX)),
+        Ok((Err(e),)) => Err(format!("{:?}", e)),
+        Err((_, msg)) => Err(msg),
+    }
+}
+
+/// Checks the balance every `CHECK_INTERVAL`. Timers do not survive
+/// upgrades, this runs again after each one.
+pub fn schedule() {
+    ic_cdk_timers::set_timer_interval(CHECK_INTERVAL, || ic_cdk::spawn(check()));
+}
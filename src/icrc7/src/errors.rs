@@ -1,6 +1,6 @@
 use candid::CandidType;
 
-#[derive(CandidType, Clone)]
+#[derive(CandidType, Clone, Debug)]
 pub enum TransferError {
     Unauthorized { tokens_ids: Vec<u128> },
     TooOld,
@@ -8,6 +8,30 @@ pub enum TransferError {
     Duplicate { duplicate_of: u128 },
     TemporaryUnavailable,
     GenericError { error_code: u128, msg: String },
+    Paused,
+    TokenFrozen { tokens_ids: Vec<u128> },
+    NonTransferable { tokens_ids: Vec<u128> },
+    TokenLocked { tokens_ids: Vec<u128> },
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub enum TransferAndCallError {
+    Transfer(TransferError),
+    NotCanister,
+    /// The recipient returned an error, the tokens were sent back.
+    Rejected {
+        reason: String,
+    },
+    /// The hook could not be called, the tokens were sent back.
+    CallFailed {
+        msg: String,
+    },
+}
+
+impl From<TransferError> for TransferAndCallError {
+    fn from(e: TransferError) -> Self {
+        TransferAndCallError::Transfer(e)
+    }
 }
 
 #[derive(CandidType, Clone)]
@@ -16,4 +40,208 @@ pub enum ApprovalError {
     TooOld,
     TemporaryUnavailable,
     GenericError { error_code: u128, msg: String },
+    Paused,
+    NonTransferable { tokens_ids: Vec<u128> },
+    TokenLocked { tokens_ids: Vec<u128> },
+}
+
+#[derive(CandidType, Clone)]
+pub enum BurnError {
+    Unauthorized { tokens_ids: Vec<u128> },
+    Paused,
+    TokenFrozen { tokens_ids: Vec<u128> },
+    GenericError { error_code: u128, msg: String },
+    TokenLocked { tokens_ids: Vec<u128> },
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub enum ConfigError {
+    InvalidName,
+    InvalidRoyalties { max_royalties: u16 },
+    InvalidTxWindow { max_tx_window: u64 },
+    InvalidPermittedDrift { max_permitted_drift: u64 },
+    SupplyCapBelowTotalSupply { total_supply: u128 },
+}
+
+#[derive(CandidType, Clone)]
+pub enum MarketError {
+    Unauthorized { tokens_ids: Vec<u128> },
+    NotListed,
+    InvalidPrice,
+    PriceChanged { price: u128 },
+    NoPaymentLedger,
+    PaymentFailed { msg: String },
+    Paused,
+    TokenFrozen { tokens_ids: Vec<u128> },
+    TokenLocked { tokens_ids: Vec<u128> },
+    NonTransferable { tokens_ids: Vec<u128> },
+    GenericError { error_code: u128, msg: String },
+    NoAuction,
+    InvalidAuction,
+    AuctionEnded,
+    AuctionNotEnded,
+    BidTooLow { min_bid: u128 },
+    HasBids,
+    NoOffer,
+    OfferExpired,
+    NotFractionalized,
+    InvalidShares,
+    InsufficientShares { balance: u128 },
+    NoBuyout,
+    BoughtOut,
+    NotBoughtOut,
+    SharesOutstanding { outstanding: u128 },
+    ShareLedgerFailed { msg: String },
+    PayoutBelowFee { payout: u128, fee: u128 },
+    CyclesRequired { cycles: u128 },
+    TokenMoved { tokens_ids: Vec<u128> },
+}
+
+#[derive(CandidType, Clone)]
+pub enum SwapError {
+    NoSwap,
+    Unauthorized,
+    InvalidSwap,
+    Expired,
+    NotOpen,
+    Busy,
+    DepositFailed { msg: String },
+    TooManySwaps { max: u64 },
+}
+
+#[derive(CandidType, Clone)]
+pub enum WithdrawError {
+    Unauthorized { tokens_ids: Vec<u128> },
+    NoPaymentLedger,
+    TokenFrozen { tokens_ids: Vec<u128> },
+    TokenLocked { tokens_ids: Vec<u128> },
+    WithdrawFailed { msg: String },
+}
+
+#[derive(CandidType, Clone)]
+pub enum RentalError {
+    Unauthorized { tokens_ids: Vec<u128> },
+    NotForRent,
+    InvalidRental,
+    RentalActive { expires_at: u64 },
+    PriceChanged { price: u128 },
+    NoPaymentLedger,
+    PaymentFailed { msg: String },
+    TokenFrozen { tokens_ids: Vec<u128> },
+    TokenLocked { tokens_ids: Vec<u128> },
+}
+
+#[derive(CandidType, Clone)]
+pub enum StakingError {
+    Unauthorized { tokens_ids: Vec<u128> },
+    StakingDisabled,
+    InvalidConfig,
+    InvalidDuration,
+    NotStaked,
+    StillLocked { unlock_at: u64 },
+    TokenFrozen { tokens_ids: Vec<u128> },
+    TokenLocked { tokens_ids: Vec<u128> },
+    RewardsExhausted { available: u128 },
+    FundingFailed { msg: String },
+}
+
+#[derive(CandidType, Clone, Debug)]
+pub enum MintError {
+    Unauthorized,
+    Paused,
+    NoActivePhase,
+    InvalidPhase,
+    NotEligible,
+    LimitReached {
+        limit: u32,
+    },
+    SoldOut,
+    NoPaymentLedger,
+    NoTreasury,
+    PriceChanged {
+        price: u128,
+    },
+    PaymentFailed {
+        msg: String,
+    },
+    /// The active phase follows the drop schedule.
+    DropScheduled,
+    TokenExists,
+}
+
+#[derive(CandidType, Clone)]
+pub enum RevealError {
+    NotCommitted,
+    AlreadyCommitted,
+    AlreadyRevealed,
+    InvalidCommitment,
+    TooManyEntries { size: u64 },
+    MissingEntries { uploaded: u64 },
+    NotSoldOut { remaining: u64 },
+    CommitmentMismatch,
+    RandomnessFailed { msg: String },
+    InvalidStart { uploaded: u64 },
+    Revealing,
+}
+
+#[derive(CandidType, Clone)]
+pub enum VoucherError {
+    Paused,
+    InvalidKey,
+    InvalidSignature,
+    Expired,
+    NonceUsed,
+    NotRecipient,
+    MetadataMismatch,
+    TokenExists,
+    SoldOut,
+    NoPaymentLedger,
+    NoTreasury,
+    PaymentFailed { msg: String },
+}
+
+#[derive(CandidType, Clone)]
+pub enum AirdropError {
+    NoAirdrop,
+    Unauthorized,
+    NotUploading,
+    NotCompleted,
+    Finished,
+    TooManyRecipients { max: u64 },
+}
+
+#[derive(CandidType, Clone)]
+pub enum SubscriptionError {
+    NoSubscription,
+    Unauthorized,
+    NotCanister,
+    NoEvents,
+    TooManySubscriptions { max: u64 },
+    SubscriberLimitReached { max: u64 },
+}
+
+#[derive(CandidType, Clone)]
+pub enum CyclesError {
+    InvalidAmount,
+}
+
+impl From<TransferError> for MarketError {
+    fn from(e: TransferError) -> Self {
+        match e {
+            TransferError::Unauthorized { tokens_ids } => MarketError::Unauthorized { tokens_ids },
+            TransferError::Paused => MarketError::Paused,
+            TransferError::TokenFrozen { tokens_ids } => MarketError::TokenFrozen { tokens_ids },
+            TransferError::TokenLocked { tokens_ids } => MarketError::TokenLocked { tokens_ids },
+            TransferError::NonTransferable { tokens_ids } => {
+                MarketError::NonTransferable { tokens_ids }
+            }
+            TransferError::GenericError { error_code, msg } => {
+                MarketError::GenericError { error_code, msg }
+            }
+            _ => MarketError::GenericError {
+                error_code: 0,
+                msg: "Transfer Failed".into(),
+            },
+        }
+    }
 }
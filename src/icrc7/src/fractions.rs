@@ -0,0 +1,491 @@
+use crate::{
+    errors::MarketError,
+    locks::{lock_token, unlock_token, Lock},
+    market::{move_token, sellable},
+    pause::{is_frozen, pause_state},
+    payments::{balance_of, collect_into, ledger_fee, pay_out_from, payment_ledger, transfer},
+    roles::{has_role, Role},
+    state::{CONFIG, FRACTIONS, SHARE_LEDGERS, TOKENS},
+    types::{FractionArgs, FractionalizeArgs},
+};
+use b3_utils::{
+    ledger::{ICRC1TransferError, ICRCAccount},
+    memory::types::{Bound, Storable},
+    Subaccount,
+};
+use candid::{CandidType, Decode, Encode, Nat, Principal};
+use ic_cdk::api::call::{msg_cycles_accept128, msg_cycles_available128};
+use ic_cdk::api::management_canister::main::{
+    create_canister, install_code, uninstall_code, CanisterIdRecord, CanisterInstallMode,
+    CanisterSettings, CreateCanisterArgument, InstallCodeArgument,
+};
+use serde_derive::{Deserialize, Serialize};
+
+/// First byte of every vault subaccount.
+const VAULT_DOMAIN: u8 = 3;
+
+/// Minimal ICRC-1 ledger spawned for the shares of each fractionalized token.
+const SHARE_LEDGER_WASM: &[u8] =
+    std::include_bytes!("./../../../target/wasm32-unknown-unknown/release/share_ledger.wasm");
+
+/// Cycles a new share ledger is created with, attached by the caller unless
+/// it holds `Role::Fractionalizer`.
+const SHARE_LEDGER_CYCLES: u128 = 200_000_000_000;
+
+/// Install argument of the share ledger.
+#[derive(CandidType)]
+struct ShareLedgerArgs {
+    name: String,
+    symbol: String,
+    token_id: u128,
+    holder: ICRCAccount,
+    supply: Nat,
+}
+
+/// `burn_from` argument of the share ledger.
+#[derive(CandidType)]
+struct BurnFromArgs {
+    from: ICRCAccount,
+    amount: Nat,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FractionStatus {
+    /// Shares are outstanding, the token can be redeemed or bought out.
+    Active,
+    /// A holder's shares are being burned to take the token out.
+    Redeeming,
+    /// A buyout payment is in flight.
+    BuyingOut,
+    /// The token was sold at the reserve price, holders claim their part of it.
+    BoughtOut,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct Fraction {
+    pub token_id: u128,
+    pub issuer: ICRCAccount,
+    /// Canister account holding the token and, after a buyout, its proceeds.
+    pub vault: ICRCAccount,
+    /// ICRC-1 ledger the shares live on, controlled by the collection.
+    pub share_ledger: Principal,
+    /// Number of shares issued.
+    pub shares: u128,
+    /// Shares not burned yet.
+    pub outstanding: u128,
+    /// `None` disables buyouts.
+    pub reserve_price: Option<u128>,
+    pub ledger: Option<Principal>,
+    pub created_at: u64,
+    pub status: FractionStatus,
+}
+
+impl Storable for Fraction {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+/// Share ledger spawned for a token, kept when its fraction ends.
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct ShareLedger {
+    pub canister_id: Principal,
+    pub created_at: u64,
+}
+
+impl Storable for ShareLedger {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+/// `[VAULT_DOMAIN, 0.., token_id]`.
+pub fn vault_subaccount(token_id: u128) -> Subaccount {
+    let mut bytes = [0u8; 32];
+    bytes[0] = VAULT_DOMAIN;
+    bytes[16..].copy_from_slice(&token_id.to_be_bytes());
+    Subaccount(bytes)
+}
+
+pub fn fraction(token_id: &u128) -> Option<Fraction> {
+    FRACTIONS.with(|f| f.borrow().get(token_id))
+}
+
+pub fn fractions(prev: Option<u128>, take: Option<u128>) -> Vec<Fraction> {
+    let take = take.unwrap_or(100) as usize;
+
+    FRACTIONS.with(|f| {
+        let f = f.borrow();
+        match prev {
+            None => f.iter().map(|(_, fraction)| fraction).take(take).collect(),
+            Some(prev) => f
+                .range(prev..)
+                .filter(|(id, _)| *id != prev)
+                .map(|(_, fraction)| fraction)
+                .take(take)
+                .collect(),
+        }
+    })
+}
+
+fn save(fraction: &Fraction) {
+    FRACTIONS.with(|f| f.borrow_mut().insert(fraction.token_id, fraction.clone()));
+}
+
+/// Share ledger spawned for `token_id`, kept when the fraction ends and
+/// reinstalled the next time the token is fractionalized.
+pub fn share_ledger_of(token_id: &u128) -> Option<Principal> {
+    SHARE_LEDGERS.with(|l| l.borrow().get(token_id).map(|l| l.canister_id))
+}
+
+/// Spawns the share ledger of `token_id`, or wipes the one left from an
+/// earlier fraction, with the whole supply credited to `holder`.
+async fn install_share_ledger(
+    token_id: u128,
+    holder: &ICRCAccount,
+    shares: u128,
+) -> Result<Principal, String> {
+    let (canister_id, mode) = match share_ledger_of(&token_id) {
+        Some(canister_id) => (canister_id, CanisterInstallMode::Reinstall),
+        None => {
+            let args = CreateCanisterArgument {
+                settings: Some(CanisterSettings {
+                    controllers: Some(vec![ic_cdk::id()]),
+                    compute_allocation: None,
+                    memory_allocation: None,
+                    freezing_threshold: None,
+                }),
+            };
+            let (record,) = create_canister(args, SHARE_LEDGER_CYCLES)
+                .await
+                .map_err(|(_, msg)| msg)?;
+            let share_ledger = ShareLedger {
+                canister_id: record.canister_id,
+                created_at: ic_cdk::api::time(),
+            };
+            SHARE_LEDGERS.with(|l| l.borrow_mut().insert(token_id, share_ledger));
+            (record.canister_id, CanisterInstallMode::Install)
+        }
+    };
+
+    let (name, symbol) = CONFIG.with(|c| {
+        let c = c.borrow();
+        let config = c.get();
+        (config.name(), config.symbol())
+    });
+    let arg = Encode!(&ShareLedgerArgs {
+        name: format!("{} #{} Shares", name, token_id),
+        symbol: format!("{}-{}", symbol, token_id),
+        token_id,
+        holder: holder.clone(),
+        supply: Nat::from(shares),
+    })
+    .unwrap();
+
+    install_code(InstallCodeArgument {
+        mode,
+        canister_id,
+        wasm_module: SHARE_LEDGER_WASM.to_vec(),
+        arg,
+    })
+    .await
+    .map_err(|(_, msg)| msg)?;
+
+    Ok(canister_id)
+}
+
+/// Burns `amount` shares out of `from` on the share ledger.
+async fn burn_shares(
+    share_ledger: Principal,
+    from: &ICRCAccount,
+    amount: u128,
+) -> Result<(), MarketError> {
+    let arg = BurnFromArgs {
+        from: from.clone(),
+        amount: Nat::from(amount),
+    };
+    let result: Result<(Result<Nat, ICRC1TransferError>,), _> =
+        ic_cdk::call(share_ledger, "burn_from", (arg,)).await;
+
+    match result {
+        Ok((Ok(_),)) => Ok(()),
+        Ok((Err(ICRC1TransferError::InsufficientFunds { balance }),)) => {
+            Err(MarketError::InsufficientShares {
+                balance: balance.0.try_into().unwrap_or(u128::MAX),
+            })
+        }
+        Ok((Err(e),)) => Err(MarketError::ShareLedgerFailed { msg: e.to_string() }),
+        Err((_, msg)) => Err(MarketError::ShareLedgerFailed { msg }),
+    }
+}
+
+/// Credits `amount` shares back to `to`, minted from the collection account.
+async fn mint_shares(share_ledger: Principal, to: ICRCAccount, amount: u128) -> Result<(), String> {
+    transfer(share_ledger, None, to, amount).await
+}
+
+/// The token can leave its vault, checked before any share is burned or any
+/// buyout is paid.
+fn releasable(token_id: u128) -> Result<(), MarketError> {
+    if pause_state().transfers {
+        return Err(MarketError::Paused);
+    }
+    if is_frozen(&token_id) {
+        return Err(MarketError::TokenFrozen {
+            tokens_ids: vec![token_id],
+        });
+    }
+    Ok(())
+}
+
+/// The owner may put the token into its vault. A bought out token is only
+/// fractionalized again once every share of the buyout was claimed, the vault
+/// still holds the unclaimed proceeds.
+fn check_fractionalizable(token_id: u128, owner: &ICRCAccount) -> Result<(), MarketError> {
+    let token = match TOKENS.with(|tokens| tokens.borrow().get(&token_id)) {
+        None => ic_cdk::trap("Invalid Id"),
+        Some(token) => token,
+    };
+    if token.owner != *owner {
+        return Err(MarketError::Unauthorized {
+            tokens_ids: vec![token.id],
+        });
+    }
+    sellable(&token)?;
+    if let Some(fraction) = fraction(&token.id) {
+        if fraction.outstanding > 0 {
+            return Err(MarketError::SharesOutstanding {
+                outstanding: fraction.outstanding,
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Spawns the share ledger with all shares credited to the owner, then moves
+/// the token into its vault. The token stays locked while the ledger is set
+/// up so it cannot change hands meanwhile. Only a fractionalizer spawns a new
+/// ledger on the collection's cycles, anyone else attaches
+/// `SHARE_LEDGER_CYCLES`, the ledger left from an earlier fraction is reused
+/// for free.
+pub async fn fractionalize(arg: FractionalizeArgs) -> Result<(), MarketError> {
+    let owner = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let token_id = arg.token_id;
+
+    if arg.shares == 0 {
+        return Err(MarketError::InvalidShares);
+    }
+    let ledger = match arg.reserve_price {
+        None => None,
+        Some(0) => return Err(MarketError::InvalidPrice),
+        Some(_) => Some(payment_ledger().ok_or(MarketError::NoPaymentLedger)?),
+    };
+    check_fractionalizable(token_id, &owner)?;
+    if share_ledger_of(&token_id).is_none() && !has_role(&owner.owner(), Role::Fractionalizer) {
+        if msg_cycles_available128() < SHARE_LEDGER_CYCLES {
+            return Err(MarketError::CyclesRequired {
+                cycles: SHARE_LEDGER_CYCLES,
+            });
+        }
+        msg_cycles_accept128(SHARE_LEDGER_CYCLES);
+    }
+
+    lock_token(token_id, Lock::Fraction);
+    let installed = install_share_ledger(token_id, &owner, arg.shares).await;
+    unlock_token(&token_id);
+
+    let share_ledger = installed.map_err(|msg| MarketError::ShareLedgerFailed { msg })?;
+
+    // the token may have been frozen or transfers paused while the ledger was
+    // set up, its shares are wiped again then
+    let vault = ICRCAccount::new(ic_cdk::id(), Some(vault_subaccount(token_id)));
+    let moved = check_fractionalizable(token_id, &owner)
+        .and_then(|_| move_token(token_id, &owner, vault.clone()).map_err(Into::into));
+    if let Err(e) = moved {
+        let _ = uninstall_code(CanisterIdRecord {
+            canister_id: share_ledger,
+        })
+        .await;
+        return Err(e);
+    }
+    lock_token(token_id, Lock::Fraction);
+
+    save(&Fraction {
+        token_id,
+        issuer: owner,
+        vault,
+        share_ledger,
+        shares: arg.shares,
+        outstanding: arg.shares,
+        reserve_price: arg.reserve_price,
+        ledger,
+        created_at: ic_cdk::api::time(),
+        status: FractionStatus::Active,
+    });
+
+    Ok(())
+}
+
+/// Whoever holds every share has them burned on the share ledger and takes
+/// the token out of the vault. Should the token fail to leave, the shares are
+/// minted back.
+pub async fn redeem(arg: FractionArgs) -> Result<(), MarketError> {
+    let holder = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let token_id = arg.token_id;
+    let mut fraction = fraction(&token_id).ok_or(MarketError::NotFractionalized)?;
+
+    if fraction.status != FractionStatus::Active {
+        return Err(MarketError::BoughtOut);
+    }
+    releasable(token_id)?;
+
+    fraction.status = FractionStatus::Redeeming;
+    save(&fraction);
+
+    if let Err(e) = burn_shares(fraction.share_ledger, &holder, fraction.shares).await {
+        fraction.status = FractionStatus::Active;
+        save(&fraction);
+        return Err(e);
+    }
+
+    unlock_token(&token_id);
+    if let Err(e) = move_token(token_id, &fraction.vault, holder.clone()) {
+        // e.g. frozen while the burn was in flight
+        lock_token(token_id, Lock::Fraction);
+        let minted = mint_shares(fraction.share_ledger, holder, fraction.shares).await;
+        fraction.status = FractionStatus::Active;
+        save(&fraction);
+        return Err(match minted {
+            Ok(()) => e.into(),
+            Err(msg) => MarketError::ShareLedgerFailed { msg },
+        });
+    }
+
+    FRACTIONS.with(|f| f.borrow_mut().remove(&token_id));
+
+    Ok(())
+}
+
+/// Anyone buys the token at the reserve price. The payment stays in the vault
+/// until the share holders claim it.
+pub async fn buyout(arg: FractionArgs) -> Result<(), MarketError> {
+    let buyer = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let mut fraction = fraction(&arg.token_id).ok_or(MarketError::NotFractionalized)?;
+
+    if fraction.status != FractionStatus::Active {
+        return Err(MarketError::BoughtOut);
+    }
+    let (price, ledger) = match (fraction.reserve_price, fraction.ledger) {
+        (Some(price), Some(ledger)) => (price, ledger),
+        _ => return Err(MarketError::NoBuyout),
+    };
+    releasable(arg.token_id)?;
+
+    fraction.status = FractionStatus::BuyingOut;
+    save(&fraction);
+
+    let paid = collect_into(
+        ledger,
+        buyer.clone(),
+        Some(vault_subaccount(arg.token_id)),
+        price,
+    )
+    .await;
+
+    if let Err(msg) = paid {
+        fraction.status = FractionStatus::Active;
+        save(&fraction);
+        return Err(MarketError::PaymentFailed { msg });
+    }
+
+    unlock_token(&arg.token_id);
+    if let Err(e) = move_token(arg.token_id, &fraction.vault, buyer.clone()) {
+        // e.g. frozen while the payment was in flight, the buyer is refunded
+        lock_token(arg.token_id, Lock::Fraction);
+        fraction.status = FractionStatus::Active;
+        save(&fraction);
+        // queued like any failed payout, the retry looks the fee up again
+        let fee = ledger_fee(ledger).await.unwrap_or_default();
+        pay_out_from(
+            ledger,
+            fee,
+            Some(vault_subaccount(arg.token_id)),
+            buyer,
+            price,
+        )
+        .await;
+        return Err(e.into());
+    }
+
+    fraction.status = FractionStatus::BoughtOut;
+    save(&fraction);
+
+    Ok(())
+}
+
+/// After a buyout, burns the caller's shares for their part of the price.
+/// The ledger fee comes out of the payout, shares worth no more than the fee
+/// are left in place. A payout the ledger rejects is queued for retry.
+pub async fn claim(arg: FractionArgs) -> Result<u128, MarketError> {
+    let holder = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let token_id = arg.token_id;
+    let fraction = fraction(&token_id).ok_or(MarketError::NotFractionalized)?;
+
+    if fraction.status != FractionStatus::BoughtOut {
+        return Err(MarketError::NotBoughtOut);
+    }
+    let (price, ledger) = match (fraction.reserve_price, fraction.ledger) {
+        (Some(price), Some(ledger)) => (price, ledger),
+        _ => return Err(MarketError::NoBuyout),
+    };
+
+    let balance = balance_of(fraction.share_ledger, &holder)
+        .await
+        .map_err(|msg| MarketError::ShareLedgerFailed { msg })?;
+    if balance == 0 {
+        return Err(MarketError::InsufficientShares { balance });
+    }
+    let payout =
+        price / fraction.shares * balance + price % fraction.shares * balance / fraction.shares;
+
+    let fee = ledger_fee(ledger)
+        .await
+        .map_err(|msg| MarketError::PaymentFailed { msg })?;
+    if payout <= fee {
+        return Err(MarketError::PayoutBelowFee { payout, fee });
+    }
+
+    // burned before the payout so the same shares cannot be claimed twice
+    burn_shares(fraction.share_ledger, &holder, balance).await?;
+    update_outstanding(token_id, |outstanding| outstanding - balance);
+
+    pay_out_from(
+        ledger,
+        fee,
+        Some(vault_subaccount(token_id)),
+        holder,
+        payout,
+    )
+    .await;
+
+    Ok(payout)
+}
+
+fn update_outstanding(token_id: u128, f: impl FnOnce(u128) -> u128) {
+    if let Some(mut fraction) = fraction(&token_id) {
+        fraction.outstanding = f(fraction.outstanding);
+        save(&fraction);
+    }
+}
@@ -0,0 +1,131 @@
+use crate::{
+    state::{legacy_log_len, HISTORY_START, TOKEN_EVENTS, TOKEN_HISTORY, TRANSACTION_ID},
+    subscriptions::enqueue,
+};
+use b3_utils::{
+    ledger::ICRCAccount,
+    memory::types::{Bound, Storable},
+};
+use candid::{CandidType, Decode, Encode};
+use serde_derive::{Deserialize, Serialize};
+use std::ops;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub enum TokenEventKind {
+    Mint {
+        to: ICRCAccount,
+    },
+    Transfer {
+        from: ICRCAccount,
+        to: ICRCAccount,
+        memo: Option<Vec<u8>>,
+    },
+    Approval {
+        owner: ICRCAccount,
+        spender: ICRCAccount,
+        expires_at: Option<u64>,
+    },
+    Burn {
+        from: ICRCAccount,
+    },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct TokenEvent {
+    /// Position in the event log, the `prev` cursor of the history query.
+    pub position: u64,
+    pub token_id: u128,
+    pub tx_index: u128,
+    pub at: u64,
+    pub kind: TokenEventKind,
+}
+
+impl Storable for TokenEvent {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+/// Index entries sort newest first within a token.
+fn history_key(token_id: u128, position: u64) -> (u128, u64) {
+    (token_id, u64::MAX - position)
+}
+
+/// First position of events recorded as they happen, the ones below are
+/// backfilled from the legacy transfer log, see `migration::MIGRATIONS`.
+pub fn history_start() -> u64 {
+    HISTORY_START.with(|s| *s.borrow().get())
+}
+
+/// Called on upgrade, before anything is recorded, keeps one position per
+/// legacy log entry free for the backfill.
+pub fn reserve_legacy_positions() {
+    if TOKEN_EVENTS.with(|e| e.borrow().is_empty()) && history_start() == 0 {
+        let start = legacy_log_len();
+        HISTORY_START.with(|s| s.borrow_mut().set(start).unwrap());
+    }
+}
+
+/// Stores an event and indexes its position for the token.
+pub fn insert(event: &TokenEvent) {
+    TOKEN_EVENTS.with(|e| e.borrow_mut().insert(event.position, event.clone()));
+    TOKEN_HISTORY.with(|h| {
+        h.borrow_mut()
+            .insert(history_key(event.token_id, event.position), ())
+    });
+}
+
+/// Appends an event under the transaction being committed and queues it for
+/// subscribers.
+pub fn record(token_id: u128, kind: TokenEventKind) {
+    let tx_index = TRANSACTION_ID.with(|id| *id.borrow().get());
+
+    let next = TOKEN_EVENTS.with(|e| {
+        e.borrow()
+            .last_key_value()
+            .map(|(position, _)| position + 1)
+    });
+    let event = TokenEvent {
+        position: next.unwrap_or_default().max(history_start()),
+        token_id,
+        tx_index,
+        at: ic_cdk::api::time(),
+        kind,
+    };
+
+    insert(&event);
+    enqueue(&event);
+}
+
+/// Events of one token newest first, starting below position `prev`.
+/// Tokens of the first release only have history from their first transfer
+/// on, it did not log mints nor approvals.
+pub fn token_history(token_id: u128, prev: Option<u64>, take: Option<u128>) -> Vec<TokenEvent> {
+    let take = take.unwrap_or(100) as usize;
+    let start = match prev {
+        None => ops::Bound::Included((token_id, 0)),
+        Some(prev) => ops::Bound::Excluded(history_key(token_id, prev)),
+    };
+
+    let positions: Vec<u64> = TOKEN_HISTORY.with(|h| {
+        h.borrow()
+            .range((start, ops::Bound::Included((token_id, u64::MAX))))
+            .map(|((_, key), _)| u64::MAX - key)
+            .take(take)
+            .collect()
+    });
+
+    TOKEN_EVENTS.with(|e| {
+        let e = e.borrow();
+        positions
+            .iter()
+            .filter_map(|position| e.get(position))
+            .collect()
+    })
+}
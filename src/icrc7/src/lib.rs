@@ -1,31 +1,158 @@
+pub mod airdrops;
+pub mod auction;
+pub mod audit;
+pub mod cycles;
 pub mod errors;
+pub mod fractions;
+pub mod history;
+pub mod locks;
+pub mod market;
+pub mod metrics;
+pub mod migration;
+pub mod offers;
+pub mod pause;
+pub mod payments;
+pub mod receiver;
+pub mod rentals;
+pub mod reveal;
+pub mod roles;
+pub mod sale;
+pub mod snapshots;
+pub mod staking;
 pub mod state;
+pub mod subscriptions;
+pub mod swaps;
+pub mod token_accounts;
 pub mod types;
+pub mod vouchers;
 
-use crate::types::{CollectionMetadata, Standard};
+use crate::types::{
+    CollectionArgs, CollectionMetadata, RoleArgs, Standard, UpdateCollectionConfigArgs,
+};
 use crate::{
-    errors::{ApprovalError, TransferError},
+    audit::AuditEvent,
+    errors::{
+        AirdropError, ApprovalError, BurnError, ConfigError, CyclesError, MarketError, MintError,
+        RentalError, RevealError, StakingError, SubscriptionError, SwapError, TransferAndCallError,
+        TransferError, VoucherError, WithdrawError,
+    },
     state::Token,
-    state::{CollectionConfig, CONFIG},
-    types::{ApprovalArgs, MintArgs, TransferArgs},
+    state::{features, Transferability, CONFIG, FEATURES},
+    types::{
+        AcceptOfferArgs, AddMintPhaseArgs, ApprovalArgs, BidArgs, BurnArgs, BuyArgs,
+        CommitRevealArgs, CreateAuctionArgs, DepositSwapArgs, FractionArgs, FractionalizeArgs,
+        FundRewardsArgs, ListArgs, MakeOfferArgs, MintArgs, PauseArgs, ProposeSwapArgs,
+        RedeemVoucherArgs, RentArgs, SelfMintArgs, SetCyclesConfigArgs, SetRentalTermsArgs,
+        SetUserArgs, StakeArgs, SubscribeArgs, TransferAndCallArgs, TransferArgs, UnstakeArgs,
+        WithdrawArgs,
+    },
 };
+use airdrops::{Airdrop, AirdropFailure, AirdropMode, AirdropRecipient};
+use auction::Auction;
 use b3_utils::http::{HttpRequest, HttpResponse, HttpResponseBuilder};
 use b3_utils::ledger::{ICRC1MetadataValue, ICRCAccount};
 use b3_utils::memory::with_stable_mem;
-use ic_cdk::{init, query, update};
+use candid::Principal;
+use cycles::CyclesState;
+use fractions::Fraction;
+use history::{TokenEvent, TokenEventKind};
+use ic_cdk::{init, post_upgrade, query, update};
+use locks::{is_locked, Lock};
+use market::{token_moved, Listing};
+use metrics::{encode_metrics, record_call};
+use migration::MigrationStatus;
+use offers::Offer;
+use pause::{is_frozen, pause_state, PauseState};
+use payments::Payout;
+use rentals::{Rental, RentalTerms};
+use reveal::{RevealState, TokenMetadata};
+use roles::{guard_role, has_role, Role};
+use sale::{DropStatus, DropWindow, MintPhase, SaleLog};
+use snapshots::{ExportStatus, SnapshotExport, SnapshotPage};
+use staking::{Stake, StakeEvent, StakingConfig};
 use state::{
-    get_total_supply, id_validity_check, increment_total_supply, increment_tx_id,
-    tx_deduplication_check, Approval, TransferLog, TOKENS, TOTAL_SUPPLY, TRANSFER_LOG,
+    decrement_total_supply, get_total_supply, get_transfer_log, id_validity_check, increment_tx_id,
+    insert_minted, log_transaction, tx_deduplication_check, Approval, LogOp, AUDIT_LOG, TOKENS,
+    TOTAL_SUPPLY,
 };
 use std::collections::HashMap;
+use subscriptions::{DeadLetter, Subscription};
+use swaps::{Swap, SwapStatus};
+use vouchers::MinterKey;
 
 #[init]
-pub fn init(arg: CollectionConfig) {
+pub fn init(arg: CollectionArgs) {
+    let arg = match arg {
+        CollectionArgs::Init(config) => config,
+        CollectionArgs::Upgrade(_) => ic_cdk::trap("Expected Init Args"),
+    };
+    let minting_authority = arg.minting_authority;
+
     CONFIG.with(|c| {
         let mut c = c.borrow_mut();
 
         c.set(arg).unwrap();
     });
+
+    roles::seed_roles(minting_authority);
+    migration::mark_current();
+    cycles::schedule();
+}
+
+#[post_upgrade]
+pub fn post_upgrade(arg: Option<CollectionArgs>) {
+    roles::migrate_minting_authority();
+    history::reserve_legacy_positions();
+
+    let arg = match arg {
+        None => None,
+        Some(CollectionArgs::Upgrade(arg)) => arg,
+        Some(CollectionArgs::Init(_)) => ic_cdk::trap("Expected Upgrade Args"),
+    };
+
+    if let Some(arg) = arg {
+        if let Some(config) = arg.config {
+            CONFIG.with(|c| {
+                let mut c = c.borrow_mut();
+                let mut current = c.get().clone();
+
+                if let Err(e) = current.update(config.clone()) {
+                    ic_cdk::trap(&format!("Invalid Config: {:?}", e))
+                }
+                c.set(current).unwrap();
+            });
+
+            log_transaction(
+                LogOp::ConfigUpdate {
+                    by: ic_cdk::caller(),
+                    changes: Box::new(config),
+                },
+                None,
+            );
+            increment_tx_id();
+        }
+
+        if let Some(toggles) = arg.features {
+            FEATURES.with(|f| {
+                let mut f = f.borrow_mut();
+                let mut features = f.get().clone();
+
+                features.update(toggles.clone());
+                f.set(features).unwrap();
+            });
+
+            audit::log_event(AuditEvent::FeaturesUpdated(toggles));
+        }
+    }
+
+    migration::schedule();
+    auction::schedule_all();
+    swaps::schedule_all();
+    sale::schedule_drop();
+    airdrops::schedule_all();
+    snapshots::schedule_all();
+    subscriptions::schedule_all();
+    cycles::schedule();
 }
 
 /// ======== Query ========
@@ -79,7 +206,7 @@ pub fn icrc7_collection_metadata() -> CollectionMetadata {
 pub fn icrc7_metadata(id: u128) -> Vec<(String, ICRC1MetadataValue)> {
     match TOKENS.with(|tokens| tokens.borrow().get(&id)) {
         None => ic_cdk::trap("Invalid Token Id"),
-        Some(token) => token.token_metadata(),
+        Some(token) => reveal::revealed(token).token_metadata(),
     }
 }
 
@@ -94,6 +221,15 @@ pub fn icrc7_owner_of(id: u128) -> ICRCAccount {
     })
 }
 
+#[query]
+pub fn icrc7_token_history(
+    token_id: u128,
+    prev: Option<u64>,
+    take: Option<u128>,
+) -> Vec<TokenEvent> {
+    history::token_history(token_id, prev, take)
+}
+
 #[query]
 pub fn icrc7_balance_of(account: ICRCAccount) -> u128 {
     let mut balance = 0;
@@ -124,6 +260,11 @@ pub fn icrc7_tokens_of(account: ICRCAccount) -> Vec<u128> {
     ids
 }
 
+#[query]
+pub fn icrc7_migration_status() -> MigrationStatus {
+    migration::status()
+}
+
 #[query]
 pub fn icrc7_supported_standards() -> Vec<Standard> {
     vec![Standard {
@@ -136,6 +277,61 @@ pub fn icrc7_supported_standards() -> Vec<Standard> {
 
 #[update]
 pub fn icrc7_transfer(arg: TransferArgs) -> Result<u128, TransferError> {
+    let result = transfer(arg);
+
+    record_call("icrc7_transfer", result.is_err());
+
+    result
+}
+
+/// Transfers the tokens atomically to a canister, then calls its
+/// `icrc7_on_received` hook. Tokens the recipient rejects are sent back.
+#[update]
+pub async fn icrc7_transfer_and_call(
+    arg: TransferAndCallArgs,
+) -> Result<u128, TransferAndCallError> {
+    let result = transfer_and_call(arg).await;
+
+    record_call("icrc7_transfer_and_call", result.is_err());
+
+    result
+}
+
+async fn transfer_and_call(arg: TransferAndCallArgs) -> Result<u128, TransferAndCallError> {
+    if !receiver::is_canister(&arg.to.owner()) {
+        return Err(TransferAndCallError::NotCanister);
+    }
+
+    // the hook is told `from` owned the tokens, a spender moves them on its behalf
+    let owners = receiver::owners(&arg.token_ids);
+    let unauthorized: Vec<u128> = owners
+        .iter()
+        .filter(|(_, owner)| *owner != arg.from)
+        .map(|(id, _)| *id)
+        .collect();
+    if !unauthorized.is_empty() {
+        return Err(TransferError::Unauthorized {
+            tokens_ids: unauthorized,
+        }
+        .into());
+    }
+
+    let tx_index = transfer(TransferArgs {
+        spender_subaccount: arg.spender_subaccount,
+        from: arg.from.clone(),
+        to: arg.to.clone(),
+        token_ids: arg.token_ids,
+        memo: arg.memo,
+        created_at_time: arg.created_at_time,
+        is_atomic: Some(true),
+    })?;
+
+    receiver::notify(arg.from, arg.to, owners, arg.data).await?;
+
+    Ok(tx_index)
+}
+
+fn transfer(arg: TransferArgs) -> Result<u128, TransferError> {
     if arg.token_ids.len() == 0 {
         ic_cdk::trap("No Token Provided")
     }
@@ -147,10 +343,14 @@ pub fn icrc7_transfer(arg: TransferArgs) -> Result<u128, TransferError> {
     let current_time = ic_cdk::api::time();
     let mut tx_deduplication: HashMap<u128, TransferError> = HashMap::new();
 
-    CONFIG.with(|c| {
+    let result = CONFIG.with(|c| {
         let c = c.borrow();
         let config = c.get();
 
+        if pause_state().transfers {
+            return Err(TransferError::Paused);
+        }
+
         if let Some(arg_time) = arg.created_at_time {
             let permitted_past_time = current_time - config.tx_window - config.permitted_drift;
             let permitted_future_time = current_time + config.permitted_drift;
@@ -184,6 +384,9 @@ pub fn icrc7_transfer(arg: TransferArgs) -> Result<u128, TransferError> {
         }
 
         let mut unauthorized: Vec<u128> = vec![];
+        let mut frozen: Vec<u128> = vec![];
+        let mut non_transferable: Vec<u128> = vec![];
+        let mut locked: Vec<u128> = vec![];
         arg.token_ids.iter().for_each(|id| {
             let token = match TOKENS.with(|tokens| tokens.borrow().get(id)) {
                 None => ic_cdk::trap("Invalid Id"),
@@ -195,6 +398,15 @@ pub fn icrc7_transfer(arg: TransferArgs) -> Result<u128, TransferError> {
             if token.owner != caller && !approval_check {
                 unauthorized.push(id.clone())
             }
+            if is_frozen(id) {
+                frozen.push(*id)
+            }
+            if !token.is_transferable() {
+                non_transferable.push(*id)
+            }
+            if is_locked(id) {
+                locked.push(*id)
+            }
         });
 
         match arg.is_atomic {
@@ -205,6 +417,7 @@ pub fn icrc7_transfer(arg: TransferArgs) -> Result<u128, TransferError> {
                         return Err(e.clone());
                     }
                     let mut token = TOKENS.with(|tokens| tokens.borrow().get(id).unwrap());
+                    let owner = token.owner.clone();
 
                     match token.transfer(
                         current_time + config.permitted_drift,
@@ -213,16 +426,25 @@ pub fn icrc7_transfer(arg: TransferArgs) -> Result<u128, TransferError> {
                     ) {
                         Err(_) => continue,
                         Ok(_) => {
-                            let log = TransferLog {
-                                id: id.clone(),
-                                at: current_time,
-                                memo: arg.memo.clone(),
-                                from: caller.clone(),
-                                to: arg.to.clone(),
-                            };
                             TOKENS.with(|tokens| tokens.borrow_mut().insert(id.clone(), token));
+                            token_moved(id);
+                            history::record(
+                                *id,
+                                TokenEventKind::Transfer {
+                                    from: owner,
+                                    to: arg.to.clone(),
+                                    memo: arg.memo.clone(),
+                                },
+                            );
 
-                            TRANSFER_LOG.with(|log_ref| log_ref.borrow_mut().push(&log).unwrap());
+                            log_transaction(
+                                LogOp::Transfer {
+                                    id: *id,
+                                    from: caller.clone(),
+                                    to: arg.to.clone(),
+                                },
+                                arg.memo.clone(),
+                            );
                         }
                     }
                 }
@@ -231,6 +453,17 @@ pub fn icrc7_transfer(arg: TransferArgs) -> Result<u128, TransferError> {
                         tokens_ids: unauthorized,
                     });
                 }
+                if !frozen.is_empty() {
+                    return Err(TransferError::TokenFrozen { tokens_ids: frozen });
+                }
+                if !non_transferable.is_empty() {
+                    return Err(TransferError::NonTransferable {
+                        tokens_ids: non_transferable,
+                    });
+                }
+                if !locked.is_empty() {
+                    return Err(TransferError::TokenLocked { tokens_ids: locked });
+                }
 
                 Ok(increment_tx_id())
             }
@@ -244,29 +477,51 @@ pub fn icrc7_transfer(arg: TransferArgs) -> Result<u128, TransferError> {
                         tokens_ids: unauthorized,
                     });
                 }
+                if !frozen.is_empty() {
+                    return Err(TransferError::TokenFrozen { tokens_ids: frozen });
+                }
+                if !non_transferable.is_empty() {
+                    return Err(TransferError::NonTransferable {
+                        tokens_ids: non_transferable,
+                    });
+                }
+                if !locked.is_empty() {
+                    return Err(TransferError::TokenLocked { tokens_ids: locked });
+                }
                 for id in arg.token_ids.iter() {
                     let mut token = TOKENS.with(|tokens| tokens.borrow().get(id).unwrap());
+                    let owner = token.owner.clone();
                     token.transfer(
                         current_time + config.permitted_drift,
                         &caller,
                         arg.to.clone(),
                     )?;
-                    let log = TransferLog {
-                        id: id.clone(),
-                        at: current_time,
-                        memo: arg.memo.clone(),
-                        from: caller.clone(),
-                        to: arg.to.clone(),
-                    };
-
                     TOKENS.with(|tokens| tokens.borrow_mut().insert(id.clone(), token));
-                    TRANSFER_LOG.with(|log_ref| log_ref.borrow_mut().push(&log).unwrap());
+                    token_moved(id);
+                    history::record(
+                        *id,
+                        TokenEventKind::Transfer {
+                            from: owner,
+                            to: arg.to.clone(),
+                            memo: arg.memo.clone(),
+                        },
+                    );
+                    log_transaction(
+                        LogOp::Transfer {
+                            id: *id,
+                            from: caller.clone(),
+                            to: arg.to.clone(),
+                        },
+                        arg.memo.clone(),
+                    );
                 }
 
                 Ok(increment_tx_id())
             }
         }
-    })
+    });
+
+    result
 }
 
 #[update]
@@ -274,7 +529,12 @@ pub fn icrc7_approve(arg: ApprovalArgs) -> Result<u128, ApprovalError> {
     let caller = ICRCAccount::from(ic_cdk::caller());
 
     let token_ids = match arg.token_ids {
-        None => icrc7_tokens_of(caller.clone()),
+        // approving every token of the caller skips the ones that cannot move
+        None => icrc7_tokens_of(caller.clone())
+            .into_iter()
+            .filter(|id| TOKENS.with(|tokens| tokens.borrow().get(id).unwrap().is_transferable()))
+            .filter(|id| !is_locked(id))
+            .collect(),
         Some(ids) => {
             id_validity_check(&ids);
             ids
@@ -290,19 +550,55 @@ pub fn icrc7_approve(arg: ApprovalArgs) -> Result<u128, ApprovalError> {
         expires_at: arg.expires_at,
     };
 
-    TOKENS.with(|tokens| {
+    let result = TOKENS.with(|tokens| {
+        if pause_state().approvals {
+            return Err(ApprovalError::Paused);
+        }
+
+        let non_transferable: Vec<u128> = token_ids
+            .iter()
+            .filter(|id| !tokens.borrow().get(id).unwrap().is_transferable())
+            .cloned()
+            .collect();
+        if !non_transferable.is_empty() {
+            return Err(ApprovalError::NonTransferable {
+                tokens_ids: non_transferable,
+            });
+        }
+
+        let locked: Vec<u128> = token_ids
+            .iter()
+            .filter(|id| is_locked(id))
+            .cloned()
+            .collect();
+        if !locked.is_empty() {
+            return Err(ApprovalError::TokenLocked { tokens_ids: locked });
+        }
+
         for id in token_ids.iter() {
             let mut token = tokens.borrow().get(id).unwrap();
             token.approve(&caller, approval.clone())?;
             tokens.borrow_mut().insert(id.clone(), token);
+            history::record(
+                *id,
+                TokenEventKind::Approval {
+                    owner: caller.clone(),
+                    spender: approval.account.clone(),
+                    expires_at: approval.expires_at,
+                },
+            );
         }
 
         Ok(increment_tx_id())
-    })
+    });
+
+    record_call("icrc7_approve", result.is_err());
+
+    result
 }
 
 #[update]
-pub fn icrc7_mint(arg: MintArgs) -> u128 {
+pub fn icrc7_mint(arg: MintArgs) -> Result<u128, MintError> {
     let token = Token {
         id: arg.id,
         name: arg.name,
@@ -310,77 +606,1094 @@ pub fn icrc7_mint(arg: MintArgs) -> u128 {
         image: arg.image,
         owner: arg.to,
         approvals: Vec::new(),
+        transferability: arg.transferability,
+        user: None,
     };
 
-    CONFIG.with(|c| {
+    let result = CONFIG.with(|c| {
         let c = c.borrow();
         let config = c.get();
 
-        if ic_cdk::caller() != config.minting_authority {
-            ic_cdk::trap("Unauthorized Caller")
+        if !has_role(&ic_cdk::caller(), Role::Minter) {
+            return Err(MintError::Unauthorized);
+        }
+
+        if pause_state().mints {
+            return Err(MintError::Paused);
         }
 
         if let Some(cap) = config.supply_cap {
-            if cap < get_total_supply() {
-                ic_cdk::trap("Supply Cap Reached")
+            if get_total_supply() >= cap {
+                return Err(MintError::SoldOut);
             }
         }
 
         if TOKENS.with(|tokens| tokens.borrow().contains_key(&token.id)) {
-            ic_cdk::trap("Id Exist")
+            return Err(MintError::TokenExists);
         }
 
-        increment_total_supply();
+        Ok(insert_minted(token))
+    });
 
-        TOKENS.with(|tokens| tokens.borrow_mut().insert(token.id, token));
+    record_call("icrc7_mint", result.is_err());
 
-        increment_tx_id()
-    })
+    result
 }
 
-#[query]
-fn http_request(req: HttpRequest) -> HttpResponse {
-    match req.path() {
-        "/token" => {
-            let token_id = req.raw_query_param("id").unwrap();
+#[update]
+pub fn icrc7_burn(arg: BurnArgs) -> Result<u128, BurnError> {
+    if arg.token_ids.is_empty() {
+        ic_cdk::trap("No Token Provided")
+    }
+    id_validity_check(&arg.token_ids);
 
-            let token = TOKENS.with(|tokens| {
-                let tokens = tokens.borrow();
-                match tokens.get(&token_id.parse::<u128>().unwrap_or_default()) {
-                    None => ic_cdk::trap("Invalid Token Id"),
-                    Some(token) => token,
-                }
-            });
+    let caller = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
 
-            HttpResponseBuilder::ok()
-                .header("Content-Type", "application/json; charset=utf-8")
-                .with_body_and_content_length(serde_json::to_string(&token).unwrap_or_default())
-                .build()
+    let result = TOKENS.with(|tokens| {
+        if pause_state().burns {
+            return Err(BurnError::Paused);
         }
-        "/partition_details" => {
-            let list = with_stable_mem(|pm| pm.partition_details());
 
-            HttpResponseBuilder::ok()
-                .header("Content-Type", "application/json; charset=utf-8")
-                .with_body_and_content_length(serde_json::to_string(&list).unwrap_or_default())
-                .build()
+        let mut unauthorized: Vec<u128> = vec![];
+        let mut frozen: Vec<u128> = vec![];
+        let mut locked: Vec<u128> = vec![];
+        for id in arg.token_ids.iter() {
+            let token = tokens.borrow().get(id).unwrap();
+            let authorized = match token.transferability() {
+                Transferability::BurnOnlyByIssuer => has_role(&caller.owner(), Role::Minter),
+                _ => token.owner == caller,
+            };
+            if !authorized {
+                unauthorized.push(*id)
+            }
+            if is_frozen(id) {
+                frozen.push(*id)
+            }
+            if is_locked(id) {
+                locked.push(*id)
+            }
         }
-        "/transfer_log" => {
-            let transfer_id = req.raw_query_param("id").unwrap();
-
-            let tx_logs = TRANSFER_LOG.with(|logs| {
-                let logs = logs.borrow();
-                match logs.get(transfer_id.parse::<u64>().unwrap_or_default()) {
-                    None => ic_cdk::trap("Invalid Transfer Id"),
-                    Some(log) => log,
-                }
+        if !unauthorized.is_empty() {
+            return Err(BurnError::Unauthorized {
+                tokens_ids: unauthorized,
             });
+        }
+        if !frozen.is_empty() {
+            return Err(BurnError::TokenFrozen { tokens_ids: frozen });
+        }
+        if !locked.is_empty() {
+            return Err(BurnError::TokenLocked { tokens_ids: locked });
+        }
 
-            HttpResponseBuilder::ok()
-                .header("Content-Type", "application/json; charset=utf-8")
-                .with_body_and_content_length(serde_json::to_string(&tx_logs).unwrap_or_default())
-                .build()
+        for id in arg.token_ids.iter() {
+            let owner = tokens.borrow_mut().remove(id).unwrap().owner;
+            token_moved(id);
+            token_accounts::record_burn(*id, owner.clone());
+            history::record(*id, TokenEventKind::Burn { from: owner });
+            log_transaction(
+                LogOp::Burn {
+                    id: *id,
+                    from: caller.clone(),
+                },
+                None,
+            );
+            decrement_total_supply();
+        }
+
+        Ok(increment_tx_id())
+    });
+
+    record_call("icrc7_burn", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_update_collection_config(arg: UpdateCollectionConfigArgs) -> Result<(), ConfigError> {
+    // name, description and image are metadata, everything else a setting
+    if arg.changes_metadata() {
+        guard_role(Role::MetadataUpdater);
+    }
+    if arg.changes_settings() || !arg.changes_metadata() {
+        guard_role(Role::Admin);
+    }
+
+    let result = CONFIG.with(|c| {
+        let mut c = c.borrow_mut();
+        let mut config = c.get().clone();
+
+        config.update(arg.clone())?;
+        c.set(config).unwrap();
+
+        log_transaction(
+            LogOp::ConfigUpdate {
+                by: ic_cdk::caller(),
+                changes: Box::new(arg),
+            },
+            None,
+        );
+        increment_tx_id();
+
+        Ok(())
+    });
+
+    record_call("icrc7_update_collection_config", result.is_err());
+
+    result
+}
+
+/// ======== Pause ========
+
+#[query]
+pub fn icrc7_pause_state() -> PauseState {
+    pause::pause_state()
+}
+
+#[query]
+pub fn icrc7_frozen_tokens() -> Vec<u128> {
+    pause::frozen_tokens()
+}
+
+#[update]
+pub fn icrc7_set_pause(arg: PauseArgs) -> PauseState {
+    guard_role(Role::Pauser);
+
+    let state = pause::set_pause(arg);
+
+    record_call("icrc7_set_pause", false);
+
+    state
+}
+
+#[update]
+pub fn icrc7_freeze_tokens(token_ids: Vec<u128>) {
+    guard_role(Role::Pauser);
+    id_validity_check(&token_ids);
+
+    pause::freeze_tokens(token_ids);
+
+    record_call("icrc7_freeze_tokens", false);
+}
+
+#[update]
+pub fn icrc7_unfreeze_tokens(token_ids: Vec<u128>) {
+    guard_role(Role::Pauser);
+
+    pause::unfreeze_tokens(token_ids);
+
+    record_call("icrc7_unfreeze_tokens", false);
+}
+
+/// ======== Market ========
+
+#[query]
+pub fn icrc7_listing(token_id: u128) -> Option<Listing> {
+    market::listing(&token_id)
+}
+
+#[query]
+pub fn icrc7_listings(prev: Option<u128>, take: Option<u128>) -> Vec<Listing> {
+    market::listings(prev, take)
+}
+
+#[query]
+pub fn icrc7_locked_tokens() -> Vec<(u128, Lock)> {
+    locks::locked_tokens()
+}
+
+#[query]
+pub fn icrc7_pending_payouts() -> Vec<(u64, Payout)> {
+    payments::pending_payouts()
+}
+
+#[update]
+pub fn icrc7_list(arg: ListArgs) -> Result<(), MarketError> {
+    let result = market::list(arg);
+
+    record_call("icrc7_list", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_delist(token_id: u128) -> Result<(), MarketError> {
+    let result = market::delist(token_id);
+
+    record_call("icrc7_delist", result.is_err());
+
+    result
+}
+
+#[update]
+pub async fn icrc7_buy(arg: BuyArgs) -> Result<u128, MarketError> {
+    let result = market::buy(arg).await;
+
+    record_call("icrc7_buy", result.is_err());
+
+    result
+}
+
+#[update]
+pub async fn icrc7_retry_payouts() -> u64 {
+    guard_role(Role::Admin);
+
+    let pending = payments::retry_payouts().await;
+
+    record_call("icrc7_retry_payouts", false);
+
+    pending
+}
+
+/// ======== Auction ========
+
+#[query]
+pub fn icrc7_auction(token_id: u128) -> Option<Auction> {
+    auction::auction(&token_id)
+}
+
+#[query]
+pub fn icrc7_auctions(prev: Option<u128>, take: Option<u128>) -> Vec<Auction> {
+    auction::auctions(prev, take)
+}
+
+/// Smallest amount a bid has to offer right now.
+#[query]
+pub fn icrc7_auction_price(token_id: u128) -> Option<u128> {
+    auction::auction(&token_id).map(|a| a.price(ic_cdk::api::time()))
+}
+
+#[update]
+pub fn icrc7_create_auction(arg: CreateAuctionArgs) -> Result<(), MarketError> {
+    let result = auction::create(arg);
+
+    record_call("icrc7_create_auction", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_cancel_auction(token_id: u128) -> Result<(), MarketError> {
+    let result = auction::cancel(token_id);
+
+    record_call("icrc7_cancel_auction", result.is_err());
+
+    result
+}
+
+#[update]
+pub async fn icrc7_bid(arg: BidArgs) -> Result<(), MarketError> {
+    let result = auction::bid(arg).await;
+
+    record_call("icrc7_bid", result.is_err());
+
+    result
+}
+
+/// Anyone may settle an ended auction, the timer does it otherwise.
+#[update]
+pub async fn icrc7_settle_auction(token_id: u128) -> Result<(), MarketError> {
+    let result = auction::settle(token_id).await;
+
+    record_call("icrc7_settle_auction", result.is_err());
+
+    result
+}
+
+/// ======== Offers ========
+
+#[query]
+pub fn icrc7_offer(offer_id: u64) -> Option<Offer> {
+    offers::offer(&offer_id)
+}
+
+#[query]
+pub fn icrc7_offers(prev: Option<u64>, take: Option<u128>) -> Vec<Offer> {
+    offers::offers(prev, take)
+}
+
+#[query]
+pub fn icrc7_token_offers(token_id: u128) -> Vec<Offer> {
+    offers::token_offers(&token_id)
+}
+
+#[update]
+pub async fn icrc7_make_offer(arg: MakeOfferArgs) -> Result<u64, MarketError> {
+    let result = offers::make(arg).await;
+
+    record_call("icrc7_make_offer", result.is_err());
+
+    result
+}
+
+#[update]
+pub async fn icrc7_cancel_offer(offer_id: u64) -> Result<(), MarketError> {
+    let result = offers::cancel(offer_id).await;
+
+    record_call("icrc7_cancel_offer", result.is_err());
+
+    result
+}
+
+#[update]
+pub async fn icrc7_accept_offer(arg: AcceptOfferArgs) -> Result<u128, MarketError> {
+    let result = offers::accept(arg).await;
+
+    record_call("icrc7_accept_offer", result.is_err());
+
+    result
+}
+
+/// ======== Swaps ========
+
+#[query]
+pub fn icrc7_swap(swap_id: u64) -> Option<Swap> {
+    swaps::swap(&swap_id)
+}
+
+#[query]
+pub fn icrc7_swaps(prev: Option<u64>, take: Option<u128>) -> Vec<Swap> {
+    swaps::swaps(prev, take)
+}
+
+#[update]
+pub fn icrc7_propose_swap(arg: ProposeSwapArgs) -> Result<u64, SwapError> {
+    let result = swaps::propose(arg);
+
+    record_call("icrc7_propose_swap", result.is_err());
+
+    result
+}
+
+#[update]
+pub async fn icrc7_deposit_swap(arg: DepositSwapArgs) -> Result<SwapStatus, SwapError> {
+    let result = swaps::deposit(arg).await;
+
+    record_call("icrc7_deposit_swap", result.is_err());
+
+    result
+}
+
+#[update]
+pub async fn icrc7_cancel_swap(swap_id: u64) -> Result<(), SwapError> {
+    let result = swaps::cancel(swap_id).await;
+
+    record_call("icrc7_cancel_swap", result.is_err());
+
+    result
+}
+
+/// ======== Token Accounts ========
+
+#[query]
+pub fn icrc7_token_account(token_id: u128) -> Option<ICRCAccount> {
+    token_accounts::token_account(&token_id)
+}
+
+#[update]
+pub async fn icrc7_token_withdraw(arg: WithdrawArgs) -> Result<(), WithdrawError> {
+    let result = token_accounts::withdraw(arg).await;
+
+    record_call("icrc7_token_withdraw", result.is_err());
+
+    result
+}
+
+/// ======== Fractions ========
+
+#[query]
+pub fn icrc7_fraction(token_id: u128) -> Option<Fraction> {
+    fractions::fraction(&token_id)
+}
+
+#[query]
+pub fn icrc7_fractions(prev: Option<u128>, take: Option<u128>) -> Vec<Fraction> {
+    fractions::fractions(prev, take)
+}
+
+#[update]
+pub async fn icrc7_fractionalize(arg: FractionalizeArgs) -> Result<(), MarketError> {
+    let result = fractions::fractionalize(arg).await;
+
+    record_call("icrc7_fractionalize", result.is_err());
+
+    result
+}
+
+#[update]
+pub async fn icrc7_redeem(arg: FractionArgs) -> Result<(), MarketError> {
+    let result = fractions::redeem(arg).await;
+
+    record_call("icrc7_redeem", result.is_err());
+
+    result
+}
+
+#[update]
+pub async fn icrc7_buyout(arg: FractionArgs) -> Result<(), MarketError> {
+    let result = fractions::buyout(arg).await;
+
+    record_call("icrc7_buyout", result.is_err());
+
+    result
+}
+
+#[update]
+pub async fn icrc7_claim_buyout(arg: FractionArgs) -> Result<u128, MarketError> {
+    let result = fractions::claim(arg).await;
+
+    record_call("icrc7_claim_buyout", result.is_err());
+
+    result
+}
+
+/// ======== Staking ========
+
+#[query]
+pub fn icrc7_staking_config() -> StakingConfig {
+    staking::staking_config()
+}
+
+#[query]
+pub fn icrc7_stake_of(token_id: u128) -> Option<Stake> {
+    staking::stake_of(&token_id)
+}
+
+#[query]
+pub fn icrc7_stakes_of(account: ICRCAccount) -> Vec<Stake> {
+    staking::stakes_of(&account)
+}
+
+#[query]
+pub fn icrc7_stake_history(
+    token_id: Option<u128>,
+    prev: Option<u64>,
+    take: Option<u128>,
+) -> Vec<StakeEvent> {
+    staking::stake_history(token_id, prev, take)
+}
+
+#[update]
+pub fn icrc7_set_staking_config(config: StakingConfig) -> Result<(), StakingError> {
+    guard_role(Role::Admin);
+
+    let result = staking::set_staking_config(config);
+
+    record_call("icrc7_set_staking_config", result.is_err());
+
+    result
+}
+
+#[query]
+pub fn icrc7_reward_budget(ledger: Principal) -> u128 {
+    staking::reward_budget(&ledger)
+}
+
+/// Anyone may fund the rewards, the caller approves `amount + fee` first.
+#[update]
+pub async fn icrc7_fund_rewards(arg: FundRewardsArgs) -> Result<u128, StakingError> {
+    let result = staking::fund_rewards(arg).await;
+
+    record_call("icrc7_fund_rewards", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_stake(arg: StakeArgs) -> Result<u64, StakingError> {
+    let result = staking::stake(arg);
+
+    record_call("icrc7_stake", result.is_err());
+
+    result
+}
+
+#[update]
+pub async fn icrc7_claim_rewards(arg: UnstakeArgs) -> Result<u128, StakingError> {
+    let result = staking::claim(arg).await;
+
+    record_call("icrc7_claim_rewards", result.is_err());
+
+    result
+}
+
+#[update]
+pub async fn icrc7_unstake(arg: UnstakeArgs) -> Result<u128, StakingError> {
+    let result = staking::unstake(arg).await;
+
+    record_call("icrc7_unstake", result.is_err());
+
+    result
+}
+
+/// ======== Rentals ========
+
+#[query]
+pub fn icrc7_user_of(token_id: u128) -> ICRCAccount {
+    rentals::user_of(&token_id)
+}
+
+#[query]
+pub fn icrc7_rental(token_id: u128) -> Option<Rental> {
+    rentals::rental(&token_id)
+}
+
+#[query]
+pub fn icrc7_rental_terms(token_id: u128) -> Option<RentalTerms> {
+    rentals::rental_terms(&token_id)
+}
+
+#[update]
+pub fn icrc7_set_user(arg: SetUserArgs) -> Result<(), RentalError> {
+    let result = rentals::set_user(arg);
+
+    record_call("icrc7_set_user", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_set_rental_terms(arg: SetRentalTermsArgs) -> Result<(), RentalError> {
+    let result = rentals::set_rental_terms(arg);
+
+    record_call("icrc7_set_rental_terms", result.is_err());
+
+    result
+}
+
+#[update]
+pub async fn icrc7_rent(arg: RentArgs) -> Result<u64, RentalError> {
+    let result = rentals::rent(arg).await;
+
+    record_call("icrc7_rent", result.is_err());
+
+    result
+}
+
+/// ======== Sale ========
+
+#[query]
+pub fn icrc7_mint_phases() -> Vec<MintPhase> {
+    sale::sale_state().phases
+}
+
+#[query]
+pub fn icrc7_active_phase() -> Option<MintPhase> {
+    sale::active_phase()
+}
+
+#[query]
+pub fn icrc7_is_allowlisted(phase_id: u32, principal: Principal) -> bool {
+    sale::is_allowlisted(phase_id, principal)
+}
+
+#[query]
+pub fn icrc7_minted_in_phase(phase_id: u32, principal: Principal) -> u32 {
+    sale::minted_in_phase(phase_id, principal)
+}
+
+#[query]
+pub fn icrc7_mint_price() -> Option<u128> {
+    sale::mint_price()
+}
+
+#[query]
+pub fn icrc7_treasury() -> Option<ICRCAccount> {
+    sale::sale_state().treasury
+}
+
+#[query]
+pub fn icrc7_sale_log(prev: Option<u64>, take: Option<u128>) -> Vec<SaleLog> {
+    sale::sale_log(prev, take)
+}
+
+#[query]
+pub fn icrc7_drop() -> Option<Vec<DropWindow>> {
+    sale::sale_state().drop
+}
+
+#[query]
+pub fn icrc7_drop_status() -> DropStatus {
+    sale::drop_status()
+}
+
+#[update]
+pub fn icrc7_set_treasury(treasury: Option<ICRCAccount>) {
+    guard_role(Role::Admin);
+
+    sale::set_treasury(treasury);
+
+    record_call("icrc7_set_treasury", false);
+}
+
+#[update]
+pub fn icrc7_add_mint_phase(arg: AddMintPhaseArgs) -> Result<u32, MintError> {
+    guard_role(Role::Admin);
+
+    let result = sale::add_phase(arg);
+
+    record_call("icrc7_add_mint_phase", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_set_active_phase(phase_id: Option<u32>) -> Result<(), MintError> {
+    guard_role(Role::Admin);
+
+    let result = sale::set_active_phase(phase_id);
+
+    record_call("icrc7_set_active_phase", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_set_drop(windows: Option<Vec<DropWindow>>) -> Result<(), MintError> {
+    guard_role(Role::Admin);
+
+    let result = sale::set_drop(windows);
+
+    record_call("icrc7_set_drop", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_add_to_allowlist(phase_id: u32, principals: Vec<Principal>) -> Result<(), MintError> {
+    guard_role(Role::Admin);
+
+    let result = sale::add_to_allowlist(phase_id, principals);
+
+    record_call("icrc7_add_to_allowlist", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_remove_from_allowlist(phase_id: u32, principals: Vec<Principal>) {
+    guard_role(Role::Admin);
+
+    sale::remove_from_allowlist(phase_id, principals);
+
+    record_call("icrc7_remove_from_allowlist", false);
+}
+
+#[update]
+pub async fn icrc7_self_mint(arg: SelfMintArgs) -> Result<u128, MintError> {
+    let result = sale::self_mint(arg).await;
+
+    record_call("icrc7_self_mint", result.is_err());
+
+    result
+}
+
+/// ======== Reveal ========
+
+#[query]
+pub fn icrc7_reveal_state() -> RevealState {
+    reveal::reveal_state()
+}
+
+#[update]
+pub fn icrc7_commit_reveal(arg: CommitRevealArgs) -> Result<(), RevealError> {
+    guard_role(Role::MetadataUpdater);
+
+    let result = reveal::commit(arg);
+
+    record_call("icrc7_commit_reveal", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_upload_reveal(
+    entries: Vec<TokenMetadata>,
+    start: Option<u64>,
+) -> Result<u64, RevealError> {
+    guard_role(Role::MetadataUpdater);
+
+    let result = reveal::upload(entries, start);
+
+    record_call("icrc7_upload_reveal", result.is_err());
+
+    result
+}
+
+#[update]
+pub async fn icrc7_reveal() -> Result<u64, RevealError> {
+    guard_role(Role::MetadataUpdater);
+
+    let result = reveal::reveal().await;
+
+    record_call("icrc7_reveal", result.is_err());
+
+    result
+}
+
+/// ======== Vouchers ========
+
+#[query]
+pub fn icrc7_minter_keys() -> Vec<(u32, MinterKey)> {
+    vouchers::minter_keys()
+}
+
+#[query]
+pub fn icrc7_is_nonce_used(nonce: u64) -> bool {
+    vouchers::is_nonce_used(nonce)
+}
+
+#[update]
+pub fn icrc7_add_minter_key(key: MinterKey) -> Result<u32, VoucherError> {
+    guard_role(Role::Admin);
+
+    let result = vouchers::add_minter_key(key);
+
+    record_call("icrc7_add_minter_key", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_remove_minter_key(key_id: u32) {
+    guard_role(Role::Admin);
+
+    vouchers::remove_minter_key(key_id);
+
+    record_call("icrc7_remove_minter_key", false);
+}
+
+#[update]
+pub async fn icrc7_redeem_voucher(arg: RedeemVoucherArgs) -> Result<u128, VoucherError> {
+    let result = vouchers::redeem(arg).await;
+
+    record_call("icrc7_redeem_voucher", result.is_err());
+
+    result
+}
+
+/// ======== Airdrops ========
+
+#[query]
+pub fn icrc7_airdrop(id: u64) -> Option<Airdrop> {
+    airdrops::airdrop(&id)
+}
+
+#[query]
+pub fn icrc7_airdrops(prev: Option<u64>, take: Option<u128>) -> Vec<Airdrop> {
+    airdrops::airdrops(prev, take)
+}
+
+#[query]
+pub fn icrc7_airdrop_failures(
+    id: u64,
+    prev: Option<u64>,
+    take: Option<u128>,
+) -> Vec<AirdropFailure> {
+    airdrops::failures(id, prev, take)
+}
+
+#[update]
+pub fn icrc7_create_airdrop(mode: AirdropMode) -> Result<u64, AirdropError> {
+    guard_role(Role::Minter);
+
+    let result = airdrops::create(mode);
+
+    record_call("icrc7_create_airdrop", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_upload_airdrop(
+    id: u64,
+    recipients: Vec<AirdropRecipient>,
+) -> Result<u64, AirdropError> {
+    guard_role(Role::Minter);
+
+    let result = airdrops::upload(id, recipients);
+
+    record_call("icrc7_upload_airdrop", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_start_airdrop(id: u64) -> Result<(), AirdropError> {
+    guard_role(Role::Minter);
+
+    let result = airdrops::start(id);
+
+    record_call("icrc7_start_airdrop", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_cancel_airdrop(id: u64) -> Result<(), AirdropError> {
+    guard_role(Role::Minter);
+
+    let result = airdrops::cancel(id);
+
+    record_call("icrc7_cancel_airdrop", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_retry_airdrop(id: u64) -> Result<u64, AirdropError> {
+    guard_role(Role::Minter);
+
+    let result = airdrops::retry(id);
+
+    record_call("icrc7_retry_airdrop", result.is_err());
+
+    result
+}
+
+/// ======== Snapshots ========
+
+/// Owners as of transaction `tx_index`. Transactions from before the upgrade
+/// that introduced the ownership index are replayed from the legacy transfer
+/// log, which carried no ids: they are numbered one per logged call, without
+/// the mints and approvals in between, so indexes from that era do not match
+/// the ids those transactions were given.
+#[query]
+pub fn icrc7_snapshot(tx_index: u128, prev: Option<u128>, take: Option<u128>) -> SnapshotPage {
+    snapshots::snapshot(tx_index, prev, take)
+}
+
+#[query]
+pub fn icrc7_owner_at(token_id: u128, tx_index: u128) -> Option<ICRCAccount> {
+    snapshots::owner_at(token_id, tx_index)
+}
+
+#[query]
+pub fn icrc7_snapshot_export(id: u64) -> Option<SnapshotExport> {
+    snapshots::export(&id)
+}
+
+#[query]
+pub fn icrc7_snapshot_exports(prev: Option<u64>, take: Option<u128>) -> Vec<SnapshotExport> {
+    snapshots::exports(prev, take)
+}
+
+#[update]
+pub fn icrc7_export_snapshot(tx_index: Option<u128>) -> u64 {
+    guard_role(Role::Admin);
+
+    let id = snapshots::start_export(tx_index);
+
+    record_call("icrc7_export_snapshot", false);
+
+    id
+}
+
+/// ======== Subscriptions ========
+
+#[query]
+pub fn icrc7_subscription(id: u64) -> Option<Subscription> {
+    subscriptions::subscription(&id)
+}
+
+#[query]
+pub fn icrc7_subscriptions(prev: Option<u64>, take: Option<u128>) -> Vec<Subscription> {
+    subscriptions::subscriptions(prev, take)
+}
+
+#[query]
+pub fn icrc7_subscription_pending(id: u64) -> u64 {
+    subscriptions::pending(id)
+}
+
+#[query]
+pub fn icrc7_dead_letters(id: u64, prev: Option<u64>, take: Option<u128>) -> Vec<DeadLetter> {
+    subscriptions::dead_letters(id, prev, take)
+}
+
+#[update]
+pub fn icrc7_subscribe(arg: SubscribeArgs) -> Result<u64, SubscriptionError> {
+    let result = subscriptions::subscribe(arg);
+
+    record_call("icrc7_subscribe", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_unsubscribe(id: u64) -> Result<(), SubscriptionError> {
+    let result = subscriptions::unsubscribe(id);
+
+    record_call("icrc7_unsubscribe", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_redeliver_dead_letters(id: u64) -> Result<u64, SubscriptionError> {
+    let result = subscriptions::redeliver(id);
+
+    record_call("icrc7_redeliver_dead_letters", result.is_err());
+
+    result
+}
+
+#[update]
+pub fn icrc7_evict_subscriber(subscriber: Principal) -> u64 {
+    guard_role(Role::Admin);
+
+    let evicted = subscriptions::evict(subscriber);
+
+    record_call("icrc7_evict_subscriber", false);
+
+    evicted
+}
+
+/// ======== Cycles ========
+
+#[query]
+pub fn icrc7_cycles_balance() -> u128 {
+    cycles::balance()
+}
+
+#[query]
+pub fn icrc7_cycles_state() -> CyclesState {
+    cycles::cycles_state()
+}
+
+#[update]
+pub fn icrc7_set_cycles_config(arg: SetCyclesConfigArgs) -> Result<(), CyclesError> {
+    guard_role(Role::Admin);
+
+    let result = cycles::set_config(arg);
+
+    record_call("icrc7_set_cycles_config", result.is_err());
+
+    result
+}
+
+/// Runs the periodic balance check right away.
+#[update]
+pub async fn icrc7_check_cycles() -> CyclesState {
+    guard_role(Role::Admin);
+
+    cycles::check().await;
+
+    record_call("icrc7_check_cycles", false);
+
+    cycles::cycles_state()
+}
+
+/// ======== Roles ========
+
+#[query]
+pub fn icrc7_list_roles() -> Vec<(Principal, Vec<Role>)> {
+    guard_role(Role::Admin);
+
+    roles::list_roles()
+}
+
+#[update]
+pub fn icrc7_grant_role(arg: RoleArgs) {
+    guard_role(Role::Admin);
+
+    roles::grant_role(arg.principal, arg.role);
+
+    record_call("icrc7_grant_role", false);
+}
+
+#[update]
+pub fn icrc7_revoke_role(arg: RoleArgs) {
+    guard_role(Role::Admin);
+
+    roles::revoke_role(arg.principal, arg.role);
+
+    record_call("icrc7_revoke_role", false);
+}
+
+#[query]
+fn http_request(req: HttpRequest) -> HttpResponse {
+    match req.path() {
+        "/token" => {
+            let token_id = req.raw_query_param("id").unwrap();
+
+            let token = TOKENS.with(|tokens| {
+                let tokens = tokens.borrow();
+                match tokens.get(&token_id.parse::<u128>().unwrap_or_default()) {
+                    None => ic_cdk::trap("Invalid Token Id"),
+                    Some(token) => reveal::revealed(token),
+                }
+            });
+
+            HttpResponseBuilder::ok()
+                .header("Content-Type", "application/json; charset=utf-8")
+                .with_body_and_content_length(serde_json::to_string(&token).unwrap_or_default())
+                .build()
+        }
+        "/partition_details" => {
+            let list = with_stable_mem(|pm| pm.partition_details());
+
+            HttpResponseBuilder::ok()
+                .header("Content-Type", "application/json; charset=utf-8")
+                .with_body_and_content_length(serde_json::to_string(&list).unwrap_or_default())
+                .build()
+        }
+        "/transfer_log" => {
+            let transfer_id = req.raw_query_param("id").unwrap();
+
+            let tx_logs = match get_transfer_log(transfer_id.parse::<u64>().unwrap_or_default()) {
+                None => ic_cdk::trap("Invalid Transfer Id"),
+                Some(log) => log,
+            };
+
+            HttpResponseBuilder::ok()
+                .header("Content-Type", "application/json; charset=utf-8")
+                .with_body_and_content_length(serde_json::to_string(&tx_logs).unwrap_or_default())
+                .build()
+        }
+        "/audit_log" => {
+            let audit_id = req.raw_query_param("id").unwrap();
+
+            let audit_log = AUDIT_LOG.with(|logs| {
+                let logs = logs.borrow();
+                match logs.get(&audit_id.parse::<u64>().unwrap_or_default()) {
+                    None => ic_cdk::trap("Invalid Audit Id"),
+                    Some(log) => log,
+                }
+            });
+
+            HttpResponseBuilder::ok()
+                .header("Content-Type", "application/json; charset=utf-8")
+                .with_body_and_content_length(serde_json::to_string(&audit_log).unwrap_or_default())
+                .build()
+        }
+        "/snapshot" => {
+            let export_id = req.raw_query_param("id").unwrap();
+
+            let export = match snapshots::export(&export_id.parse::<u64>().unwrap_or_default()) {
+                Some(export) if export.status == ExportStatus::Ready => export,
+                _ => ic_cdk::trap("Invalid Export Id"),
+            };
+            let page = req
+                .raw_query_param("page")
+                .and_then(|page| page.parse::<usize>().ok())
+                .unwrap_or_default();
+            let holders = snapshots::holders(export.id, page);
+
+            match req.raw_query_param("format") {
+                Some("csv") => HttpResponseBuilder::ok()
+                    .header("Content-Type", "text/csv; charset=utf-8")
+                    .with_body_and_content_length(snapshots::holders_csv(&holders))
+                    .build(),
+                _ => HttpResponseBuilder::ok()
+                    .header("Content-Type", "application/json; charset=utf-8")
+                    .with_body_and_content_length(snapshots::holders_json(&holders))
+                    .build(),
+            }
         }
+        "/metrics" if features().metrics() => HttpResponseBuilder::ok()
+            .header("Content-Type", "text/plain; version=0.0.4")
+            .with_body_and_content_length(encode_metrics())
+            .build(),
         _ => HttpResponseBuilder::not_found().build(),
     }
 }
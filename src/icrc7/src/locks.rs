@@ -0,0 +1,63 @@
+use crate::state::LOCKED_TOKENS;
+use b3_utils::memory::types::{Bound, Storable};
+use candid::{CandidType, Decode, Encode};
+use serde_derive::{Deserialize, Serialize};
+
+/// Why a token is held by the canister and cannot move.
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Lock {
+    /// A marketplace purchase is waiting on the buyer's payment.
+    Sale,
+    /// Held for the duration of an auction.
+    Auction,
+    /// The owner is withdrawing from the token-bound account.
+    Withdrawal,
+    /// A paid rental is waiting on the renter's payment.
+    Rental,
+    /// Held in the vault while its shares are outstanding.
+    Fraction,
+    /// Staked by its owner until the stake is released.
+    Staked,
+    /// Sent to a canister whose `icrc7_on_received` hook has not returned yet.
+    Receiving,
+}
+
+impl Storable for Lock {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+pub fn lock_of(id: &u128) -> Option<Lock> {
+    LOCKED_TOKENS.with(|l| l.borrow().get(id))
+}
+
+pub fn is_locked(id: &u128) -> bool {
+    LOCKED_TOKENS.with(|l| l.borrow().contains_key(id))
+}
+
+/// Returns `false` when the token already holds another lock.
+pub fn lock_token(id: u128, lock: Lock) -> bool {
+    LOCKED_TOKENS.with(|l| {
+        let mut l = l.borrow_mut();
+        if l.contains_key(&id) {
+            return false;
+        }
+        l.insert(id, lock);
+        true
+    })
+}
+
+pub fn unlock_token(id: &u128) {
+    LOCKED_TOKENS.with(|l| l.borrow_mut().remove(id));
+}
+
+pub fn locked_tokens() -> Vec<(u128, Lock)> {
+    LOCKED_TOKENS.with(|l| l.borrow().iter().collect())
+}
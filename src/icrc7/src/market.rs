@@ -0,0 +1,313 @@
+use crate::{
+    errors::{MarketError, TransferError},
+    history::{self, TokenEventKind},
+    locks::{is_locked, lock_token, unlock_token, Lock},
+    offers::invalidate_token_offers,
+    pause::{is_frozen, pause_state},
+    payments::{collect, ledger_fee, pay_out, payment_ledger},
+    rentals::remove_rental_terms,
+    snapshots::record_owner,
+    state::{increment_tx_id, log_transaction, LogOp, Token, CONFIG, LISTINGS, TOKENS},
+    types::{BuyArgs, ListArgs},
+};
+use b3_utils::{
+    ledger::ICRCAccount,
+    memory::types::{Bound, Storable},
+};
+use candid::{CandidType, Decode, Encode, Principal};
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct Listing {
+    pub token_id: u128,
+    pub seller: ICRCAccount,
+    pub price: u128,
+    pub listed_at: u64,
+}
+
+impl Storable for Listing {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+pub fn listing(token_id: &u128) -> Option<Listing> {
+    LISTINGS.with(|l| l.borrow().get(token_id))
+}
+
+pub fn listings(prev: Option<u128>, take: Option<u128>) -> Vec<Listing> {
+    let take = take.unwrap_or(100) as usize;
+
+    LISTINGS.with(|l| {
+        let l = l.borrow();
+        match prev {
+            None => l.iter().map(|(_, listing)| listing).take(take).collect(),
+            Some(prev) => l
+                .range(prev..)
+                .filter(|(id, _)| *id != prev)
+                .map(|(_, listing)| listing)
+                .take(take)
+                .collect(),
+        }
+    })
+}
+
+/// Listings die with the ownership they were created under.
+pub fn remove_listing(token_id: &u128) {
+    LISTINGS.with(|l| l.borrow_mut().remove(token_id));
+}
+
+/// Drops everything that was tied to the previous owner of a token that
+/// just moved or was burned.
+pub fn token_moved(token_id: &u128) {
+    record_owner(token_id);
+    remove_listing(token_id);
+    invalidate_token_offers(token_id);
+    remove_rental_terms(token_id);
+}
+
+pub fn sellable(token: &Token) -> Result<(), MarketError> {
+    if pause_state().transfers {
+        return Err(MarketError::Paused);
+    }
+    if is_frozen(&token.id) {
+        return Err(MarketError::TokenFrozen {
+            tokens_ids: vec![token.id],
+        });
+    }
+    if !token.is_transferable() {
+        return Err(MarketError::NonTransferable {
+            tokens_ids: vec![token.id],
+        });
+    }
+    if is_locked(&token.id) {
+        return Err(MarketError::TokenLocked {
+            tokens_ids: vec![token.id],
+        });
+    }
+    Ok(())
+}
+
+pub fn list(arg: ListArgs) -> Result<(), MarketError> {
+    let seller = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+
+    if payment_ledger().is_none() {
+        return Err(MarketError::NoPaymentLedger);
+    }
+    if arg.price == 0 {
+        return Err(MarketError::InvalidPrice);
+    }
+
+    let token = match TOKENS.with(|tokens| tokens.borrow().get(&arg.token_id)) {
+        None => ic_cdk::trap("Invalid Id"),
+        Some(token) => token,
+    };
+    if token.owner != seller {
+        return Err(MarketError::Unauthorized {
+            tokens_ids: vec![token.id],
+        });
+    }
+    sellable(&token)?;
+
+    let listing = Listing {
+        token_id: token.id,
+        seller,
+        price: arg.price,
+        listed_at: ic_cdk::api::time(),
+    };
+    LISTINGS.with(|l| l.borrow_mut().insert(token.id, listing));
+
+    Ok(())
+}
+
+pub fn delist(token_id: u128) -> Result<(), MarketError> {
+    let caller = ic_cdk::caller();
+
+    let listing = listing(&token_id).ok_or(MarketError::NotListed)?;
+    if listing.seller.owner() != caller {
+        return Err(MarketError::Unauthorized {
+            tokens_ids: vec![token_id],
+        });
+    }
+    if is_locked(&token_id) {
+        return Err(MarketError::TokenLocked {
+            tokens_ids: vec![token_id],
+        });
+    }
+
+    remove_listing(&token_id);
+
+    Ok(())
+}
+
+/// Splits `price` into the seller share and the royalty share.
+pub fn royalty_split(price: u128) -> (u128, Option<(ICRCAccount, u128)>) {
+    let (royalties, recipient) = CONFIG.with(|c| {
+        let c = c.borrow();
+        let config = c.get();
+        (config.royalties(), config.royalty_recipient())
+    });
+
+    match (royalties, recipient) {
+        (Some(bps), Some(recipient)) if bps > 0 => {
+            let royalty = price / 10_000 * bps as u128 + price % 10_000 * bps as u128 / 10_000;
+            (price - royalty, Some((recipient, royalty)))
+        }
+        _ => (price, None),
+    }
+}
+
+/// Pays the listed price via ICRC-2 `transfer_from`, then moves the token to
+/// the buyer and logs the transfer in the same message. Proceeds are paid out
+/// afterwards, failed payouts are queued for retry.
+pub async fn buy(arg: BuyArgs) -> Result<u128, MarketError> {
+    let buyer = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let ledger = payment_ledger().ok_or(MarketError::NoPaymentLedger)?;
+
+    let listing = listing(&arg.token_id).ok_or(MarketError::NotListed)?;
+    if listing.price != arg.price {
+        return Err(MarketError::PriceChanged {
+            price: listing.price,
+        });
+    }
+    if listing.seller == buyer {
+        return Err(MarketError::Unauthorized {
+            tokens_ids: vec![arg.token_id],
+        });
+    }
+
+    let token = TOKENS.with(|tokens| tokens.borrow().get(&arg.token_id).unwrap());
+    if token.owner != listing.seller {
+        remove_listing(&arg.token_id);
+        return Err(MarketError::NotListed);
+    }
+    sellable(&token)?;
+
+    let fee = ledger_fee(ledger)
+        .await
+        .map_err(|msg| MarketError::PaymentFailed { msg })?;
+
+    // the listing may have changed while waiting for the fee
+    match self::listing(&arg.token_id) {
+        Some(current) if current.price == listing.price && current.seller == listing.seller => {}
+        _ => return Err(MarketError::NotListed),
+    }
+    if !lock_token(arg.token_id, Lock::Sale) {
+        return Err(MarketError::TokenLocked {
+            tokens_ids: vec![arg.token_id],
+        });
+    }
+
+    if let Err(msg) = collect(ledger, buyer.clone(), listing.price).await {
+        unlock_token(&arg.token_id);
+        return Err(MarketError::PaymentFailed { msg });
+    }
+
+    unlock_token(&arg.token_id);
+
+    // the collection may have been paused or the token frozen while the
+    // payment was in flight, the buyer is refunded then
+    let still_sellable = match TOKENS.with(|tokens| tokens.borrow().get(&arg.token_id)) {
+        Some(token) if token.owner == listing.seller => sellable(&token),
+        _ => Err(MarketError::NotListed),
+    };
+    if let Err(e) = still_sellable {
+        pay_out(ledger, fee, buyer, listing.price).await;
+        return Err(e);
+    }
+
+    remove_listing(&arg.token_id);
+
+    settle_sale(
+        ledger,
+        fee,
+        arg.token_id,
+        listing.seller,
+        buyer,
+        listing.price,
+    )
+    .await
+}
+
+/// Moves a token on behalf of the canister, with the same checks and
+/// transfer log entry as `icrc7_transfer`, the transfer pause included.
+pub fn move_token(
+    token_id: u128,
+    from: &ICRCAccount,
+    to: ICRCAccount,
+) -> Result<u128, TransferError> {
+    if pause_state().transfers {
+        return Err(TransferError::Paused);
+    }
+
+    return_token(token_id, from, to)
+}
+
+/// `move_token` past the transfer pause, only for sending a token back where
+/// it just came from, e.g. when a recipient canister rejects it.
+pub fn return_token(
+    token_id: u128,
+    from: &ICRCAccount,
+    to: ICRCAccount,
+) -> Result<u128, TransferError> {
+    let mut token = TOKENS.with(|tokens| tokens.borrow().get(&token_id).unwrap());
+    let now = ic_cdk::api::time();
+    let owner = token.owner.clone();
+
+    token.transfer(now, from, to.clone())?;
+
+    TOKENS.with(|tokens| tokens.borrow_mut().insert(token_id, token));
+    token_moved(&token_id);
+    history::record(
+        token_id,
+        TokenEventKind::Transfer {
+            from: owner,
+            to: to.clone(),
+            memo: None,
+        },
+    );
+    log_transaction(
+        LogOp::Transfer {
+            id: token_id,
+            from: from.clone(),
+            to,
+        },
+        None,
+    );
+
+    Ok(increment_tx_id())
+}
+
+/// Moves a paid-for token from `seller` to `buyer`, logs the transfer in the
+/// same message and pays out the proceeds. The buyer is refunded when the token
+/// can no longer move, e.g. it was frozen while the payment was in flight.
+pub async fn settle_sale(
+    ledger: Principal,
+    fee: u128,
+    token_id: u128,
+    seller: ICRCAccount,
+    buyer: ICRCAccount,
+    price: u128,
+) -> Result<u128, MarketError> {
+    let tx_id = match move_token(token_id, &seller, buyer.clone()) {
+        Ok(tx_id) => tx_id,
+        Err(e) => {
+            pay_out(ledger, fee, buyer, price).await;
+            return Err(e.into());
+        }
+    };
+
+    let (seller_share, royalty) = royalty_split(price);
+    pay_out(ledger, fee, seller, seller_share).await;
+    if let Some((recipient, royalty)) = royalty {
+        pay_out(ledger, fee, recipient, royalty).await;
+    }
+
+    Ok(tx_id)
+}
@@ -0,0 +1,184 @@
+use crate::state::{get_total_supply, transfer_log_len, METRICS, TOKENS};
+use b3_utils::memory::{
+    types::{Bound, Storable},
+    with_stable_mem,
+};
+use candid::{CandidType, Decode, Encode};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+
+const WASM_PAGE_SIZE: u64 = 65536;
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Default)]
+pub struct EndpointMetrics {
+    pub calls: u64,
+    pub errors: u64,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Default)]
+pub struct Metrics {
+    pub endpoints: BTreeMap<String, EndpointMetrics>,
+}
+
+impl Storable for Metrics {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+/// Counts a call to an update handler, and an error when it returned `Err`.
+/// Calls that trap are rolled back together with their counters.
+pub fn record_call(endpoint: &str, failed: bool) {
+    METRICS.with(|m| {
+        let mut m = m.borrow_mut();
+        let mut metrics = m.get().endpoints.clone();
+
+        let entry = metrics.entry(endpoint.to_string()).or_default();
+        entry.calls += 1;
+        if failed {
+            entry.errors += 1;
+        }
+
+        m.set(Metrics { endpoints: metrics }).unwrap();
+    });
+}
+
+fn heap_memory_size() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        core::arch::wasm32::memory_size(0) as u64 * WASM_PAGE_SIZE
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        0
+    }
+}
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str) {
+    writeln!(out, "# HELP {} {}", name, help).unwrap();
+    writeln!(out, "# TYPE {} {}", name, kind).unwrap();
+}
+
+/// Renders the canister metrics in the Prometheus text exposition format.
+pub fn encode_metrics() -> String {
+    let mut out = String::new();
+
+    write_metric(
+        &mut out,
+        "icrc7_cycles_balance",
+        "gauge",
+        "Cycles balance of the canister.",
+    );
+    writeln!(
+        out,
+        "icrc7_cycles_balance {}",
+        ic_cdk::api::canister_balance128()
+    )
+    .unwrap();
+
+    write_metric(
+        &mut out,
+        "icrc7_heap_memory_bytes",
+        "gauge",
+        "Size of the heap memory in bytes.",
+    );
+    writeln!(out, "icrc7_heap_memory_bytes {}", heap_memory_size()).unwrap();
+
+    write_metric(
+        &mut out,
+        "icrc7_stable_memory_bytes",
+        "gauge",
+        "Size of each stable memory partition in bytes.",
+    );
+    for partition in with_stable_mem(|pm| pm.partition_details()) {
+        writeln!(
+            out,
+            "icrc7_stable_memory_bytes{{partition=\"{}\"}} {}",
+            partition.name,
+            partition.size * WASM_PAGE_SIZE
+        )
+        .unwrap();
+    }
+
+    let mut holders = HashSet::new();
+    let mut approvals = 0;
+    TOKENS.with(|tokens| {
+        for (_, token) in tokens.borrow().iter() {
+            approvals += token.approvals.len();
+            holders.insert(token.owner);
+        }
+    });
+
+    write_metric(
+        &mut out,
+        "icrc7_total_supply",
+        "gauge",
+        "Number of tokens in circulation.",
+    );
+    writeln!(out, "icrc7_total_supply {}", get_total_supply()).unwrap();
+
+    write_metric(
+        &mut out,
+        "icrc7_holders",
+        "gauge",
+        "Number of distinct token holders.",
+    );
+    writeln!(out, "icrc7_holders {}", holders.len()).unwrap();
+
+    write_metric(
+        &mut out,
+        "icrc7_transfer_log_length",
+        "gauge",
+        "Number of entries in the transfer log.",
+    );
+    writeln!(out, "icrc7_transfer_log_length {}", transfer_log_len()).unwrap();
+
+    write_metric(
+        &mut out,
+        "icrc7_approvals",
+        "gauge",
+        "Number of approvals stored on tokens.",
+    );
+    writeln!(out, "icrc7_approvals {}", approvals).unwrap();
+
+    let endpoints = METRICS.with(|m| m.borrow().get().endpoints.clone());
+
+    write_metric(
+        &mut out,
+        "icrc7_endpoint_calls_total",
+        "counter",
+        "Number of calls per update endpoint.",
+    );
+    for (endpoint, metrics) in endpoints.iter() {
+        writeln!(
+            out,
+            "icrc7_endpoint_calls_total{{endpoint=\"{}\"}} {}",
+            endpoint, metrics.calls
+        )
+        .unwrap();
+    }
+
+    write_metric(
+        &mut out,
+        "icrc7_endpoint_errors_total",
+        "counter",
+        "Number of errors per update endpoint.",
+    );
+    for (endpoint, metrics) in endpoints.iter() {
+        writeln!(
+            out,
+            "icrc7_endpoint_errors_total{{endpoint=\"{}\"}} {}",
+            endpoint, metrics.errors
+        )
+        .unwrap();
+    }
+
+    out
+}
@@ -0,0 +1,330 @@
+use crate::{
+    audit::{log_event, AuditEvent},
+    history::{self, history_start, TokenEvent, TokenEventKind},
+    snapshots::{is_indexed, set_owner},
+    state::{
+        get_transfer_log, transfer_log_len, LogOp, TransferLog, CONFIG, LEGACY_TRANSFER_LOG,
+        MIGRATION_CURSOR, SCHEMA_VERSION, TOKENS, TRANSFER_LOG,
+    },
+};
+use candid::{CandidType, Encode};
+use serde_derive::Deserialize;
+use std::ops::Bound;
+use std::time::Duration;
+
+const CANDID_MAGIC: &[u8] = b"DIDL";
+
+/// Instructions a single timer slice may spend before yielding.
+const INSTRUCTION_BUDGET: u64 = 2_000_000_000;
+
+/// Prefixes the Candid encoding of a stored record with its schema version.
+pub fn encode_versioned<T: CandidType>(version: u8, value: &T) -> Vec<u8> {
+    let mut bytes = vec![version];
+    bytes.extend(Encode!(value).unwrap());
+    bytes
+}
+
+/// Splits a stored record into its schema version and Candid payload.
+/// Records written before versioning are raw Candid and report version 0.
+pub fn split_versioned(bytes: &[u8]) -> (u8, &[u8]) {
+    if bytes.starts_with(CANDID_MAGIC) {
+        (0, bytes)
+    } else {
+        (bytes[0], &bytes[1..])
+    }
+}
+
+pub struct Migration {
+    /// Schema version reached once the migration has completed.
+    pub version: u32,
+    pub description: &'static str,
+    /// Rewrites records after `cursor` until the instruction budget runs out.
+    /// Returns the cursor to resume from, or `None` once every record is current.
+    pub run: fn(Option<u128>) -> Option<u128>,
+}
+
+pub const MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        description: "Store the collection config with its schema version",
+        run: rewrite_config,
+    },
+    Migration {
+        version: 2,
+        description: "Store tokens with their schema version",
+        run: rewrite_tokens,
+    },
+    Migration {
+        version: 3,
+        description: "Index token owners for snapshots from the transfer log",
+        run: index_owners,
+    },
+    Migration {
+        version: 4,
+        description: "Move transfer logs into the unbounded transaction log",
+        run: move_transfer_logs,
+    },
+    Migration {
+        version: 5,
+        description: "Index owners of tokens missing from the transfer log",
+        run: index_unlogged_owners,
+    },
+    Migration {
+        version: 6,
+        description: "Backfill token histories from the legacy transfer log",
+        run: backfill_token_history,
+    },
+];
+
+pub fn latest_version() -> u32 {
+    MIGRATIONS.last().map(|m| m.version).unwrap_or_default()
+}
+
+pub fn schema_version() -> u32 {
+    SCHEMA_VERSION.with(|v| *v.borrow().get())
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct MigrationStatus {
+    pub schema_version: u32,
+    pub latest_version: u32,
+    pub cursor: Option<u128>,
+}
+
+pub fn status() -> MigrationStatus {
+    MigrationStatus {
+        schema_version: schema_version(),
+        latest_version: latest_version(),
+        cursor: MIGRATION_CURSOR.with(|c| *c.borrow().get()),
+    }
+}
+
+/// A fresh install has no old records, so it starts at the latest version.
+pub fn mark_current() {
+    SCHEMA_VERSION.with(|v| v.borrow_mut().set(latest_version()).unwrap());
+}
+
+pub fn budget_exhausted() -> bool {
+    ic_cdk::api::instruction_counter() > INSTRUCTION_BUDGET
+}
+
+/// Runs pending migrations in the next round, one slice per timer.
+pub fn schedule() {
+    if schema_version() < latest_version() {
+        ic_cdk_timers::set_timer(Duration::ZERO, run_pending);
+    }
+}
+
+fn run_pending() {
+    for migration in MIGRATIONS.iter() {
+        if migration.version <= schema_version() {
+            continue;
+        }
+
+        let cursor = MIGRATION_CURSOR.with(|c| *c.borrow().get());
+        let next = (migration.run)(cursor);
+
+        MIGRATION_CURSOR.with(|c| c.borrow_mut().set(next).unwrap());
+
+        if next.is_some() {
+            return schedule();
+        }
+
+        log_event(AuditEvent::MigrationCompleted {
+            version: migration.version,
+        });
+        SCHEMA_VERSION.with(|v| v.borrow_mut().set(migration.version).unwrap());
+
+        if budget_exhausted() {
+            return schedule();
+        }
+    }
+}
+
+fn rewrite_config(_: Option<u128>) -> Option<u128> {
+    CONFIG.with(|c| {
+        let mut c = c.borrow_mut();
+        let config = c.get().clone();
+        c.set(config).unwrap();
+    });
+
+    None
+}
+
+fn rewrite_tokens(cursor: Option<u128>) -> Option<u128> {
+    let mut cursor = cursor;
+
+    loop {
+        let lower = match cursor {
+            None => Bound::Unbounded,
+            Some(id) => Bound::Excluded(id),
+        };
+        let next = TOKENS.with(|tokens| tokens.borrow().range((lower, Bound::Unbounded)).next());
+
+        match next {
+            None => return None,
+            Some((id, token)) => {
+                TOKENS.with(|tokens| tokens.borrow_mut().insert(id, token));
+                cursor = Some(id);
+            }
+        }
+
+        if budget_exhausted() {
+            return cursor;
+        }
+    }
+}
+
+/// Walks the transfer log in order with the transaction of every entry.
+/// Legacy entries did not record theirs, the first release used one
+/// transaction per transfer call and logged every token of a call at the same
+/// time, so they are numbered by call instead. That places them at the
+/// earliest transaction they can belong to, mints and approvals in between
+/// were not logged.
+struct LogReplay {
+    /// Legacy calls seen so far.
+    calls: u64,
+    /// Next log position.
+    position: u64,
+}
+
+impl LogReplay {
+    fn resume(cursor: Option<u128>) -> Self {
+        let cursor = cursor.unwrap_or_default();
+
+        LogReplay {
+            calls: (cursor >> 64) as u64,
+            position: cursor as u64,
+        }
+    }
+
+    fn cursor(&self) -> Option<u128> {
+        Some(((self.calls as u128) << 64) | self.position as u128)
+    }
+
+    /// The entry at the next position below `end`, its position and
+    /// transaction.
+    fn next(&mut self, end: u64) -> Option<(u64, u128, TransferLog)> {
+        if self.position >= end {
+            return None;
+        }
+        let position = self.position;
+        let log = get_transfer_log(position)?;
+
+        let tx_index = match log.tx_index {
+            Some(tx_index) => tx_index,
+            None => {
+                let previous = position.checked_sub(1).and_then(get_transfer_log);
+                if previous.filter(|previous| previous.at == log.at).is_none() {
+                    self.calls += 1;
+                }
+                (self.calls - 1) as u128
+            }
+        };
+        self.position += 1;
+
+        Some((position, tx_index, log))
+    }
+}
+
+/// Replays the transfer log into the ownership index, each entry records the
+/// owner the token moved to.
+fn index_owners(cursor: Option<u128>) -> Option<u128> {
+    let mut replay = LogReplay::resume(cursor);
+
+    while let Some((_, tx_index, log)) = replay.next(transfer_log_len()) {
+        match log.op {
+            LogOp::Transfer { id, to, .. } => set_owner(id, tx_index, Some(to)),
+            LogOp::Burn { id, .. } => set_owner(id, tx_index, None),
+            LogOp::ConfigUpdate { .. } => {}
+        }
+
+        if budget_exhausted() {
+            return replay.cursor();
+        }
+    }
+
+    None
+}
+
+/// Tokens never transferred have been with their owner since they were
+/// minted, which the first release did not log, so they are indexed from
+/// the first transaction on.
+fn index_unlogged_owners(cursor: Option<u128>) -> Option<u128> {
+    let mut cursor = cursor;
+
+    loop {
+        let lower = match cursor {
+            None => Bound::Unbounded,
+            Some(id) => Bound::Excluded(id),
+        };
+        let next = TOKENS.with(|tokens| tokens.borrow().range((lower, Bound::Unbounded)).next());
+
+        match next {
+            None => return None,
+            Some((id, token)) => {
+                if !is_indexed(&id) {
+                    set_owner(id, 0, Some(token.owner));
+                }
+                cursor = Some(id);
+            }
+        }
+
+        if budget_exhausted() {
+            return cursor;
+        }
+    }
+}
+
+/// Pops entries off the back of the legacy log so that every index is found
+/// in exactly one of the two logs at any time, see `state::get_transfer_log`.
+fn move_transfer_logs(_: Option<u128>) -> Option<u128> {
+    LEGACY_TRANSFER_LOG.with(|legacy| {
+        let legacy = legacy.borrow();
+
+        while let Some(log) = legacy.pop() {
+            TRANSFER_LOG.with(|logs| logs.borrow_mut().insert(legacy.len(), log.into()));
+
+            if budget_exhausted() {
+                return Some(legacy.len() as u128);
+            }
+        }
+
+        None
+    })
+}
+
+/// Turns the legacy log entries into token events at the positions kept free
+/// for them, see `history::reserve_legacy_positions`. The legacy log recorded
+/// the caller as `from`, which is the owner unless a spender transferred.
+fn backfill_token_history(cursor: Option<u128>) -> Option<u128> {
+    let mut replay = LogReplay::resume(cursor);
+
+    while let Some((position, tx_index, log)) = replay.next(history_start()) {
+        let (token_id, kind) = match log.op {
+            LogOp::Transfer { id, from, to } => (
+                id,
+                TokenEventKind::Transfer {
+                    from,
+                    to,
+                    memo: log.memo,
+                },
+            ),
+            LogOp::Burn { id, from } => (id, TokenEventKind::Burn { from }),
+            LogOp::ConfigUpdate { .. } => continue,
+        };
+        history::insert(&TokenEvent {
+            position,
+            token_id,
+            tx_index,
+            at: log.at,
+            kind,
+        });
+
+        if budget_exhausted() {
+            return replay.cursor();
+        }
+    }
+
+    None
+}
@@ -0,0 +1,290 @@
+use crate::{
+    errors::MarketError,
+    locks::{lock_token, unlock_token, Lock},
+    market::{sellable, settle_sale},
+    payments::{
+        collect, ledger_fee, pay_out, payment_ledger, queue_payout, schedule_payouts, Payout,
+    },
+    state::{is_unexpired, Token, CONFIG, OFFERS, OFFER_ID, TOKENS, TOKEN_OFFERS},
+    types::{AcceptOfferArgs, MakeOfferArgs},
+};
+use b3_utils::{
+    ledger::ICRCAccount,
+    memory::types::{Bound, Storable},
+};
+use candid::{CandidType, Decode, Encode, Principal};
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct Offer {
+    pub id: u64,
+    pub buyer: ICRCAccount,
+    /// `None` bids on any token of the collection.
+    pub token_id: Option<u128>,
+    pub amount: u128,
+    /// Ledger holding the escrowed amount.
+    pub ledger: Principal,
+    pub created_at: u64,
+    /// Same semantics as `Approval::expires_at`.
+    pub expires_at: Option<u64>,
+}
+
+impl Storable for Offer {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Offer {
+    pub fn is_active(&self, current_time: u64) -> bool {
+        is_unexpired(self.expires_at, current_time)
+    }
+}
+
+pub fn offer(id: &u64) -> Option<Offer> {
+    OFFERS.with(|o| o.borrow().get(id))
+}
+
+pub fn offers(prev: Option<u64>, take: Option<u128>) -> Vec<Offer> {
+    let take = take.unwrap_or(100) as usize;
+
+    OFFERS.with(|o| {
+        let o = o.borrow();
+        match prev {
+            None => o.iter().map(|(_, offer)| offer).take(take).collect(),
+            Some(prev) => o
+                .range(prev..)
+                .filter(|(id, _)| *id != prev)
+                .map(|(_, offer)| offer)
+                .take(take)
+                .collect(),
+        }
+    })
+}
+
+pub fn token_offers(token_id: &u128) -> Vec<Offer> {
+    token_offer_ids(token_id).iter().filter_map(offer).collect()
+}
+
+fn token_offer_ids(token_id: &u128) -> Vec<u64> {
+    TOKEN_OFFERS.with(|t| {
+        t.borrow()
+            .range((*token_id, 0)..=(*token_id, u64::MAX))
+            .map(|((_, id), _)| id)
+            .collect()
+    })
+}
+
+fn next_offer_id() -> u64 {
+    OFFER_ID.with(|o| {
+        let mut o = o.borrow_mut();
+        let id = *o.get();
+        o.set(id + 1).unwrap();
+        id
+    })
+}
+
+fn save(offer: Offer) {
+    if let Some(token_id) = offer.token_id {
+        TOKEN_OFFERS.with(|t| t.borrow_mut().insert((token_id, offer.id), ()));
+    }
+    OFFERS.with(|o| o.borrow_mut().insert(offer.id, offer));
+}
+
+fn remove(offer: &Offer) {
+    if let Some(token_id) = offer.token_id {
+        TOKEN_OFFERS.with(|t| t.borrow_mut().remove(&(token_id, offer.id)));
+    }
+    OFFERS.with(|o| o.borrow_mut().remove(&offer.id));
+}
+
+async fn refund(offer: Offer) {
+    match ledger_fee(offer.ledger).await {
+        Ok(fee) => pay_out(offer.ledger, fee, offer.buyer, offer.amount).await,
+        // queued like any failed payout, the retry looks the fee up again
+        Err(_) => pay_out(offer.ledger, 0, offer.buyer, offer.amount).await,
+    }
+}
+
+/// A token stays open to offers while it can move and is not held by the
+/// bidder.
+fn check_offerable(token: &Token, buyer: &ICRCAccount) -> Result<(), MarketError> {
+    if token.owner == *buyer {
+        return Err(MarketError::Unauthorized {
+            tokens_ids: vec![token.id],
+        });
+    }
+    if !token.is_transferable() {
+        return Err(MarketError::NonTransferable {
+            tokens_ids: vec![token.id],
+        });
+    }
+
+    Ok(())
+}
+
+/// Escrows `amount` via ICRC-2 `transfer_from` and records the offer.
+pub async fn make(arg: MakeOfferArgs) -> Result<u64, MarketError> {
+    let buyer = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let ledger = payment_ledger().ok_or(MarketError::NoPaymentLedger)?;
+    let now = ic_cdk::api::time();
+
+    if arg.amount == 0 {
+        return Err(MarketError::InvalidPrice);
+    }
+    if !is_unexpired(arg.expires_at, now) {
+        return Err(MarketError::OfferExpired);
+    }
+    let owner = match arg.token_id {
+        None => None,
+        Some(token_id) => {
+            let token = match TOKENS.with(|tokens| tokens.borrow().get(&token_id)) {
+                None => ic_cdk::trap("Invalid Id"),
+                Some(token) => token,
+            };
+            check_offerable(&token, &buyer)?;
+            Some(token.owner)
+        }
+    };
+
+    if let Err(msg) = collect(ledger, buyer.clone(), arg.amount).await {
+        return Err(MarketError::PaymentFailed { msg });
+    }
+
+    let offer = Offer {
+        id: next_offer_id(),
+        buyer,
+        token_id: arg.token_id,
+        amount: arg.amount,
+        ledger,
+        created_at: now,
+        expires_at: arg.expires_at,
+    };
+
+    // the token may have been burned or changed hands while the payment was
+    // in flight, the offer was made to the previous owner and the escrow goes
+    // straight back then
+    if let Some(token_id) = offer.token_id {
+        let checked = match TOKENS.with(|tokens| tokens.borrow().get(&token_id)) {
+            Some(token) if Some(&token.owner) == owner.as_ref() => {
+                check_offerable(&token, &offer.buyer)
+            }
+            _ => Err(MarketError::TokenMoved {
+                tokens_ids: vec![token_id],
+            }),
+        };
+        if let Err(err) = checked {
+            refund(offer).await;
+            return Err(err);
+        }
+    }
+
+    let id = offer.id;
+    save(offer);
+
+    Ok(id)
+}
+
+/// The buyer takes the escrow back, whether the offer expired or not.
+pub async fn cancel(id: u64) -> Result<(), MarketError> {
+    let offer = offer(&id).ok_or(MarketError::NoOffer)?;
+
+    if offer.buyer.owner() != ic_cdk::caller() {
+        return Err(MarketError::Unauthorized { tokens_ids: vec![] });
+    }
+
+    remove(&offer);
+    refund(offer).await;
+
+    Ok(())
+}
+
+/// The current owner of the token, as recorded in `TOKENS`, sells it for the
+/// escrowed amount. Collection-wide offers accept any token of the collection.
+pub async fn accept(arg: AcceptOfferArgs) -> Result<u128, MarketError> {
+    let seller = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let offer = offer(&arg.offer_id).ok_or(MarketError::NoOffer)?;
+    let now = ic_cdk::api::time();
+    let permitted_drift = CONFIG.with(|c| c.borrow().get().permitted_drift);
+
+    if !offer.is_active(now + permitted_drift) {
+        return Err(MarketError::OfferExpired);
+    }
+    if let Some(token_id) = offer.token_id {
+        if token_id != arg.token_id {
+            return Err(MarketError::NoOffer);
+        }
+    }
+
+    let token = match TOKENS.with(|tokens| tokens.borrow().get(&arg.token_id)) {
+        None => ic_cdk::trap("Invalid Id"),
+        Some(token) => token,
+    };
+    if token.owner != seller {
+        return Err(MarketError::Unauthorized {
+            tokens_ids: vec![arg.token_id],
+        });
+    }
+    if token.owner == offer.buyer {
+        return Err(MarketError::Unauthorized {
+            tokens_ids: vec![arg.token_id],
+        });
+    }
+    sellable(&token)?;
+
+    // off the book and the token held while the ledger fee is looked up
+    remove(&offer);
+    lock_token(arg.token_id, Lock::Sale);
+
+    let fee = ledger_fee(offer.ledger).await;
+    unlock_token(&arg.token_id);
+
+    let fee = match fee {
+        Ok(fee) => fee,
+        Err(msg) => {
+            save(offer);
+            return Err(MarketError::PaymentFailed { msg });
+        }
+    };
+
+    settle_sale(
+        offer.ledger,
+        fee,
+        arg.token_id,
+        seller,
+        offer.buyer,
+        offer.amount,
+    )
+    .await
+}
+
+/// Offers on a token only stand for the ownership they were made under.
+/// They are dropped as soon as the token moves and their escrow is queued
+/// for refund.
+pub fn invalidate_token_offers(token_id: &u128) {
+    let invalidated: Vec<Offer> = token_offer_ids(token_id).iter().filter_map(offer).collect();
+
+    if invalidated.is_empty() {
+        return;
+    }
+
+    for offer in invalidated {
+        remove(&offer);
+        queue_payout(Payout {
+            ledger: offer.ledger,
+            to: offer.buyer,
+            amount: offer.amount,
+            at: ic_cdk::api::time(),
+            reason: "Offer Invalidated".into(),
+            from_subaccount: None,
+        });
+    }
+
+    schedule_payouts();
+}
@@ -0,0 +1,91 @@
+use crate::{
+    audit::{log_event, AuditEvent},
+    state::{FROZEN_TOKENS, PAUSE},
+    types::PauseArgs,
+};
+use b3_utils::memory::types::{Bound, Storable};
+use candid::{CandidType, Decode, Encode};
+use serde_derive::{Deserialize, Serialize};
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Default)]
+pub struct PauseState {
+    pub transfers: bool,
+    pub approvals: bool,
+    pub mints: bool,
+    pub burns: bool,
+}
+
+impl Storable for PauseState {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+pub fn pause_state() -> PauseState {
+    PAUSE.with(|p| p.borrow().get().clone())
+}
+
+pub fn set_pause(arg: PauseArgs) -> PauseState {
+    let state = PAUSE.with(|p| {
+        let mut p = p.borrow_mut();
+        let mut state = p.get().clone();
+
+        if let Some(transfers) = arg.transfers {
+            state.transfers = transfers;
+        }
+        if let Some(approvals) = arg.approvals {
+            state.approvals = approvals;
+        }
+        if let Some(mints) = arg.mints {
+            state.mints = mints;
+        }
+        if let Some(burns) = arg.burns {
+            state.burns = burns;
+        }
+
+        p.set(state.clone()).unwrap();
+        state
+    });
+
+    log_event(AuditEvent::PauseUpdated(state.clone()));
+
+    state
+}
+
+pub fn is_frozen(id: &u128) -> bool {
+    FROZEN_TOKENS.with(|f| f.borrow().contains_key(id))
+}
+
+pub fn frozen_tokens() -> Vec<u128> {
+    FROZEN_TOKENS.with(|f| f.borrow().iter().map(|(id, _)| id).collect())
+}
+
+pub fn freeze_tokens(token_ids: Vec<u128>) {
+    let now = ic_cdk::api::time();
+
+    FROZEN_TOKENS.with(|f| {
+        let mut f = f.borrow_mut();
+        for id in token_ids.iter() {
+            f.insert(*id, now);
+        }
+    });
+
+    log_event(AuditEvent::TokensFrozen { token_ids });
+}
+
+pub fn unfreeze_tokens(token_ids: Vec<u128>) {
+    FROZEN_TOKENS.with(|f| {
+        let mut f = f.borrow_mut();
+        for id in token_ids.iter() {
+            f.remove(id);
+        }
+    });
+
+    log_event(AuditEvent::TokensUnfrozen { token_ids });
+}
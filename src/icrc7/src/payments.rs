@@ -0,0 +1,269 @@
+use crate::{
+    audit::{log_event, AuditEvent},
+    state::{CONFIG, PENDING_PAYOUTS},
+};
+use b3_utils::{
+    ledger::{ICRC1TransferArgs, ICRC2TransferFromArgs, ICRCAccount, ICRC1, ICRC2},
+    memory::types::{Bound, Storable},
+    Subaccount,
+};
+use candid::{CandidType, Decode, Encode, Nat, Principal};
+use serde_derive::{Deserialize, Serialize};
+use std::{cell::Cell, time::Duration};
+
+/// How long a scheduled retry waits when another run is still going.
+const RETRY_BUSY_DELAY: Duration = Duration::from_secs(60);
+
+thread_local! {
+    /// Set while `retry_payouts` works through the queue, so overlapping runs
+    /// do not pay the same payout twice.
+    static RETRYING: Cell<bool> = Cell::default();
+    /// Set while a retry timer is queued, so invalidations do not stack timers.
+    static RETRY_SCHEDULED: Cell<bool> = Cell::default();
+}
+
+/// A payout the ledger rejected, kept until an admin retries it.
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct Payout {
+    pub ledger: Principal,
+    pub to: ICRCAccount,
+    pub amount: u128,
+    pub at: u64,
+    pub reason: String,
+    /// Canister subaccount paying out, `None` for the default one.
+    pub from_subaccount: Option<Subaccount>,
+}
+
+impl Storable for Payout {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+pub fn payment_ledger() -> Option<Principal> {
+    CONFIG.with(|c| c.borrow().get().payment_ledger())
+}
+
+fn to_u128(amount: Nat) -> u128 {
+    amount.0.try_into().unwrap_or(u128::MAX)
+}
+
+pub async fn balance_of(ledger: Principal, account: &ICRCAccount) -> Result<u128, String> {
+    let (balance,): (Nat,) = ic_cdk::call(ledger, "icrc1_balance_of", (account,))
+        .await
+        .map_err(|(_, msg)| msg)?;
+
+    Ok(to_u128(balance))
+}
+
+pub async fn ledger_fee(ledger: Principal) -> Result<u128, String> {
+    ICRC1::new(ledger)
+        .fee()
+        .await
+        .map(to_u128)
+        .map_err(|e| e.to_string())
+}
+
+/// Pulls `amount` from `from` into the canister account, using the allowance
+/// `from` granted to this canister.
+pub async fn collect(ledger: Principal, from: ICRCAccount, amount: u128) -> Result<(), String> {
+    collect_into(ledger, from, None, amount).await
+}
+
+/// Like `collect`, into one of the canister subaccounts.
+pub async fn collect_into(
+    ledger: Principal,
+    from: ICRCAccount,
+    subaccount: Option<Subaccount>,
+    amount: u128,
+) -> Result<(), String> {
+    transfer_from(
+        ledger,
+        from,
+        ICRCAccount::new(ic_cdk::id(), subaccount),
+        amount,
+    )
+    .await
+}
+
+/// Moves `amount` from `from` straight to `to`, using the allowance `from`
+/// granted to this canister.
+pub async fn transfer_from(
+    ledger: Principal,
+    from: ICRCAccount,
+    to: ICRCAccount,
+    amount: u128,
+) -> Result<(), String> {
+    let args = ICRC2TransferFromArgs {
+        spender_subaccount: None,
+        from,
+        to,
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    };
+
+    match ICRC2::new(ledger).transfer_from(args).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Sends `amount` out of the canister account. The ledger fee is taken from
+/// `amount`, shares smaller than the fee are kept by the canister and noted
+/// in the audit log. Failed payouts are queued in `PENDING_PAYOUTS`.
+pub async fn pay_out(ledger: Principal, fee: u128, to: ICRCAccount, amount: u128) {
+    pay_out_from(ledger, fee, None, to, amount).await
+}
+
+/// `pay_out` from a canister subaccount, e.g. a fraction vault.
+pub async fn pay_out_from(
+    ledger: Principal,
+    fee: u128,
+    from_subaccount: Option<Subaccount>,
+    to: ICRCAccount,
+    amount: u128,
+) {
+    if amount <= fee {
+        log_event(AuditEvent::PayoutBelowFee {
+            ledger,
+            to,
+            amount,
+            fee,
+        });
+        return;
+    }
+
+    if let Err(reason) = transfer(ledger, from_subaccount.clone(), to.clone(), amount - fee).await {
+        queue_payout(Payout {
+            ledger,
+            to,
+            amount,
+            at: ic_cdk::api::time(),
+            reason,
+            from_subaccount,
+        });
+    }
+}
+
+/// Sends exactly `amount` out of a canister subaccount, the fee is paid on top.
+pub async fn transfer(
+    ledger: Principal,
+    from_subaccount: Option<Subaccount>,
+    to: ICRCAccount,
+    amount: u128,
+) -> Result<(), String> {
+    let args = ICRC1TransferArgs {
+        to,
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+        created_at_time: None,
+        from_subaccount,
+    };
+
+    match ICRC1::new(ledger).transfer(args).await {
+        Ok(Ok(_)) => Ok(()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+pub fn queue_payout(payout: Payout) {
+    PENDING_PAYOUTS.with(|p| {
+        let mut p = p.borrow_mut();
+        let index = p.last_key_value().map(|(k, _)| k + 1).unwrap_or_default();
+        p.insert(index, payout);
+    });
+}
+
+pub fn pending_payouts() -> Vec<(u64, Payout)> {
+    PENDING_PAYOUTS.with(|p| p.borrow().iter().collect())
+}
+
+/// Clears `RETRYING` when a run ends, trapped callbacks included.
+struct RetryGuard;
+
+impl Drop for RetryGuard {
+    fn drop(&mut self) {
+        RETRYING.with(|r| r.set(false));
+    }
+}
+
+/// Retries every queued payout and returns how many are still pending.
+/// Returns right away while another run is in progress.
+pub async fn retry_payouts() -> u64 {
+    if RETRYING.with(|r| r.replace(true)) {
+        return PENDING_PAYOUTS.with(|p| p.borrow().len());
+    }
+    let _guard = RetryGuard;
+
+    // each entry stays queued until its transfer has an outcome
+    for (index, payout) in pending_payouts() {
+        let result = match ledger_fee(payout.ledger).await {
+            Ok(fee) if payout.amount <= fee => {
+                log_event(AuditEvent::PayoutBelowFee {
+                    ledger: payout.ledger,
+                    to: payout.to.clone(),
+                    amount: payout.amount,
+                    fee,
+                });
+                Ok(())
+            }
+            Ok(fee) => {
+                transfer(
+                    payout.ledger,
+                    payout.from_subaccount.clone(),
+                    payout.to.clone(),
+                    payout.amount - fee,
+                )
+                .await
+            }
+            Err(reason) => Err(reason),
+        };
+
+        PENDING_PAYOUTS.with(|p| {
+            let mut p = p.borrow_mut();
+            match result {
+                Ok(()) => p.remove(&index),
+                Err(reason) => p.insert(index, Payout { reason, ..payout }),
+            };
+        });
+    }
+
+    PENDING_PAYOUTS.with(|p| p.borrow().len())
+}
+
+/// Works through the queue in the next round, unless a retry is already
+/// queued. A run still in progress is waited out so entries queued after it
+/// started are not left behind.
+pub fn schedule_payouts() {
+    schedule_payouts_in(Duration::ZERO);
+}
+
+fn schedule_payouts_in(delay: Duration) {
+    if RETRY_SCHEDULED.with(|s| s.replace(true)) {
+        return;
+    }
+
+    ic_cdk_timers::set_timer(delay, || {
+        RETRY_SCHEDULED.with(|s| s.set(false));
+
+        if RETRYING.with(|r| r.get()) {
+            schedule_payouts_in(RETRY_BUSY_DELAY);
+            return;
+        }
+
+        ic_cdk::spawn(async {
+            retry_payouts().await;
+        })
+    });
+}
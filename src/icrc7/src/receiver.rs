@@ -0,0 +1,64 @@
+use crate::{
+    errors::TransferAndCallError,
+    locks::{lock_token, unlock_token, Lock},
+    market::return_token,
+    state::TOKENS,
+};
+use b3_utils::ledger::ICRCAccount;
+use candid::Principal;
+
+/// Implemented by receiving canisters as
+/// `(from : ICRCAccount, token_ids : vec nat, data : blob) -> (variant { Ok; Err : text })`.
+const ON_RECEIVED: &str = "icrc7_on_received";
+
+/// Canister ids are opaque principals, the only kind ending in `0x01`.
+pub fn is_canister(principal: &Principal) -> bool {
+    principal.as_slice().last() == Some(&1)
+}
+
+/// Current owners of the tokens, where they go back to on a rejection.
+pub fn owners(token_ids: &[u128]) -> Vec<(u128, ICRCAccount)> {
+    TOKENS.with(|tokens| {
+        let tokens = tokens.borrow();
+        token_ids
+            .iter()
+            .filter_map(|id| tokens.get(id).map(|token| (*id, token.owner)))
+            .collect()
+    })
+}
+
+/// Calls the recipient's hook with the tokens locked, so that nothing, the
+/// recipient included, moves them before it returns. Rejected tokens, or ones
+/// the hook could not be called for, are sent back to their previous owners.
+pub async fn notify(
+    from: ICRCAccount,
+    to: ICRCAccount,
+    owners: Vec<(u128, ICRCAccount)>,
+    data: Vec<u8>,
+) -> Result<(), TransferAndCallError> {
+    let token_ids: Vec<u128> = owners.iter().map(|(id, _)| *id).collect();
+    for id in token_ids.iter() {
+        lock_token(*id, Lock::Receiving);
+    }
+
+    let result: Result<(Result<(), String>,), _> =
+        ic_cdk::call(to.owner(), ON_RECEIVED, (from, token_ids.clone(), data)).await;
+
+    for id in token_ids.iter() {
+        unlock_token(id);
+    }
+
+    let error = match result {
+        Ok((Ok(()),)) => return Ok(()),
+        Ok((Err(reason),)) => TransferAndCallError::Rejected { reason },
+        Err((_, msg)) => TransferAndCallError::CallFailed { msg },
+    };
+
+    for (id, owner) in owners {
+        // a token frozen while the hook ran stays with the recipient, a
+        // transfer pause does not keep it there
+        let _ = return_token(id, &to, owner);
+    }
+
+    Err(error)
+}
@@ -0,0 +1,240 @@
+use crate::{
+    errors::RentalError,
+    locks::{lock_token, unlock_token, Lock},
+    pause::is_frozen,
+    payments::{collect, ledger_fee, pay_out, payment_ledger},
+    state::{Token, RENTAL_TERMS, TOKENS},
+    types::{RentArgs, SetRentalTermsArgs, SetUserArgs},
+};
+use b3_utils::{
+    ledger::ICRCAccount,
+    memory::types::{Bound, Storable},
+    Subaccount,
+};
+use candid::{CandidType, Decode, Encode};
+use serde_derive::{Deserialize, Serialize};
+
+/// Usage right on a token, separate from ownership. It lapses at
+/// `expires_at` and is cleared whenever the token moves.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Rental {
+    pub user: ICRCAccount,
+    pub expires_at: u64,
+}
+
+impl Rental {
+    pub fn is_active(&self, current_time: u64) -> bool {
+        current_time < self.expires_at
+    }
+}
+
+/// Price list for renting a token out on the payment ledger.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct RentalTerms {
+    pub price_per_period: u128,
+    /// Length of one period in nanoseconds.
+    pub period: u64,
+    pub max_periods: u64,
+}
+
+impl Storable for RentalTerms {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+fn get_token(token_id: &u128) -> Token {
+    match TOKENS.with(|tokens| tokens.borrow().get(token_id)) {
+        None => ic_cdk::trap("Invalid Id"),
+        Some(token) => token,
+    }
+}
+
+/// The renter while a rental is running, the owner otherwise.
+pub fn user_of(token_id: &u128) -> ICRCAccount {
+    let token = get_token(token_id);
+
+    match token.rental(ic_cdk::api::time()) {
+        Some(rental) => rental.user,
+        None => token.owner,
+    }
+}
+
+pub fn rental(token_id: &u128) -> Option<Rental> {
+    get_token(token_id).rental(ic_cdk::api::time())
+}
+
+pub fn rental_terms(token_id: &u128) -> Option<RentalTerms> {
+    RENTAL_TERMS.with(|r| r.borrow().get(token_id))
+}
+
+/// Rental terms die with the ownership they were set under.
+pub fn remove_rental_terms(token_id: &u128) {
+    RENTAL_TERMS.with(|r| r.borrow_mut().remove(token_id));
+}
+
+fn check_owner(token: &Token, from_subaccount: Option<Subaccount>) -> Result<(), RentalError> {
+    if token.owner != ICRCAccount::new(ic_cdk::caller(), from_subaccount) {
+        return Err(RentalError::Unauthorized {
+            tokens_ids: vec![token.id],
+        });
+    }
+    if is_frozen(&token.id) {
+        return Err(RentalError::TokenFrozen {
+            tokens_ids: vec![token.id],
+        });
+    }
+    Ok(())
+}
+
+fn check_available(token: &Token, user: &ICRCAccount, now: u64) -> Result<(), RentalError> {
+    match token.rental(now) {
+        Some(rental) if rental.user != *user => Err(RentalError::RentalActive {
+            expires_at: rental.expires_at,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// The owner grants usage for free, replacing a lapsed rental or extending
+/// the current user's. A running rental can only be extended, never cut
+/// short, so a paid rental is not taken back.
+pub fn set_user(arg: SetUserArgs) -> Result<(), RentalError> {
+    let mut token = get_token(&arg.token_id);
+    let now = ic_cdk::api::time();
+
+    check_owner(&token, arg.from_subaccount)?;
+    if arg.expires_at <= now || arg.user == token.owner {
+        return Err(RentalError::InvalidRental);
+    }
+    check_available(&token, &arg.user, now)?;
+    if let Some(rental) = token.rental(now) {
+        if arg.expires_at < rental.expires_at {
+            return Err(RentalError::RentalActive {
+                expires_at: rental.expires_at,
+            });
+        }
+    }
+
+    token.user = Some(Rental {
+        user: arg.user,
+        expires_at: arg.expires_at,
+    });
+    TOKENS.with(|tokens| tokens.borrow_mut().insert(token.id, token));
+
+    Ok(())
+}
+
+/// `None` takes the token off the rental market.
+pub fn set_rental_terms(arg: SetRentalTermsArgs) -> Result<(), RentalError> {
+    let token = get_token(&arg.token_id);
+
+    check_owner(&token, arg.from_subaccount)?;
+
+    match arg.terms {
+        None => remove_rental_terms(&arg.token_id),
+        Some(terms) => {
+            if terms.price_per_period == 0 || terms.period == 0 || terms.max_periods == 0 {
+                return Err(RentalError::InvalidRental);
+            }
+            payment_ledger().ok_or(RentalError::NoPaymentLedger)?;
+            RENTAL_TERMS.with(|r| r.borrow_mut().insert(arg.token_id, terms));
+        }
+    }
+
+    Ok(())
+}
+
+/// Collects the price via ICRC-2 `transfer_from` and becomes the user for
+/// `periods` periods, the owner is paid out afterwards. Renting again while
+/// the own rental runs extends it. Returns the new expiry.
+pub async fn rent(arg: RentArgs) -> Result<u64, RentalError> {
+    let renter = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let ledger = payment_ledger().ok_or(RentalError::NoPaymentLedger)?;
+    let terms = rental_terms(&arg.token_id).ok_or(RentalError::NotForRent)?;
+    let token = get_token(&arg.token_id);
+
+    if arg.periods == 0 || arg.periods > terms.max_periods || token.owner == renter {
+        return Err(RentalError::InvalidRental);
+    }
+    let price = terms.price_per_period.saturating_mul(arg.periods as u128);
+    if price != arg.price {
+        return Err(RentalError::PriceChanged { price });
+    }
+    check_rentable(&token, &renter, &terms)?;
+
+    let fee = ledger_fee(ledger)
+        .await
+        .map_err(|msg| RentalError::PaymentFailed { msg })?;
+
+    // the owner or the terms may have changed while waiting for the fee
+    let token = get_token(&arg.token_id);
+    check_rentable(&token, &renter, &terms)?;
+
+    // the owner cannot move the token away while the payment is in flight
+    if !lock_token(token.id, Lock::Rental) {
+        return Err(RentalError::TokenLocked {
+            tokens_ids: vec![token.id],
+        });
+    }
+    let paid = collect(ledger, renter.clone(), price).await;
+    unlock_token(&token.id);
+
+    if let Err(msg) = paid {
+        return Err(RentalError::PaymentFailed { msg });
+    }
+
+    // the owner may have changed the terms or handed the token to another
+    // user while the payment was in flight, the renter is refunded then
+    let mut current = get_token(&arg.token_id);
+    if current.owner != token.owner {
+        pay_out(ledger, fee, renter, price).await;
+        return Err(RentalError::Unauthorized {
+            tokens_ids: vec![token.id],
+        });
+    }
+    if let Err(e) = check_rentable(&current, &renter, &terms) {
+        pay_out(ledger, fee, renter, price).await;
+        return Err(e);
+    }
+
+    let now = ic_cdk::api::time();
+    let start = match current.rental(now) {
+        Some(rental) if rental.user == renter => rental.expires_at,
+        _ => now,
+    };
+    let expires_at = start.saturating_add(terms.period.saturating_mul(arg.periods));
+
+    current.user = Some(Rental {
+        user: renter,
+        expires_at,
+    });
+    TOKENS.with(|tokens| tokens.borrow_mut().insert(current.id, current));
+
+    pay_out(ledger, fee, token.owner, price).await;
+
+    Ok(expires_at)
+}
+
+/// The token is still offered on `terms` and free for `renter`.
+fn check_rentable(
+    token: &Token,
+    renter: &ICRCAccount,
+    terms: &RentalTerms,
+) -> Result<(), RentalError> {
+    if rental_terms(&token.id).as_ref() != Some(terms) {
+        return Err(RentalError::NotForRent);
+    }
+    if is_frozen(&token.id) {
+        return Err(RentalError::TokenFrozen {
+            tokens_ids: vec![token.id],
+        });
+    }
+    check_available(token, renter, ic_cdk::api::time())
+}
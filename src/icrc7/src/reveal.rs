@@ -0,0 +1,270 @@
+use crate::{
+    audit::{log_event, AuditEvent},
+    errors::RevealError,
+    state::{Token, HIDDEN_TOKENS, REVEAL, REVEAL_METADATA},
+    types::CommitRevealArgs,
+};
+use b3_utils::{
+    ledger::raw_sha256,
+    memory::types::{Bound, Storable},
+};
+use candid::{CandidType, Decode, Encode};
+use serde_bytes::ByteBuf;
+use serde_derive::{Deserialize, Serialize};
+use std::cell::Cell;
+
+thread_local! {
+    /// Set while the reveal waits for randomness, the checked metadata must
+    /// not change under it.
+    static REVEALING: Cell<bool> = Cell::default();
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct TokenMetadata {
+    pub name: String,
+    pub description: Option<String>,
+    pub image: Option<Vec<u8>>,
+}
+
+impl Storable for TokenMetadata {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl TokenMetadata {
+    /// sha256 over the name, description and image, each prefixed with its
+    /// length as a big-endian u64, a missing field counts as empty.
+    pub fn hash(&self) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for field in [
+            self.name.as_bytes(),
+            self.description.as_deref().unwrap_or_default().as_bytes(),
+            self.image.as_deref().unwrap_or_default(),
+        ] {
+            bytes.extend_from_slice(&(field.len() as u64).to_be_bytes());
+            bytes.extend_from_slice(field);
+        }
+        raw_sha256(&bytes)
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Default)]
+pub struct RevealState {
+    /// Shown for hidden tokens until the reveal.
+    pub placeholder: Option<TokenMetadata>,
+    /// sha256 over the concatenated hashes of the final metadata, in order.
+    pub commitment: Option<ByteBuf>,
+    /// Tokens minted hidden, and metadata entries the reveal expects.
+    pub size: u64,
+    pub minted: u64,
+    pub uploaded: u64,
+    /// Hidden token `n` in mint order shows entry `(n + offset) % size`.
+    pub offset: Option<u64>,
+    pub revealed_at: Option<u64>,
+}
+
+impl Storable for RevealState {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl RevealState {
+    fn is_hiding(&self) -> bool {
+        self.commitment.is_some() && self.offset.is_none()
+    }
+}
+
+pub fn reveal_state() -> RevealState {
+    REVEAL.with(|r| r.borrow().get().clone())
+}
+
+fn update_reveal<R>(f: impl FnOnce(&mut RevealState) -> R) -> R {
+    REVEAL.with(|r| {
+        let mut r = r.borrow_mut();
+        let mut state = r.get().clone();
+        let result = f(&mut state);
+        r.set(state).unwrap();
+        result
+    })
+}
+
+/// Published at drop start, before anything is minted hidden.
+pub fn commit(arg: CommitRevealArgs) -> Result<(), RevealError> {
+    if reveal_state().commitment.is_some() {
+        return Err(RevealError::AlreadyCommitted);
+    }
+    if arg.commitment.len() != 32 || arg.size == 0 {
+        return Err(RevealError::InvalidCommitment);
+    }
+
+    update_reveal(|state| {
+        *state = RevealState {
+            placeholder: Some(arg.placeholder),
+            commitment: Some(arg.commitment.clone()),
+            size: arg.size,
+            ..Default::default()
+        }
+    });
+    log_event(AuditEvent::RevealCommitted {
+        commitment: arg.commitment,
+        size: arg.size,
+    });
+
+    Ok(())
+}
+
+/// Every hidden token has been minted, nothing more can be until the reveal.
+pub fn is_sold_out() -> bool {
+    let state = reveal_state();
+    state.is_hiding() && state.minted >= state.size
+}
+
+/// Takes the next hidden slot for a freshly minted token and returns the
+/// placeholder it carries, `None` outside of a hidden drop.
+pub fn hide(token_id: u128) -> Option<TokenMetadata> {
+    let state = reveal_state();
+    if !state.is_hiding() || state.minted >= state.size {
+        return None;
+    }
+
+    HIDDEN_TOKENS.with(|h| h.borrow_mut().insert(token_id, state.minted));
+    update_reveal(|state| state.minted += 1);
+
+    state.placeholder
+}
+
+/// Writes final metadata in committed order from entry `start`, by default
+/// right after the last uploaded one. Uploaded entries are overwritten, so a
+/// wrong upload can be fixed until the reveal starts. Returns the number
+/// uploaded.
+pub fn upload(entries: Vec<TokenMetadata>, start: Option<u64>) -> Result<u64, RevealError> {
+    let state = reveal_state();
+    if !state.is_hiding() {
+        return Err(RevealError::NotCommitted);
+    }
+    if REVEALING.with(|r| r.get()) {
+        return Err(RevealError::Revealing);
+    }
+    let start = start.unwrap_or(state.uploaded);
+    if start > state.uploaded {
+        return Err(RevealError::InvalidStart {
+            uploaded: state.uploaded,
+        });
+    }
+    if start + entries.len() as u64 > state.size {
+        return Err(RevealError::TooManyEntries { size: state.size });
+    }
+
+    let uploaded = REVEAL_METADATA.with(|m| {
+        let mut m = m.borrow_mut();
+        for (n, entry) in (start..).zip(entries) {
+            m.insert(n, entry);
+        }
+        m.len()
+    });
+    update_reveal(|state| state.uploaded = uploaded);
+
+    Ok(uploaded)
+}
+
+/// Checks the uploaded metadata against the commitment and shuffles it onto
+/// the hidden tokens with an offset from `raw_rand`, once all of them are
+/// minted.
+pub async fn reveal() -> Result<u64, RevealError> {
+    let state = reveal_state();
+    let commitment = match (&state.commitment, state.offset) {
+        (None, _) => return Err(RevealError::NotCommitted),
+        (Some(_), Some(_)) => return Err(RevealError::AlreadyRevealed),
+        (Some(commitment), None) => commitment.clone(),
+    };
+    if state.minted < state.size {
+        return Err(RevealError::NotSoldOut {
+            remaining: state.size - state.minted,
+        });
+    }
+    if state.uploaded < state.size {
+        return Err(RevealError::MissingEntries {
+            uploaded: state.uploaded,
+        });
+    }
+
+    if REVEALING.with(|r| r.replace(true)) {
+        return Err(RevealError::Revealing);
+    }
+
+    let result = shuffle(&state, &commitment).await;
+
+    REVEALING.with(|r| r.set(false));
+
+    result
+}
+
+/// Checks the commitment and draws the offset, with uploads held off by
+/// `REVEALING`.
+async fn shuffle(state: &RevealState, commitment: &ByteBuf) -> Result<u64, RevealError> {
+    let hashes: Vec<u8> = REVEAL_METADATA.with(|m| {
+        m.borrow()
+            .iter()
+            .flat_map(|(_, entry)| entry.hash())
+            .collect()
+    });
+    if raw_sha256(&hashes) != commitment.as_slice() {
+        return Err(RevealError::CommitmentMismatch);
+    }
+
+    let (random,) = ic_cdk::api::management_canister::main::raw_rand()
+        .await
+        .map_err(|(_, msg)| RevealError::RandomnessFailed { msg })?;
+
+    // a second reveal may have finished while the randomness was in flight
+    if reveal_state().offset.is_some() {
+        return Err(RevealError::AlreadyRevealed);
+    }
+
+    let mut seed = [0; 8];
+    seed.copy_from_slice(&random[..8]);
+    let offset = u64::from_be_bytes(seed) % state.size;
+
+    update_reveal(|state| {
+        state.offset = Some(offset);
+        state.revealed_at = Some(ic_cdk::api::time());
+    });
+    log_event(AuditEvent::Revealed { offset });
+
+    Ok(offset)
+}
+
+/// The token as it is shown, with its final metadata once revealed.
+pub fn revealed(mut token: Token) -> Token {
+    let state = reveal_state();
+    let offset = match state.offset {
+        None => return token,
+        Some(offset) => offset,
+    };
+
+    let entry = HIDDEN_TOKENS
+        .with(|h| h.borrow().get(&token.id))
+        .and_then(|n| REVEAL_METADATA.with(|m| m.borrow().get(&((n + offset) % state.size))));
+
+    if let Some(entry) = entry {
+        token.name = entry.name;
+        token.description = entry.description;
+        token.image = entry.image;
+    }
+
+    token
+}
@@ -0,0 +1,150 @@
+use crate::{
+    audit::{log_event, AuditEvent},
+    state::{CONFIG, ROLES},
+};
+use b3_utils::memory::types::{Bound, Storable};
+use candid::{CandidType, Decode, Encode, Principal};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(
+    CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash,
+)]
+pub enum Role {
+    Admin,
+    Minter,
+    MetadataUpdater,
+    Pauser,
+    /// Receives token events, only for canisters trusted to reply to them.
+    Subscriber,
+    /// Fractionalizes tokens on the collection's cycles.
+    Fractionalizer,
+}
+
+impl Role {
+    pub fn all() -> Vec<Role> {
+        vec![
+            Role::Admin,
+            Role::Minter,
+            Role::MetadataUpdater,
+            Role::Pauser,
+            Role::Subscriber,
+            Role::Fractionalizer,
+        ]
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Default)]
+pub struct RoleRegistry {
+    pub members: BTreeMap<Principal, BTreeSet<Role>>,
+}
+
+impl Storable for RoleRegistry {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl RoleRegistry {
+    pub fn has_role(&self, principal: &Principal, role: Role) -> bool {
+        match self.members.get(principal) {
+            None => false,
+            Some(roles) => roles.contains(&role),
+        }
+    }
+
+    pub fn holders(&self, role: Role) -> Vec<Principal> {
+        self.members
+            .iter()
+            .filter(|(_, roles)| roles.contains(&role))
+            .map(|(principal, _)| *principal)
+            .collect()
+    }
+}
+
+pub fn has_role(principal: &Principal, role: Role) -> bool {
+    ROLES.with(|r| r.borrow().get().has_role(principal, role))
+}
+
+/// Traps unless the caller holds `role`.
+pub fn guard_role(role: Role) {
+    if !has_role(&ic_cdk::caller(), role) {
+        ic_cdk::trap("Unauthorized Caller")
+    }
+}
+
+pub fn list_roles() -> Vec<(Principal, Vec<Role>)> {
+    ROLES.with(|r| {
+        r.borrow()
+            .get()
+            .members
+            .iter()
+            .map(|(principal, roles)| (*principal, roles.iter().cloned().collect()))
+            .collect()
+    })
+}
+
+pub fn grant_role(principal: Principal, role: Role) {
+    ROLES.with(|r| {
+        let mut r = r.borrow_mut();
+        let mut members = r.get().members.clone();
+
+        if !members.entry(principal).or_default().insert(role) {
+            ic_cdk::trap("Role Already Granted")
+        }
+
+        r.set(RoleRegistry { members }).unwrap();
+    });
+
+    log_event(AuditEvent::RoleGranted { principal, role });
+}
+
+pub fn revoke_role(principal: Principal, role: Role) {
+    ROLES.with(|r| {
+        let mut r = r.borrow_mut();
+        let registry = r.get();
+
+        if !registry.has_role(&principal, role) {
+            ic_cdk::trap("Role Not Granted")
+        }
+        if role == Role::Admin && registry.holders(Role::Admin).len() == 1 {
+            ic_cdk::trap("Last Admin")
+        }
+
+        let mut members = registry.members.clone();
+        if let Some(roles) = members.get_mut(&principal) {
+            roles.remove(&role);
+            if roles.is_empty() {
+                members.remove(&principal);
+            }
+        }
+
+        r.set(RoleRegistry { members }).unwrap();
+    });
+
+    log_event(AuditEvent::RoleRevoked { principal, role });
+}
+
+/// Grants every role to `principal`, used to bootstrap a fresh collection.
+pub fn seed_roles(principal: Principal) {
+    for role in Role::all() {
+        if !has_role(&principal, role) {
+            grant_role(principal, role);
+        }
+    }
+}
+
+/// Collections deployed before the roles registry only know the single
+/// `minting_authority`, which becomes the first admin on upgrade.
+pub fn migrate_minting_authority() {
+    if ROLES.with(|r| r.borrow().get().members.is_empty()) {
+        let minting_authority = CONFIG.with(|c| c.borrow().get().minting_authority);
+        seed_roles(minting_authority);
+    }
+}
@@ -0,0 +1,631 @@
+use crate::{
+    audit::{log_event, AuditEvent},
+    errors::MintError,
+    pause::pause_state,
+    payments::{collect, ledger_fee, pay_out, payment_ledger},
+    reveal,
+    state::{
+        account_key, get_total_supply, insert_minted, AccountKey, Token, ALLOWLIST, CONFIG,
+        MINTED_IN_PHASE, NEXT_TOKEN_ID, SALE, SALE_LOG, TOKENS,
+    },
+    types::{AddMintPhaseArgs, SelfMintArgs},
+};
+use b3_utils::{
+    ledger::{raw_sha256, ICRCAccount},
+    memory::types::{Bound, Storable},
+};
+use candid::{CandidType, Decode, Encode, Principal};
+use serde_bytes::ByteBuf;
+use serde_derive::{Deserialize, Serialize};
+use std::{cell::RefCell, time::Duration};
+
+thread_local! {
+    /// Timer of the next drop switch, replaced whenever the drop changes.
+    static DROP_TIMER: RefCell<Option<ic_cdk_timers::TimerId>> = RefCell::default();
+}
+
+/// Who may mint during a phase.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum Eligibility {
+    /// Anyone.
+    Public,
+    /// Principals uploaded to the phase allowlist.
+    Allowlist,
+    /// Principals proven against a Merkle root. Leaves are the sha256 of the
+    /// principal bytes, pairs are hashed in sorted order.
+    MerkleRoot(ByteBuf),
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct MintPhase {
+    pub id: u32,
+    pub name: String,
+    pub eligibility: Eligibility,
+    /// Tokens a principal may mint during the phase, `None` for no limit.
+    pub per_account_limit: Option<u32>,
+    /// `None` mints for free.
+    pub price: Option<PriceSchedule>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct PriceTier {
+    /// The tier applies while the total supply is below this.
+    pub until_supply: u128,
+    pub price: u128,
+}
+
+/// Mint price on the payment ledger.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum PriceSchedule {
+    Fixed(u128),
+    /// Tiers ordered by `until_supply`, the last one also applies past its
+    /// bound.
+    Tiered(Vec<PriceTier>),
+    /// Falls linearly from `start_price` to `end_price` over `duration`
+    /// nanoseconds from `start_at`.
+    Dutch {
+        start_price: u128,
+        end_price: u128,
+        start_at: u64,
+        duration: u64,
+    },
+}
+
+impl PriceSchedule {
+    pub fn price(&self, now: u64, supply: u128) -> u128 {
+        match self {
+            PriceSchedule::Fixed(price) => *price,
+            PriceSchedule::Tiered(tiers) => tiers
+                .iter()
+                .find(|tier| supply < tier.until_supply)
+                .or(tiers.last())
+                .map_or(0, |tier| tier.price),
+            PriceSchedule::Dutch {
+                start_price,
+                end_price,
+                start_at,
+                duration,
+            } => {
+                let elapsed =
+                    (now.clamp(*start_at, start_at.saturating_add(*duration)) - start_at) as u128;
+                let duration = *duration as u128;
+                let drop = start_price - end_price;
+
+                start_price - drop / duration * elapsed - drop % duration * elapsed / duration
+            }
+        }
+    }
+
+    fn is_valid(&self) -> bool {
+        match self {
+            PriceSchedule::Fixed(_) => true,
+            PriceSchedule::Tiered(tiers) => {
+                !tiers.is_empty()
+                    && tiers
+                        .windows(2)
+                        .all(|pair| pair[0].until_supply < pair[1].until_supply)
+            }
+            PriceSchedule::Dutch {
+                start_price,
+                end_price,
+                start_at,
+                duration,
+            } => {
+                *duration > 0
+                    && start_price >= end_price
+                    && start_at.checked_add(*duration).is_some()
+            }
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub enum SaleOutcome {
+    Minted {
+        token_id: u128,
+    },
+    /// The payment went through but the mint did not, the price was refunded.
+    Refunded {
+        reason: String,
+    },
+    PaymentFailed {
+        msg: String,
+    },
+}
+
+/// One self-mint attempt, free or paid.
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct SaleLog {
+    pub id: u64,
+    pub at: u64,
+    pub phase_id: u32,
+    pub buyer: ICRCAccount,
+    pub price: u128,
+    pub outcome: SaleOutcome,
+}
+
+impl Storable for SaleLog {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+/// A phase of a drop, open from `start_at` until `end_at`.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct DropWindow {
+    pub phase_id: u32,
+    pub start_at: u64,
+    pub end_at: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone)]
+pub struct DropStatus {
+    /// `None` while the drop is closed.
+    pub phase: Option<MintPhase>,
+    /// `None` without a supply cap.
+    pub remaining_supply: Option<u128>,
+    /// When the drop switches phase next, `None` after its last window.
+    pub next_switch_at: Option<u64>,
+    /// Nanoseconds until `next_switch_at`.
+    pub countdown: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Default)]
+pub struct SaleState {
+    pub phases: Vec<MintPhase>,
+    /// Phase currently open for self-minting.
+    pub active: Option<u32>,
+    pub next_phase_id: u32,
+    /// Receives the proceeds of paid mints.
+    pub treasury: Option<ICRCAccount>,
+    /// Ordered windows switching the active phase on timers, minting is
+    /// closed outside of them.
+    pub drop: Option<Vec<DropWindow>>,
+}
+
+impl Storable for SaleState {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+/// Key of allowlist and per-phase mint count entries.
+pub type PhaseKey = (u32, AccountKey);
+
+fn phase_key(phase_id: u32, principal: Principal) -> PhaseKey {
+    (phase_id, account_key(&ICRCAccount::from(principal)))
+}
+
+pub fn sale_state() -> SaleState {
+    SALE.with(|s| s.borrow().get().clone())
+}
+
+fn update_sale<R>(f: impl FnOnce(&mut SaleState) -> R) -> R {
+    SALE.with(|s| {
+        let mut s = s.borrow_mut();
+        let mut state = s.get().clone();
+        let result = f(&mut state);
+        s.set(state).unwrap();
+        result
+    })
+}
+
+pub fn phase(phase_id: u32) -> Option<MintPhase> {
+    sale_state()
+        .phases
+        .into_iter()
+        .find(|phase| phase.id == phase_id)
+}
+
+pub fn active_phase() -> Option<MintPhase> {
+    sale_state().active.and_then(phase)
+}
+
+pub fn add_phase(arg: AddMintPhaseArgs) -> Result<u32, MintError> {
+    if let Eligibility::MerkleRoot(root) = &arg.eligibility {
+        if root.len() != 32 {
+            return Err(MintError::InvalidPhase);
+        }
+    }
+    if let Some(price) = &arg.price {
+        if !price.is_valid() {
+            return Err(MintError::InvalidPhase);
+        }
+        payment_ledger().ok_or(MintError::NoPaymentLedger)?;
+    }
+
+    let id = update_sale(|state| {
+        let id = state.next_phase_id;
+        state.next_phase_id += 1;
+        state.phases.push(MintPhase {
+            id,
+            name: arg.name,
+            eligibility: arg.eligibility,
+            per_account_limit: arg.per_account_limit,
+            price: arg.price,
+        });
+        id
+    });
+    log_event(AuditEvent::MintPhaseAdded { phase_id: id });
+
+    Ok(id)
+}
+
+/// `None` closes self-minting.
+pub fn set_active_phase(phase_id: Option<u32>) -> Result<(), MintError> {
+    if sale_state().drop.is_some() {
+        return Err(MintError::DropScheduled);
+    }
+    if let Some(id) = phase_id {
+        phase(id).ok_or(MintError::InvalidPhase)?;
+    }
+
+    update_sale(|state| state.active = phase_id);
+    log_event(AuditEvent::ActivePhaseSet { phase_id });
+
+    Ok(())
+}
+
+fn scheduled_phase(windows: &[DropWindow], now: u64) -> Option<u32> {
+    windows
+        .iter()
+        .find(|window| window.start_at <= now && now < window.end_at)
+        .map(|window| window.phase_id)
+}
+
+fn next_switch(windows: &[DropWindow], now: u64) -> Option<u64> {
+    windows
+        .iter()
+        .flat_map(|window| [window.start_at, window.end_at])
+        .filter(|at| *at > now)
+        .min()
+}
+
+/// Replaces the drop, windows have to be ordered and may not overlap.
+/// `None` hands the active phase back to `set_active_phase`.
+pub fn set_drop(windows: Option<Vec<DropWindow>>) -> Result<(), MintError> {
+    if let Some(windows) = &windows {
+        let state = sale_state();
+        let valid = windows.iter().all(|window| {
+            window.start_at < window.end_at
+                && state.phases.iter().any(|phase| phase.id == window.phase_id)
+        }) && windows
+            .windows(2)
+            .all(|pair| pair[0].end_at <= pair[1].start_at);
+
+        if !valid {
+            return Err(MintError::InvalidPhase);
+        }
+    }
+
+    update_sale(|state| state.drop = windows.clone());
+    log_event(AuditEvent::DropSet { windows });
+    schedule_drop();
+
+    Ok(())
+}
+
+/// Opens the phase whose window covers the current time, or closes minting
+/// between and after the windows.
+fn sync_drop() {
+    let state = sale_state();
+    let windows = match state.drop {
+        None => return,
+        Some(windows) => windows,
+    };
+
+    let phase_id = scheduled_phase(&windows, ic_cdk::api::time());
+    if state.active != phase_id {
+        update_sale(|state| state.active = phase_id);
+        log_event(AuditEvent::ActivePhaseSet { phase_id });
+    }
+}
+
+/// Syncs the active phase and sets a timer for the next switch. Timers do
+/// not survive upgrades, this runs again after each one.
+pub fn schedule_drop() {
+    if let Some(timer) = DROP_TIMER.with(|t| t.borrow_mut().take()) {
+        ic_cdk_timers::clear_timer(timer);
+    }
+
+    sync_drop();
+
+    let now = ic_cdk::api::time();
+    if let Some(at) = sale_state()
+        .drop
+        .and_then(|windows| next_switch(&windows, now))
+    {
+        let timer = ic_cdk_timers::set_timer(Duration::from_nanos(at - now), schedule_drop);
+        DROP_TIMER.with(|t| *t.borrow_mut() = Some(timer));
+    }
+}
+
+pub fn drop_status() -> DropStatus {
+    let state = sale_state();
+    let now = ic_cdk::api::time();
+    let supply_cap = CONFIG.with(|c| c.borrow().get().supply_cap);
+    let next_switch_at = state
+        .drop
+        .as_ref()
+        .and_then(|windows| next_switch(windows, now));
+
+    DropStatus {
+        phase: active_phase(),
+        remaining_supply: supply_cap.map(|cap| cap.saturating_sub(get_total_supply())),
+        next_switch_at,
+        countdown: next_switch_at.map(|at| at - now),
+    }
+}
+
+pub fn set_treasury(treasury: Option<ICRCAccount>) {
+    update_sale(|state| state.treasury = treasury.clone());
+    log_event(AuditEvent::TreasurySet { treasury });
+}
+
+pub fn add_to_allowlist(phase_id: u32, principals: Vec<Principal>) -> Result<(), MintError> {
+    phase(phase_id).ok_or(MintError::InvalidPhase)?;
+
+    ALLOWLIST.with(|a| {
+        let mut a = a.borrow_mut();
+        for principal in principals.iter() {
+            a.insert(phase_key(phase_id, *principal), ());
+        }
+    });
+    log_event(AuditEvent::AllowlistUpdated {
+        phase_id,
+        added: principals.len() as u64,
+        removed: 0,
+    });
+
+    Ok(())
+}
+
+pub fn remove_from_allowlist(phase_id: u32, principals: Vec<Principal>) {
+    ALLOWLIST.with(|a| {
+        let mut a = a.borrow_mut();
+        for principal in principals.iter() {
+            a.remove(&phase_key(phase_id, *principal));
+        }
+    });
+    log_event(AuditEvent::AllowlistUpdated {
+        phase_id,
+        added: 0,
+        removed: principals.len() as u64,
+    });
+}
+
+pub fn is_allowlisted(phase_id: u32, principal: Principal) -> bool {
+    ALLOWLIST.with(|a| a.borrow().contains_key(&phase_key(phase_id, principal)))
+}
+
+pub fn minted_in_phase(phase_id: u32, principal: Principal) -> u32 {
+    MINTED_IN_PHASE.with(|m| m.borrow().get(&phase_key(phase_id, principal)).unwrap_or(0))
+}
+
+fn verify_proof(root: &[u8], principal: Principal, proof: &[ByteBuf]) -> bool {
+    let mut hash = raw_sha256(principal.as_slice());
+
+    for sibling in proof {
+        let mut pair = Vec::with_capacity(64);
+        if hash.as_slice() <= sibling.as_slice() {
+            pair.extend_from_slice(&hash);
+            pair.extend_from_slice(sibling);
+        } else {
+            pair.extend_from_slice(sibling);
+            pair.extend_from_slice(&hash);
+        }
+        hash = raw_sha256(&pair);
+    }
+
+    hash.as_slice() == root
+}
+
+fn check_eligible(
+    phase: &MintPhase,
+    principal: Principal,
+    proof: &Option<Vec<ByteBuf>>,
+) -> Result<(), MintError> {
+    let eligible = match &phase.eligibility {
+        Eligibility::Public => true,
+        Eligibility::Allowlist => is_allowlisted(phase.id, principal),
+        Eligibility::MerkleRoot(root) => proof
+            .as_ref()
+            .is_some_and(|proof| verify_proof(root, principal, proof)),
+    };
+    if !eligible {
+        return Err(MintError::NotEligible);
+    }
+
+    if let Some(limit) = phase.per_account_limit {
+        if minted_in_phase(phase.id, principal) >= limit {
+            return Err(MintError::LimitReached { limit });
+        }
+    }
+
+    Ok(())
+}
+
+/// Lowest unused id from the self-mint counter on, ids taken by the minting
+/// authority are skipped.
+fn next_token_id() -> u128 {
+    NEXT_TOKEN_ID.with(|n| {
+        let mut n = n.borrow_mut();
+        let mut id = *n.get();
+        while TOKENS.with(|tokens| tokens.borrow().contains_key(&id)) {
+            id += 1;
+        }
+        n.set(id + 1).unwrap();
+        id
+    })
+}
+
+/// Price of the next mint in the active phase.
+pub fn mint_price() -> Option<u128> {
+    let phase = active_phase()?;
+
+    Some(phase.price.map_or(0, |price| {
+        price.price(ic_cdk::api::time(), get_total_supply())
+    }))
+}
+
+pub fn sale_log(prev: Option<u64>, take: Option<u128>) -> Vec<SaleLog> {
+    let take = take.unwrap_or(100) as usize;
+
+    SALE_LOG.with(|l| {
+        let l = l.borrow();
+        let end = prev.unwrap_or(l.len()).min(l.len());
+        (0..end)
+            .rev()
+            .filter_map(|id| l.get(&id))
+            .take(take)
+            .collect()
+    })
+}
+
+fn log_sale(phase_id: u32, buyer: ICRCAccount, price: u128, outcome: SaleOutcome) {
+    SALE_LOG.with(|l| {
+        let mut l = l.borrow_mut();
+        let id = l.len();
+        l.insert(
+            id,
+            SaleLog {
+                id,
+                at: ic_cdk::api::time(),
+                phase_id,
+                buyer,
+                price,
+                outcome,
+            },
+        );
+    });
+}
+
+/// Checks everything a mint in `phase` depends on, again after a payment
+/// since the phase, supply and limits may have moved meanwhile.
+fn check_mintable(
+    phase: &MintPhase,
+    principal: Principal,
+    proof: &Option<Vec<ByteBuf>>,
+) -> Result<(), MintError> {
+    let state = sale_state();
+    // the switch timer may not have fired yet
+    let closed = state
+        .drop
+        .is_some_and(|windows| scheduled_phase(&windows, ic_cdk::api::time()) != Some(phase.id));
+    if state.active != Some(phase.id) || closed {
+        return Err(MintError::NoActivePhase);
+    }
+    if pause_state().mints {
+        return Err(MintError::Paused);
+    }
+    let supply_cap = CONFIG.with(|c| c.borrow().get().supply_cap);
+    if supply_cap.is_some_and(|cap| get_total_supply() >= cap) || reveal::is_sold_out() {
+        return Err(MintError::SoldOut);
+    }
+    check_eligible(phase, principal, proof)
+}
+
+fn mint_next(phase_id: u32, to: ICRCAccount) -> u128 {
+    let id = next_token_id();
+    let name = CONFIG.with(|c| c.borrow().get().name.clone());
+
+    MINTED_IN_PHASE.with(|m| {
+        let key = phase_key(phase_id, to.owner());
+        let minted = m.borrow().get(&key).unwrap_or(0);
+        m.borrow_mut().insert(key, minted + 1);
+    });
+
+    let (name, image, description) = match reveal::hide(id) {
+        Some(placeholder) => (
+            format!("{} #{}", placeholder.name, id),
+            placeholder.image,
+            placeholder.description,
+        ),
+        None => (format!("{} #{}", name, id), None, None),
+    };
+
+    insert_minted(Token {
+        id,
+        owner: to,
+        name,
+        image,
+        description,
+        approvals: Vec::new(),
+        transferability: None,
+        user: None,
+    });
+
+    id
+}
+
+/// Mints the next token to the caller, the phase decides who may, how often
+/// and at what price. Paid mints pull the price via ICRC-2 `transfer_from`,
+/// forward it to the treasury and refund it when the mint fails.
+pub async fn self_mint(arg: SelfMintArgs) -> Result<u128, MintError> {
+    let caller = ic_cdk::caller();
+    let phase = active_phase().ok_or(MintError::NoActivePhase)?;
+    let to = ICRCAccount::new(caller, arg.to_subaccount);
+
+    check_mintable(&phase, caller, &arg.proof)?;
+
+    let price = match &phase.price {
+        None => 0,
+        Some(price) => price.price(ic_cdk::api::time(), get_total_supply()),
+    };
+    if arg.max_price.is_some_and(|max_price| price > max_price) {
+        return Err(MintError::PriceChanged { price });
+    }
+    if price == 0 {
+        let token_id = mint_next(phase.id, to.clone());
+        log_sale(phase.id, to, 0, SaleOutcome::Minted { token_id });
+        return Ok(token_id);
+    }
+
+    let ledger = payment_ledger().ok_or(MintError::NoPaymentLedger)?;
+    let treasury = sale_state().treasury.ok_or(MintError::NoTreasury)?;
+    let buyer = ICRCAccount::new(caller, arg.from_subaccount);
+    let fee = ledger_fee(ledger)
+        .await
+        .map_err(|msg| MintError::PaymentFailed { msg })?;
+
+    if let Err(msg) = collect(ledger, buyer.clone(), price).await {
+        log_sale(
+            phase.id,
+            buyer,
+            price,
+            SaleOutcome::PaymentFailed { msg: msg.clone() },
+        );
+        return Err(MintError::PaymentFailed { msg });
+    }
+
+    if let Err(e) = check_mintable(&phase, caller, &arg.proof) {
+        log_sale(
+            phase.id,
+            buyer.clone(),
+            price,
+            SaleOutcome::Refunded {
+                reason: format!("{:?}", e),
+            },
+        );
+        pay_out(ledger, fee, buyer, price).await;
+        return Err(e);
+    }
+
+    let token_id = mint_next(phase.id, to);
+    log_sale(phase.id, buyer, price, SaleOutcome::Minted { token_id });
+    pay_out(ledger, fee, treasury, price).await;
+
+    Ok(token_id)
+}
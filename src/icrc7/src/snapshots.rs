@@ -0,0 +1,346 @@
+use crate::{
+    migration::budget_exhausted,
+    state::{
+        account_key, AccountKey, HOLDER_EXPORTS, OWNERSHIP_INDEX, SNAPSHOT_EXPORTS, TOKENS,
+        TRANSACTION_ID,
+    },
+};
+use b3_utils::{
+    ledger::ICRCAccount,
+    memory::types::{Bound, Storable},
+};
+use candid::{CandidType, Decode, Encode};
+use serde_derive::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Holders served by a single page of an export download.
+pub const EXPORT_PAGE_SIZE: usize = 5_000;
+/// Most tokens a single `snapshot` call looks at, owned at that point or not.
+pub const SNAPSHOT_SCAN_LIMIT: usize = 1_000;
+
+/// Owner of a token after a transaction, `None` once burned.
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct Ownership {
+    pub owner: Option<ICRCAccount>,
+}
+
+impl Storable for Ownership {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+/// Index entries sort newest first within a token, the first entry at or
+/// after `(token_id, index_key(index))` is the owner at `index`.
+fn index_key(tx_index: u128) -> u128 {
+    u128::MAX - tx_index
+}
+
+/// Records the current owner of a token under the transaction being
+/// committed, called wherever a token is minted, moved or burned.
+pub fn record_owner(token_id: &u128) {
+    let tx_index = TRANSACTION_ID.with(|id| *id.borrow().get());
+    let owner = TOKENS.with(|tokens| tokens.borrow().get(token_id).map(|token| token.owner));
+
+    set_owner(*token_id, tx_index, owner);
+}
+
+/// Records the owner of a token after transaction `tx_index`, `None` once
+/// burned.
+pub fn set_owner(token_id: u128, tx_index: u128, owner: Option<ICRCAccount>) {
+    OWNERSHIP_INDEX.with(|i| {
+        i.borrow_mut()
+            .insert((token_id, index_key(tx_index)), Ownership { owner })
+    });
+}
+
+pub fn is_indexed(token_id: &u128) -> bool {
+    OWNERSHIP_INDEX.with(|i| {
+        i.borrow()
+            .range((*token_id, 0)..=(*token_id, u128::MAX))
+            .next()
+            .is_some()
+    })
+}
+
+pub fn owner_at(token_id: u128, tx_index: u128) -> Option<ICRCAccount> {
+    OWNERSHIP_INDEX.with(|i| {
+        i.borrow()
+            .range((token_id, index_key(tx_index))..=(token_id, u128::MAX))
+            .next()
+            .and_then(|(_, ownership)| ownership.owner)
+    })
+}
+
+/// Next indexed token after `prev`.
+fn next_token(prev: Option<u128>) -> Option<u128> {
+    let start = match prev {
+        None => 0,
+        Some(u128::MAX) => return None,
+        Some(prev) => prev + 1,
+    };
+
+    OWNERSHIP_INDEX.with(|i| i.borrow().range((start, 0)..).next().map(|((id, _), _)| id))
+}
+
+#[derive(CandidType)]
+pub struct SnapshotPage {
+    pub owners: Vec<(u128, ICRCAccount)>,
+    /// Last token looked at, the `prev` of the next page. `None` once every
+    /// token has been.
+    pub cursor: Option<u128>,
+}
+
+/// Tokens and their owners as of transaction `tx_index`, in token id order.
+/// Tokens not minted yet or already burned at that point are left out, a
+/// page can come back short or empty while `cursor` is still set.
+pub fn snapshot(tx_index: u128, prev: Option<u128>, take: Option<u128>) -> SnapshotPage {
+    let take = take.unwrap_or(100) as usize;
+    let mut owners = Vec::new();
+    let mut cursor = prev;
+
+    let mut scanned = 0;
+
+    while owners.len() < take && scanned < SNAPSHOT_SCAN_LIMIT {
+        let Some(token_id) = next_token(cursor) else {
+            cursor = None;
+            break;
+        };
+        if let Some(owner) = owner_at(token_id, tx_index) {
+            owners.push((token_id, owner));
+        }
+        cursor = Some(token_id);
+        scanned += 1;
+    }
+
+    SnapshotPage { owners, cursor }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExportStatus {
+    Running,
+    Ready,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct SnapshotExport {
+    pub id: u64,
+    pub tx_index: u128,
+    pub status: ExportStatus,
+    /// Last token folded into the holder list.
+    pub cursor: Option<u128>,
+    pub holders: u64,
+    pub tokens: u64,
+    pub created_at: u64,
+    pub finished_at: Option<u64>,
+}
+
+impl Storable for SnapshotExport {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct Holder {
+    pub account: ICRCAccount,
+    pub token_ids: Vec<u128>,
+}
+
+impl Storable for Holder {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+/// Key of the holder entries of an export.
+pub type HolderKey = (u64, AccountKey);
+
+pub fn export(id: &u64) -> Option<SnapshotExport> {
+    SNAPSHOT_EXPORTS.with(|e| e.borrow().get(id))
+}
+
+pub fn exports(prev: Option<u64>, take: Option<u128>) -> Vec<SnapshotExport> {
+    let take = take.unwrap_or(100) as usize;
+
+    SNAPSHOT_EXPORTS.with(|e| {
+        let e = e.borrow();
+        let end = prev.unwrap_or(e.len()).min(e.len());
+        (0..end)
+            .rev()
+            .filter_map(|id| e.get(&id))
+            .take(take)
+            .collect()
+    })
+}
+
+/// Holders of a finished export, `page` counts from zero.
+pub fn holders(id: u64, page: usize) -> Vec<Holder> {
+    HOLDER_EXPORTS.with(|h| {
+        h.borrow()
+            .range((id, [0; 62])..=(id, [u8::MAX; 62]))
+            .skip(page * EXPORT_PAGE_SIZE)
+            .take(EXPORT_PAGE_SIZE)
+            .map(|(_, holder)| holder)
+            .collect()
+    })
+}
+
+fn save(export: SnapshotExport) {
+    SNAPSHOT_EXPORTS.with(|e| e.borrow_mut().insert(export.id, export));
+}
+
+/// Starts building the holder list as of `tx_index`, `None` for the last
+/// committed transaction. The export is built over several slices, so it must
+/// point at a committed transaction: `TRANSACTION_ID` is the id the next one
+/// will take, a transfer committed meanwhile would otherwise be folded in.
+pub fn start_export(tx_index: Option<u128>) -> u64 {
+    let next_tx_index = TRANSACTION_ID.with(|id| *id.borrow().get());
+    let tx_index = match tx_index {
+        Some(tx_index) if tx_index < next_tx_index => tx_index,
+        None if next_tx_index > 0 => next_tx_index - 1,
+        _ => ic_cdk::trap("Invalid Transaction Index"),
+    };
+    let id = SNAPSHOT_EXPORTS.with(|e| e.borrow().len());
+
+    save(SnapshotExport {
+        id,
+        tx_index,
+        status: ExportStatus::Running,
+        cursor: None,
+        holders: 0,
+        tokens: 0,
+        created_at: ic_cdk::api::time(),
+        finished_at: None,
+    });
+    schedule();
+
+    id
+}
+
+/// Folds tokens into the holder list until the slice runs out of
+/// instructions. Returns whether the export is still running.
+fn build(mut export: SnapshotExport) -> bool {
+    loop {
+        let token_id = match next_token(export.cursor) {
+            None => {
+                export.status = ExportStatus::Ready;
+                export.finished_at = Some(ic_cdk::api::time());
+                break;
+            }
+            Some(token_id) => token_id,
+        };
+
+        if let Some(owner) = owner_at(token_id, export.tx_index) {
+            let key = (export.id, account_key(&owner));
+            HOLDER_EXPORTS.with(|h| {
+                let mut h = h.borrow_mut();
+                let mut holder = h.get(&key).unwrap_or_else(|| {
+                    export.holders += 1;
+                    Holder {
+                        account: owner,
+                        token_ids: Vec::new(),
+                    }
+                });
+                holder.token_ids.push(token_id);
+                h.insert(key, holder);
+            });
+            export.tokens += 1;
+        }
+        export.cursor = Some(token_id);
+
+        if budget_exhausted() {
+            break;
+        }
+    }
+
+    let running = export.status == ExportStatus::Running;
+    save(export);
+
+    running
+}
+
+fn schedule() {
+    ic_cdk_timers::set_timer(Duration::ZERO, run_pending);
+}
+
+/// Timer slice, builds running exports oldest first.
+fn run_pending() {
+    let running: Vec<SnapshotExport> = SNAPSHOT_EXPORTS.with(|e| {
+        e.borrow()
+            .iter()
+            .map(|(_, export)| export)
+            .filter(|export| export.status == ExportStatus::Running)
+            .collect()
+    });
+
+    for export in running {
+        if build(export) || budget_exhausted() {
+            return schedule();
+        }
+    }
+}
+
+/// Timers do not survive upgrades, running exports are picked up again.
+pub fn schedule_all() {
+    let running = SNAPSHOT_EXPORTS.with(|e| {
+        e.borrow()
+            .iter()
+            .any(|(_, export)| export.status == ExportStatus::Running)
+    });
+
+    if running {
+        schedule();
+    }
+}
+
+pub fn holders_csv(holders: &[Holder]) -> String {
+    let mut csv = String::from("account,count,token_ids\n");
+    for holder in holders {
+        let token_ids: Vec<String> = holder.token_ids.iter().map(|id| id.to_string()).collect();
+        csv.push_str(&format!(
+            "{},{},{}\n",
+            holder.account,
+            holder.token_ids.len(),
+            token_ids.join(" ")
+        ));
+    }
+    csv
+}
+
+#[derive(Serialize)]
+struct HolderRow<'a> {
+    account: String,
+    count: usize,
+    token_ids: &'a [u128],
+}
+
+pub fn holders_json(holders: &[Holder]) -> String {
+    let rows: Vec<HolderRow> = holders
+        .iter()
+        .map(|holder| HolderRow {
+            account: holder.account.to_string(),
+            count: holder.token_ids.len(),
+            token_ids: &holder.token_ids,
+        })
+        .collect();
+    serde_json::to_string(&rows).unwrap_or_default()
+}
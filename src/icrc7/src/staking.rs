@@ -0,0 +1,403 @@
+use crate::{
+    audit::{log_event, AuditEvent},
+    errors::StakingError,
+    locks::{lock_token, unlock_token, Lock},
+    pause::is_frozen,
+    payments::{collect_into, ledger_fee, pay_out_from, queue_payout, schedule_payouts, Payout},
+    state::{REWARD_BUDGETS, STAKES, STAKE_HISTORY, STAKING_CONFIG, TOKENS},
+    types::{FundRewardsArgs, StakeArgs, UnstakeArgs},
+};
+use b3_utils::{
+    ledger::ICRCAccount,
+    memory::types::{Bound, Storable},
+    Subaccount,
+};
+use candid::{CandidType, Decode, Encode, Principal};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+const REWARDS_DOMAIN: u8 = 4;
+
+/// Reward terms for new stakes, running stakes keep the terms they were
+/// created under.
+#[derive(CandidType, Serialize, Deserialize, Clone, Default)]
+pub struct StakingConfig {
+    /// ICRC-1 ledger rewards are paid on, staking is disabled while unset.
+    pub reward_ledger: Option<Principal>,
+    pub reward_per_period: u128,
+    /// Length of one reward period in nanoseconds.
+    pub period: u64,
+    pub min_duration: u64,
+    pub max_duration: u64,
+}
+
+impl Storable for StakingConfig {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+/// Funded rewards not paid yet, per ledger. They are held in the rewards
+/// subaccount, apart from escrow and payouts in the default account.
+#[derive(CandidType, Serialize, Deserialize, Clone, Default)]
+pub struct RewardBudgets {
+    pub balances: BTreeMap<Principal, u128>,
+}
+
+impl Storable for RewardBudgets {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct Stake {
+    pub token_id: u128,
+    pub owner: ICRCAccount,
+    pub reward_ledger: Principal,
+    pub reward_per_period: u128,
+    pub period: u64,
+    pub staked_at: u64,
+    pub unlock_at: u64,
+    /// Rewards up to this time have been paid.
+    pub claimed_until: u64,
+}
+
+impl Storable for Stake {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Stake {
+    /// Whole periods not paid yet, rewards stop accruing at `unlock_at`.
+    pub fn unclaimed_periods(&self, now: u64) -> u64 {
+        now.min(self.unlock_at).saturating_sub(self.claimed_until) / self.period
+    }
+
+    pub fn accrued(&self, now: u64) -> u128 {
+        self.reward_per_period
+            .saturating_mul(self.unclaimed_periods(now) as u128)
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub enum StakeEventKind {
+    Locked { unlock_at: u64 },
+    Unlocked,
+    Claimed { amount: u128 },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct StakeEvent {
+    pub id: u64,
+    pub at: u64,
+    pub token_id: u128,
+    pub account: ICRCAccount,
+    pub kind: StakeEventKind,
+}
+
+impl Storable for StakeEvent {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+/// `[REWARDS_DOMAIN, 0..]`, the account rewards are funded into and paid from.
+pub fn rewards_subaccount() -> Subaccount {
+    let mut bytes = [0u8; 32];
+    bytes[0] = REWARDS_DOMAIN;
+    Subaccount(bytes)
+}
+
+pub fn reward_budget(ledger: &Principal) -> u128 {
+    REWARD_BUDGETS.with(|b| {
+        b.borrow()
+            .get()
+            .balances
+            .get(ledger)
+            .copied()
+            .unwrap_or_default()
+    })
+}
+
+fn set_reward_budget(ledger: Principal, amount: u128) {
+    REWARD_BUDGETS.with(|b| {
+        let mut b = b.borrow_mut();
+        let mut balances = b.get().balances.clone();
+        balances.insert(ledger, amount);
+        b.set(RewardBudgets { balances }).unwrap();
+    });
+}
+
+/// Takes `amount` out of the budget before it is paid, so concurrent claims
+/// can not spend the same funds.
+fn reserve_rewards(ledger: Principal, amount: u128) -> Result<(), StakingError> {
+    let available = reward_budget(&ledger);
+    if amount > available {
+        return Err(StakingError::RewardsExhausted { available });
+    }
+
+    set_reward_budget(ledger, available - amount);
+
+    Ok(())
+}
+
+/// Pulls `amount` of the current reward ledger from the caller into the
+/// rewards subaccount via ICRC-2 `transfer_from`. Returns the new budget.
+pub async fn fund_rewards(arg: FundRewardsArgs) -> Result<u128, StakingError> {
+    let from = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let ledger = staking_config()
+        .reward_ledger
+        .ok_or(StakingError::StakingDisabled)?;
+
+    collect_into(ledger, from, Some(rewards_subaccount()), arg.amount)
+        .await
+        .map_err(|msg| StakingError::FundingFailed { msg })?;
+
+    let budget = reward_budget(&ledger).saturating_add(arg.amount);
+    set_reward_budget(ledger, budget);
+
+    Ok(budget)
+}
+
+pub fn staking_config() -> StakingConfig {
+    STAKING_CONFIG.with(|s| s.borrow().get().clone())
+}
+
+pub fn set_staking_config(config: StakingConfig) -> Result<(), StakingError> {
+    if config.reward_ledger.is_some()
+        && (config.period == 0 || config.min_duration > config.max_duration)
+    {
+        return Err(StakingError::InvalidConfig);
+    }
+
+    STAKING_CONFIG.with(|s| s.borrow_mut().set(config.clone()).unwrap());
+    log_event(AuditEvent::StakingUpdated(config));
+
+    Ok(())
+}
+
+pub fn stake_of(token_id: &u128) -> Option<Stake> {
+    STAKES.with(|s| s.borrow().get(token_id))
+}
+
+pub fn stakes_of(account: &ICRCAccount) -> Vec<Stake> {
+    STAKES.with(|s| {
+        s.borrow()
+            .iter()
+            .map(|(_, stake)| stake)
+            .filter(|stake| stake.owner == *account)
+            .collect()
+    })
+}
+
+/// Newest first, optionally for a single token.
+pub fn stake_history(
+    token_id: Option<u128>,
+    prev: Option<u64>,
+    take: Option<u128>,
+) -> Vec<StakeEvent> {
+    let take = take.unwrap_or(100) as usize;
+
+    STAKE_HISTORY.with(|h| {
+        let h = h.borrow();
+        let end = prev.unwrap_or(h.len()).min(h.len());
+        (0..end)
+            .rev()
+            .filter_map(|id| h.get(&id))
+            .filter(|event| token_id.map_or(true, |id| event.token_id == id))
+            .take(take)
+            .collect()
+    })
+}
+
+fn record(token_id: u128, account: ICRCAccount, kind: StakeEventKind) {
+    STAKE_HISTORY.with(|h| {
+        let mut h = h.borrow_mut();
+        let id = h.len();
+        h.insert(
+            id,
+            StakeEvent {
+                id,
+                at: ic_cdk::api::time(),
+                token_id,
+                account,
+                kind,
+            },
+        );
+    });
+}
+
+/// Locks the token for `duration`, it can neither move nor be approved until
+/// it is unstaked.
+pub fn stake(arg: StakeArgs) -> Result<u64, StakingError> {
+    let owner = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let config = staking_config();
+    let reward_ledger = config.reward_ledger.ok_or(StakingError::StakingDisabled)?;
+
+    let now = ic_cdk::api::time();
+    let unlock_at = match now.checked_add(arg.duration) {
+        Some(unlock_at)
+            if arg.duration >= config.min_duration && arg.duration <= config.max_duration =>
+        {
+            unlock_at
+        }
+        _ => return Err(StakingError::InvalidDuration),
+    };
+
+    let token = match TOKENS.with(|tokens| tokens.borrow().get(&arg.token_id)) {
+        None => ic_cdk::trap("Invalid Id"),
+        Some(token) => token,
+    };
+    if token.owner != owner {
+        return Err(StakingError::Unauthorized {
+            tokens_ids: vec![token.id],
+        });
+    }
+    if is_frozen(&token.id) {
+        return Err(StakingError::TokenFrozen {
+            tokens_ids: vec![token.id],
+        });
+    }
+    if !lock_token(token.id, Lock::Staked) {
+        return Err(StakingError::TokenLocked {
+            tokens_ids: vec![token.id],
+        });
+    }
+
+    STAKES.with(|s| {
+        s.borrow_mut().insert(
+            token.id,
+            Stake {
+                token_id: token.id,
+                owner: owner.clone(),
+                reward_ledger,
+                reward_per_period: config.reward_per_period,
+                period: config.period,
+                staked_at: now,
+                unlock_at,
+                claimed_until: now,
+            },
+        )
+    });
+    record(token.id, owner, StakeEventKind::Locked { unlock_at });
+
+    Ok(unlock_at)
+}
+
+/// Pays rewards already taken out of the budget from the rewards subaccount.
+async fn pay_rewards(stake: &Stake, amount: u128) {
+    if amount == 0 {
+        return;
+    }
+
+    record(
+        stake.token_id,
+        stake.owner.clone(),
+        StakeEventKind::Claimed { amount },
+    );
+    match ledger_fee(stake.reward_ledger).await {
+        Ok(fee) => {
+            pay_out_from(
+                stake.reward_ledger,
+                fee,
+                Some(rewards_subaccount()),
+                stake.owner.clone(),
+                amount,
+            )
+            .await
+        }
+        // queued like any failed payout, the retry looks the fee up again
+        Err(reason) => {
+            queue_payout(Payout {
+                ledger: stake.reward_ledger,
+                to: stake.owner.clone(),
+                amount,
+                at: ic_cdk::api::time(),
+                reason,
+                from_subaccount: Some(rewards_subaccount()),
+            });
+            schedule_payouts();
+        }
+    }
+}
+
+/// Pays the rewards accrued so far out of the funded budget, fails when the
+/// budget can not cover them. Failed payouts are queued with the other
+/// pending payouts.
+pub async fn claim(arg: UnstakeArgs) -> Result<u128, StakingError> {
+    let owner = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let mut stake = stake_of(&arg.token_id).ok_or(StakingError::NotStaked)?;
+
+    if stake.owner != owner {
+        return Err(StakingError::Unauthorized {
+            tokens_ids: vec![arg.token_id],
+        });
+    }
+
+    let now = ic_cdk::api::time();
+    let amount = stake.accrued(now);
+    reserve_rewards(stake.reward_ledger, amount)?;
+    // settled before the payout so the same periods cannot be claimed twice
+    stake.claimed_until += stake.unclaimed_periods(now) * stake.period;
+    STAKES.with(|s| s.borrow_mut().insert(stake.token_id, stake.clone()));
+
+    pay_rewards(&stake, amount).await;
+
+    Ok(amount)
+}
+
+/// Releases the token once its lock ran out and pays what is left to claim,
+/// fails like `claim` when the budget can not cover it.
+pub async fn unstake(arg: UnstakeArgs) -> Result<u128, StakingError> {
+    let owner = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let stake = stake_of(&arg.token_id).ok_or(StakingError::NotStaked)?;
+    let now = ic_cdk::api::time();
+
+    if stake.owner != owner {
+        return Err(StakingError::Unauthorized {
+            tokens_ids: vec![arg.token_id],
+        });
+    }
+    if now < stake.unlock_at {
+        return Err(StakingError::StillLocked {
+            unlock_at: stake.unlock_at,
+        });
+    }
+
+    let amount = stake.accrued(now);
+    reserve_rewards(stake.reward_ledger, amount)?;
+
+    STAKES.with(|s| s.borrow_mut().remove(&arg.token_id));
+    unlock_token(&arg.token_id);
+    record(arg.token_id, owner, StakeEventKind::Unlocked);
+
+    pay_rewards(&stake, amount).await;
+
+    Ok(amount)
+}
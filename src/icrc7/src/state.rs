@@ -1,6 +1,29 @@
 use crate::{
-    errors::{ApprovalError, TransferError},
-    types::CollectionMetadata,
+    airdrops::{Airdrop, AirdropFailure, AirdropRecipient},
+    auction::Auction,
+    audit::AuditLog,
+    cycles::CyclesState,
+    errors::{ApprovalError, ConfigError, TransferError},
+    fractions::{Fraction, ShareLedger},
+    history::{self, TokenEvent, TokenEventKind},
+    locks::{is_locked, Lock},
+    market::Listing,
+    metrics::Metrics,
+    migration::{encode_versioned, split_versioned},
+    offers::Offer,
+    pause::{is_frozen, PauseState},
+    payments::Payout,
+    rentals::{Rental, RentalTerms},
+    reveal::{RevealState, TokenMetadata},
+    roles::RoleRegistry,
+    sale::{PhaseKey, SaleLog, SaleState},
+    snapshots::{record_owner, Holder, HolderKey, Ownership, SnapshotExport},
+    staking::{RewardBudgets, Stake, StakeEvent, StakingConfig},
+    subscriptions::{DeadLetter, Subscription},
+    swaps::Swap,
+    token_accounts::BurnedToken,
+    types::{CollectionMetadata, UpdateCollectionConfigArgs},
+    vouchers::MinterKey,
 };
 use b3_utils::{
     ledger::{ICRC1MetadataValue, ICRCAccount},
@@ -17,16 +40,79 @@ use std::cell::RefCell;
 thread_local! {
     pub static CONFIG: RefCell<DefaultStableCell<CollectionConfig>> = init_stable_mem_refcell("config", 1).unwrap();
     pub static TOKENS: RefCell<DefaultStableBTreeMap<u128, Token>> = init_stable_mem_refcell("tokens", 2).unwrap();
-    pub static TRANSFER_LOG: RefCell<DefaultStableVec<TransferLog>> = init_stable_mem_refcell("transfer_log", 3).unwrap();
+    pub static LEGACY_TRANSFER_LOG: RefCell<DefaultStableVec<LegacyTransferLog>> = init_stable_mem_refcell("transfer_log", 3).unwrap();
     pub static TRANSACTION_ID: RefCell<DefaultStableCell<u128>> = init_stable_mem_refcell("transaction_id", 4).unwrap();
     pub static TOTAL_SUPPLY: RefCell<DefaultStableCell<u128>> = init_stable_mem_refcell("total_supply", 5).unwrap();
+    pub static METRICS: RefCell<DefaultStableCell<Metrics>> = init_stable_mem_refcell("metrics", 6).unwrap();
+    pub static ROLES: RefCell<DefaultStableCell<RoleRegistry>> = init_stable_mem_refcell("roles", 7).unwrap();
+    pub static AUDIT_LOG: RefCell<DefaultStableBTreeMap<u64, AuditLog>> = init_stable_mem_refcell("audit_log", 8).unwrap();
+    pub static SCHEMA_VERSION: RefCell<DefaultStableCell<u32>> = init_stable_mem_refcell("schema_version", 9).unwrap();
+    pub static MIGRATION_CURSOR: RefCell<DefaultStableCell<Option<u128>>> = init_stable_mem_refcell("migration_cursor", 10).unwrap();
+    pub static FEATURES: RefCell<DefaultStableCell<Features>> = init_stable_mem_refcell("features", 11).unwrap();
+    pub static PAUSE: RefCell<DefaultStableCell<PauseState>> = init_stable_mem_refcell("pause", 12).unwrap();
+    pub static FROZEN_TOKENS: RefCell<DefaultStableBTreeMap<u128, u64>> = init_stable_mem_refcell("frozen_tokens", 13).unwrap();
+    pub static LOCKED_TOKENS: RefCell<DefaultStableBTreeMap<u128, Lock>> = init_stable_mem_refcell("locked_tokens", 14).unwrap();
+    pub static LISTINGS: RefCell<DefaultStableBTreeMap<u128, Listing>> = init_stable_mem_refcell("listings", 15).unwrap();
+    pub static PENDING_PAYOUTS: RefCell<DefaultStableBTreeMap<u64, Payout>> = init_stable_mem_refcell("pending_payouts", 16).unwrap();
+    pub static AUCTIONS: RefCell<DefaultStableBTreeMap<u128, Auction>> = init_stable_mem_refcell("auctions", 17).unwrap();
+    pub static OFFERS: RefCell<DefaultStableBTreeMap<u64, Offer>> = init_stable_mem_refcell("offers", 18).unwrap();
+    pub static TOKEN_OFFERS: RefCell<DefaultStableBTreeMap<(u128, u64), ()>> = init_stable_mem_refcell("token_offers", 19).unwrap();
+    pub static OFFER_ID: RefCell<DefaultStableCell<u64>> = init_stable_mem_refcell("offer_id", 20).unwrap();
+    pub static SWAPS: RefCell<DefaultStableBTreeMap<u64, Swap>> = init_stable_mem_refcell("swaps", 21).unwrap();
+    pub static SWAP_ID: RefCell<DefaultStableCell<u64>> = init_stable_mem_refcell("swap_id", 22).unwrap();
+    pub static RENTAL_TERMS: RefCell<DefaultStableBTreeMap<u128, RentalTerms>> = init_stable_mem_refcell("rental_terms", 23).unwrap();
+    pub static FRACTIONS: RefCell<DefaultStableBTreeMap<u128, Fraction>> = init_stable_mem_refcell("fractions", 24).unwrap();
+    pub static STAKING_CONFIG: RefCell<DefaultStableCell<StakingConfig>> = init_stable_mem_refcell("staking_config", 26).unwrap();
+    pub static STAKES: RefCell<DefaultStableBTreeMap<u128, Stake>> = init_stable_mem_refcell("stakes", 27).unwrap();
+    pub static STAKE_HISTORY: RefCell<DefaultStableBTreeMap<u64, StakeEvent>> = init_stable_mem_refcell("stake_history", 28).unwrap();
+    pub static SALE: RefCell<DefaultStableCell<SaleState>> = init_stable_mem_refcell("sale", 29).unwrap();
+    pub static ALLOWLIST: RefCell<DefaultStableBTreeMap<PhaseKey, ()>> = init_stable_mem_refcell("allowlist", 30).unwrap();
+    pub static MINTED_IN_PHASE: RefCell<DefaultStableBTreeMap<PhaseKey, u32>> = init_stable_mem_refcell("minted_in_phase", 31).unwrap();
+    pub static NEXT_TOKEN_ID: RefCell<DefaultStableCell<u128>> = init_stable_mem_refcell("next_token_id", 32).unwrap();
+    pub static SALE_LOG: RefCell<DefaultStableBTreeMap<u64, SaleLog>> = init_stable_mem_refcell("sale_log", 33).unwrap();
+    pub static REVEAL: RefCell<DefaultStableCell<RevealState>> = init_stable_mem_refcell("reveal", 34).unwrap();
+    pub static HIDDEN_TOKENS: RefCell<DefaultStableBTreeMap<u128, u64>> = init_stable_mem_refcell("hidden_tokens", 35).unwrap();
+    pub static REVEAL_METADATA: RefCell<DefaultStableBTreeMap<u64, TokenMetadata>> = init_stable_mem_refcell("reveal_metadata", 36).unwrap();
+    pub static MINTER_KEYS: RefCell<DefaultStableBTreeMap<u32, MinterKey>> = init_stable_mem_refcell("minter_keys", 37).unwrap();
+    pub static USED_NONCES: RefCell<DefaultStableBTreeMap<u64, ()>> = init_stable_mem_refcell("used_nonces", 38).unwrap();
+    pub static AIRDROPS: RefCell<DefaultStableBTreeMap<u64, Airdrop>> = init_stable_mem_refcell("airdrops", 39).unwrap();
+    pub static AIRDROP_RECIPIENTS: RefCell<DefaultStableBTreeMap<(u64, u64), AirdropRecipient>> = init_stable_mem_refcell("airdrop_recipients", 40).unwrap();
+    pub static AIRDROP_FAILURES: RefCell<DefaultStableBTreeMap<(u64, u64), AirdropFailure>> = init_stable_mem_refcell("airdrop_failures", 41).unwrap();
+    pub static OWNERSHIP_INDEX: RefCell<DefaultStableBTreeMap<(u128, u128), Ownership>> = init_stable_mem_refcell("ownership_index", 42).unwrap();
+    pub static SNAPSHOT_EXPORTS: RefCell<DefaultStableBTreeMap<u64, SnapshotExport>> = init_stable_mem_refcell("snapshot_exports", 43).unwrap();
+    pub static HOLDER_EXPORTS: RefCell<DefaultStableBTreeMap<HolderKey, Holder>> = init_stable_mem_refcell("holder_exports", 44).unwrap();
+    pub static TOKEN_EVENTS: RefCell<DefaultStableBTreeMap<u64, TokenEvent>> = init_stable_mem_refcell("token_events", 45).unwrap();
+    pub static TOKEN_HISTORY: RefCell<DefaultStableBTreeMap<(u128, u64), ()>> = init_stable_mem_refcell("token_history", 46).unwrap();
+    pub static SUBSCRIPTIONS: RefCell<DefaultStableBTreeMap<u64, Subscription>> = init_stable_mem_refcell("subscriptions", 47).unwrap();
+    pub static SUBSCRIPTION_QUEUE: RefCell<DefaultStableBTreeMap<(u64, u64), ()>> = init_stable_mem_refcell("subscription_queue", 48).unwrap();
+    pub static DEAD_LETTERS: RefCell<DefaultStableBTreeMap<(u64, u64), DeadLetter>> = init_stable_mem_refcell("dead_letters", 49).unwrap();
+    pub static CYCLES: RefCell<DefaultStableCell<CyclesState>> = init_stable_mem_refcell("cycles", 50).unwrap();
+    pub static TRANSFER_LOG: RefCell<DefaultStableBTreeMap<u64, TransferLog>> = init_stable_mem_refcell("transaction_log", 51).unwrap();
+    pub static BURNED_TOKENS: RefCell<DefaultStableBTreeMap<u128, BurnedToken>> = init_stable_mem_refcell("burned_tokens", 52).unwrap();
+    pub static HISTORY_START: RefCell<DefaultStableCell<u64>> = init_stable_mem_refcell("history_start", 53).unwrap();
+    pub static SHARE_LEDGERS: RefCell<DefaultStableBTreeMap<u128, ShareLedger>> = init_stable_mem_refcell("share_ledgers", 54).unwrap();
+    pub static SUBSCRIPTION_ID: RefCell<DefaultStableCell<u64>> = init_stable_mem_refcell("subscription_id", 55).unwrap();
+    pub static REWARD_BUDGETS: RefCell<DefaultStableCell<RewardBudgets>> = init_stable_mem_refcell("reward_budgets", 56).unwrap();
 }
 
-#[derive(CandidType, Serialize, Deserialize)]
+/// Schema versions of the stored records, see `migration::MIGRATIONS`.
+pub const CONFIG_VERSION: u8 = 1;
+pub const TOKEN_VERSION: u8 = 1;
+pub const TRANSFER_LOG_VERSION: u8 = 2;
+/// Entries left in `LEGACY_TRANSFER_LOG` keep the layout they were written with.
+pub const LEGACY_TRANSFER_LOG_VERSION: u8 = 1;
+
+/// Royalties are expressed in basis points.
+pub const MAX_ROYALTIES: u16 = 10_000;
+pub const MAX_TX_WINDOW: u64 = 24 * 60 * 60 * 1_000_000_000;
+pub const MAX_PERMITTED_DRIFT: u64 = 5 * 60 * 1_000_000_000;
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
 pub struct CollectionConfig {
     pub name: String,
     pub symbol: String,
     pub royalties: Option<u16>,
+    /// Receives every role at init, authorization is checked against `ROLES` afterwards.
     pub minting_authority: Principal,
     pub royalty_recipient: Option<ICRCAccount>,
     pub description: Option<String>,
@@ -34,17 +120,24 @@ pub struct CollectionConfig {
     pub supply_cap: Option<u128>,
     pub tx_window: u64,
     pub permitted_drift: u64,
+    /// Default policy of tokens minted without their own, `None` is `Transferable`.
+    pub transferability: Option<Transferability>,
+    /// ICRC-1/ICRC-2 ledger the marketplace settles in.
+    pub payment_ledger: Option<Principal>,
 }
 
 impl Storable for CollectionConfig {
     const BOUND: Bound = Bound::Unbounded;
 
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+        std::borrow::Cow::Owned(encode_versioned(CONFIG_VERSION, &self))
     }
 
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
+        match split_versioned(&bytes) {
+            (0..=CONFIG_VERSION, payload) => Decode!(payload, Self).unwrap(),
+            (version, _) => panic!("Unknown config version {}", version),
+        }
     }
 }
 
@@ -61,6 +154,8 @@ impl Default for CollectionConfig {
             supply_cap: None,
             tx_window: 0,
             permitted_drift: 0,
+            transferability: None,
+            payment_ledger: None,
         }
     }
 }
@@ -94,6 +189,14 @@ impl CollectionConfig {
         self.supply_cap.clone()
     }
 
+    pub fn transferability(&self) -> Transferability {
+        self.transferability.unwrap_or_default()
+    }
+
+    pub fn payment_ledger(&self) -> Option<Principal> {
+        self.payment_ledger
+    }
+
     pub fn metadata(&self) -> CollectionMetadata {
         CollectionMetadata {
             icrc7_name: self.name.clone(),
@@ -104,6 +207,130 @@ impl CollectionConfig {
             icrc7_image: self.image.clone(),
             icrc7_total_supply: get_total_supply(),
             icrc7_supply_cap: self.supply_cap.clone(),
+            icrc7_transferability: self.transferability(),
+            icrc7_payment_ledger: self.payment_ledger,
+        }
+    }
+
+    pub fn update(&mut self, arg: UpdateCollectionConfigArgs) -> Result<(), ConfigError> {
+        if let Some(name) = &arg.name {
+            if name.trim().is_empty() {
+                return Err(ConfigError::InvalidName);
+            }
+        }
+        if let Some(Some(royalties)) = arg.royalties {
+            if royalties > MAX_ROYALTIES {
+                return Err(ConfigError::InvalidRoyalties {
+                    max_royalties: MAX_ROYALTIES,
+                });
+            }
+        }
+        if let Some(tx_window) = arg.tx_window {
+            if tx_window > MAX_TX_WINDOW {
+                return Err(ConfigError::InvalidTxWindow {
+                    max_tx_window: MAX_TX_WINDOW,
+                });
+            }
+        }
+        if let Some(permitted_drift) = arg.permitted_drift {
+            if permitted_drift > MAX_PERMITTED_DRIFT {
+                return Err(ConfigError::InvalidPermittedDrift {
+                    max_permitted_drift: MAX_PERMITTED_DRIFT,
+                });
+            }
+        }
+        if let Some(Some(supply_cap)) = arg.supply_cap {
+            let total_supply = get_total_supply();
+            if supply_cap < total_supply {
+                return Err(ConfigError::SupplyCapBelowTotalSupply { total_supply });
+            }
+        }
+
+        if let Some(name) = arg.name {
+            self.name = name;
+        }
+        if let Some(description) = arg.description {
+            self.description = description;
+        }
+        if let Some(image) = arg.image {
+            self.image = image;
+        }
+        if let Some(royalties) = arg.royalties {
+            self.royalties = royalties;
+        }
+        if let Some(royalty_recipient) = arg.royalty_recipient {
+            self.royalty_recipient = royalty_recipient;
+        }
+        if let Some(supply_cap) = arg.supply_cap {
+            self.supply_cap = supply_cap;
+        }
+        if let Some(tx_window) = arg.tx_window {
+            self.tx_window = tx_window;
+        }
+        if let Some(permitted_drift) = arg.permitted_drift {
+            self.permitted_drift = permitted_drift;
+        }
+        if arg.transferability.is_some() {
+            self.transferability = arg.transferability;
+        }
+        if let Some(payment_ledger) = arg.payment_ledger {
+            self.payment_ledger = payment_ledger;
+        }
+
+        Ok(())
+    }
+}
+
+/// Optional features, a toggle left as `None` keeps its default.
+#[derive(CandidType, Serialize, Deserialize, Clone, Default)]
+pub struct Features {
+    pub metrics: Option<bool>,
+}
+
+impl Storable for Features {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Features {
+    pub fn metrics(&self) -> bool {
+        self.metrics.unwrap_or(true)
+    }
+
+    pub fn update(&mut self, toggles: Features) {
+        if toggles.metrics.is_some() {
+            self.metrics = toggles.metrics;
+        }
+    }
+}
+
+pub fn features() -> Features {
+    FEATURES.with(|f| f.borrow().get().clone())
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Transferability {
+    #[default]
+    Transferable,
+    /// Never moves, the owner may still burn it.
+    Soulbound,
+    /// Never moves, only a minter may burn it.
+    BurnOnlyByIssuer,
+}
+
+impl Transferability {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Transferability::Transferable => "Transferable",
+            Transferability::Soulbound => "Soulbound",
+            Transferability::BurnOnlyByIssuer => "BurnOnlyByIssuer",
         }
     }
 }
@@ -116,6 +343,10 @@ pub struct Token {
     pub image: Option<Vec<u8>>,
     pub description: Option<String>,
     pub approvals: Vec<Approval>,
+    /// Overrides the collection policy when set.
+    pub transferability: Option<Transferability>,
+    /// Latest rental, kept after it lapses until replaced or the token moves.
+    pub user: Option<Rental>,
 }
 
 impl Token {
@@ -134,21 +365,39 @@ impl Token {
             let value = self.description.as_ref().unwrap().clone();
             metadata.push(("Description".into(), ICRC1MetadataValue::Text(value)))
         }
+        metadata.push((
+            "Transferability".into(),
+            ICRC1MetadataValue::Text(self.transferability().as_str().into()),
+        ));
         metadata
     }
 
+    pub fn transferability(&self) -> Transferability {
+        match self.transferability {
+            Some(transferability) => transferability,
+            None => CONFIG.with(|c| c.borrow().get().transferability()),
+        }
+    }
+
+    pub fn is_transferable(&self) -> bool {
+        self.transferability() == Transferability::Transferable
+    }
+
+    /// The rental still running at `current_time`, if any.
+    pub fn rental(&self, current_time: u64) -> Option<Rental> {
+        self.user
+            .clone()
+            .filter(|rental| rental.is_active(current_time))
+    }
+
     pub fn owner(&self) -> ICRCAccount {
         self.owner.clone()
     }
 
     pub fn approval_check(&self, current_time: u64, account: &ICRCAccount) -> bool {
         for approval in self.approvals.iter() {
-            if approval.account == *account {
-                if approval.expires_at.is_none() {
-                    return true;
-                } else if approval.expires_at >= Some(current_time) {
-                    return true;
-                }
+            if approval.account == *account && approval.is_active(current_time) {
+                return true;
             }
         }
         false
@@ -162,6 +411,16 @@ impl Token {
         if self.owner == approval.account {
             ic_cdk::trap("Self Approve")
         }
+        if !self.is_transferable() {
+            return Err(ApprovalError::NonTransferable {
+                tokens_ids: vec![self.id],
+            });
+        }
+        if is_locked(&self.id) {
+            return Err(ApprovalError::TokenLocked {
+                tokens_ids: vec![self.id],
+            });
+        }
         if *caller != self.owner {
             return Err(ApprovalError::Unauthorized {
                 tokens_ids: vec![self.id],
@@ -181,6 +440,21 @@ impl Token {
         if self.owner == to {
             ic_cdk::trap("Self Transfer")
         }
+        if is_frozen(&self.id) {
+            return Err(TransferError::TokenFrozen {
+                tokens_ids: vec![self.id],
+            });
+        }
+        if !self.is_transferable() {
+            return Err(TransferError::NonTransferable {
+                tokens_ids: vec![self.id],
+            });
+        }
+        if is_locked(&self.id) {
+            return Err(TransferError::TokenLocked {
+                tokens_ids: vec![self.id],
+            });
+        }
         if self.owner != *caller && !self.approval_check(permitted_time, caller) {
             return Err(TransferError::Unauthorized {
                 tokens_ids: vec![self.id],
@@ -188,6 +462,7 @@ impl Token {
         } else {
             self.owner = to;
             self.approvals.clear();
+            self.user = None;
             return Ok(());
         }
     }
@@ -197,11 +472,14 @@ impl Storable for Token {
     const BOUND: Bound = Bound::Unbounded;
 
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+        std::borrow::Cow::Owned(encode_versioned(TOKEN_VERSION, &self))
     }
 
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
+        match split_versioned(&bytes) {
+            (0..=TOKEN_VERSION, payload) => Decode!(payload, Self).unwrap(),
+            (version, _) => panic!("Unknown token version {}", version),
+        }
     }
 }
 
@@ -223,8 +501,12 @@ pub fn id_validity_check(ids: &Vec<u128>) {
     }
 }
 
+/// An entry of the bounded log written before config changes were recorded,
+/// moved into `TRANSFER_LOG` by the migration to version 4. Entries are never
+/// rewritten in place, the version byte would push one at the 200 byte bound
+/// over it and trap, unversioned ones decode as version 0.
 #[derive(CandidType, Serialize, Deserialize)]
-pub struct TransferLog {
+pub struct LegacyTransferLog {
     pub id: u128,
     pub at: u64,
     pub memo: Option<Vec<u8>>,
@@ -232,21 +514,128 @@ pub struct TransferLog {
     pub to: ICRCAccount,
 }
 
-impl Storable for TransferLog {
+impl Storable for LegacyTransferLog {
     const BOUND: Bound = Bound::Bounded {
         max_size: 200,
         is_fixed_size: false,
     };
 
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+        std::borrow::Cow::Owned(encode_versioned(LEGACY_TRANSFER_LOG_VERSION, &self))
     }
 
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap()
+        match split_versioned(&bytes) {
+            (0..=LEGACY_TRANSFER_LOG_VERSION, payload) => Decode!(payload, Self).unwrap(),
+            (version, _) => panic!("Unknown transfer log version {}", version),
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub enum LogOp {
+    /// `from` is the caller, which may be a spender rather than the owner.
+    Transfer {
+        id: u128,
+        from: ICRCAccount,
+        to: ICRCAccount,
+    },
+    /// `from` is the caller, a minter for burn-only-by-issuer tokens.
+    Burn { id: u128, from: ICRCAccount },
+    ConfigUpdate {
+        by: Principal,
+        changes: Box<UpdateCollectionConfigArgs>,
+    },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct TransferLog {
+    pub at: u64,
+    pub memo: Option<Vec<u8>>,
+    /// Transaction the entry belongs to, `None` for entries moved from the
+    /// legacy log, which did not record it.
+    pub tx_index: Option<u128>,
+    pub op: LogOp,
+}
+
+impl From<LegacyTransferLog> for TransferLog {
+    fn from(log: LegacyTransferLog) -> Self {
+        Self {
+            at: log.at,
+            memo: log.memo,
+            tx_index: None,
+            op: LogOp::Transfer {
+                id: log.id,
+                from: log.from,
+                to: log.to,
+            },
+        }
     }
 }
 
+impl Storable for TransferLog {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(encode_versioned(TRANSFER_LOG_VERSION, &self))
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        match split_versioned(&bytes) {
+            (TRANSFER_LOG_VERSION, payload) => Decode!(payload, Self).unwrap(),
+            (version, _) => panic!("Unknown transfer log version {}", version),
+        }
+    }
+}
+
+/// Number of log entries, counting those not yet moved from the legacy log.
+pub fn transfer_log_len() -> u64 {
+    let legacy = LEGACY_TRANSFER_LOG.with(|logs| logs.borrow().len());
+    let next = TRANSFER_LOG.with(|logs| logs.borrow().last_key_value().map(|(index, _)| index + 1));
+
+    legacy.max(next.unwrap_or_default())
+}
+
+/// The migration moves legacy entries from the back, every index below the
+/// legacy length is still there.
+pub fn get_transfer_log(index: u64) -> Option<TransferLog> {
+    if index < LEGACY_TRANSFER_LOG.with(|logs| logs.borrow().len()) {
+        return LEGACY_TRANSFER_LOG.with(|logs| logs.borrow().get(index).map(TransferLog::from));
+    }
+
+    TRANSFER_LOG.with(|logs| logs.borrow().get(&index))
+}
+
+/// Entries moved from the legacy log come first and are the only ones
+/// without a transaction index, returns how many there are.
+pub fn legacy_log_len() -> u64 {
+    let (mut low, mut high) = (0, transfer_log_len());
+
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match get_transfer_log(mid) {
+            Some(log) if log.tx_index.is_none() => low = mid + 1,
+            _ => high = mid,
+        }
+    }
+
+    low
+}
+
+/// Appends an entry for the transaction in progress and returns its index.
+pub fn log_transaction(op: LogOp, memo: Option<Vec<u8>>) -> u64 {
+    let log = TransferLog {
+        at: ic_cdk::api::time(),
+        memo,
+        tx_index: Some(TRANSACTION_ID.with(|id| *id.borrow().get())),
+        op,
+    };
+    let index = transfer_log_len();
+
+    TRANSFER_LOG.with(|logs| logs.borrow_mut().insert(index, log));
+    index
+}
+
 pub fn increment_tx_id() -> u128 {
     TRANSACTION_ID.with(|id| {
         let mut id = id.borrow_mut();
@@ -256,6 +645,31 @@ pub fn increment_tx_id() -> u128 {
     })
 }
 
+/// Stores a freshly minted token and returns the transaction id of the mint.
+pub fn insert_minted(token: Token) -> u128 {
+    increment_total_supply();
+    let token_id = token.id;
+    let to = token.owner.clone();
+    TOKENS.with(|tokens| tokens.borrow_mut().insert(token_id, token));
+    record_owner(&token_id);
+    history::record(token_id, TokenEventKind::Mint { to });
+    increment_tx_id()
+}
+
+/// An account packed as `[owner length, owner padded to 29 bytes, effective
+/// subaccount]`, for use in stable map keys.
+pub type AccountKey = [u8; 62];
+
+pub fn account_key(account: &ICRCAccount) -> AccountKey {
+    let owner = account.owner();
+    let owner = owner.as_slice();
+    let mut bytes = [0u8; 62];
+    bytes[0] = owner.len() as u8;
+    bytes[1..1 + owner.len()].copy_from_slice(owner);
+    bytes[30..].copy_from_slice(&account.effective_subaccount().0);
+    bytes
+}
+
 pub fn increment_total_supply() {
     TOTAL_SUPPLY.with(|s| {
         let mut s = s.borrow_mut();
@@ -264,6 +678,14 @@ pub fn increment_total_supply() {
     })
 }
 
+pub fn decrement_total_supply() {
+    TOTAL_SUPPLY.with(|s| {
+        let mut s = s.borrow_mut();
+        let current_supply = *s.get();
+        s.set(current_supply - 1).unwrap();
+    })
+}
+
 pub fn get_total_supply() -> u128 {
     TOTAL_SUPPLY.with(|s| s.borrow().get().clone())
 }
@@ -275,16 +697,27 @@ pub fn tx_deduplication_check(
     id: u128,
     caller: &ICRCAccount,
     to: &ICRCAccount,
-) -> Option<usize> {
-    TRANSFER_LOG.with(|log_ref| {
-        log_ref.borrow().iter().position(|log| {
-            log.at > permitted_past_time
-                && log.id == id
-                && log.at == created_at_time
-                && log.memo == *memo
-                && log.from == *caller
-                && log.to == *to
-        })
+) -> Option<u64> {
+    (0..transfer_log_len()).find(|index| match get_transfer_log(*index) {
+        Some(TransferLog {
+            at,
+            memo: log_memo,
+            op:
+                LogOp::Transfer {
+                    id: log_id,
+                    from,
+                    to: log_to,
+                },
+            ..
+        }) => {
+            at > permitted_past_time
+                && log_id == id
+                && at == created_at_time
+                && log_memo == *memo
+                && from == *caller
+                && log_to == *to
+        }
+        _ => false,
     })
 }
 
@@ -316,4 +749,16 @@ impl Approval {
             account,
         }
     }
+
+    pub fn is_active(&self, current_time: u64) -> bool {
+        is_unexpired(self.expires_at, current_time)
+    }
+}
+
+/// `None` never expires, otherwise valid up to and including `expires_at`.
+pub fn is_unexpired(expires_at: Option<u64>, current_time: u64) -> bool {
+    match expires_at {
+        None => true,
+        Some(expires_at) => expires_at >= current_time,
+    }
 }
@@ -0,0 +1,473 @@
+use crate::{
+    errors::SubscriptionError,
+    history::{TokenEvent, TokenEventKind},
+    receiver::is_canister,
+    roles::{has_role, revoke_role, Role},
+    state::{DEAD_LETTERS, SUBSCRIPTIONS, SUBSCRIPTION_ID, SUBSCRIPTION_QUEUE, TOKEN_EVENTS},
+    types::SubscribeArgs,
+};
+use b3_utils::{
+    ledger::ICRCAccount,
+    memory::types::{Bound, Storable},
+};
+use candid::{CandidType, Decode, Encode, Principal};
+use serde_derive::{Deserialize, Serialize};
+use std::{cell::RefCell, collections::BTreeSet, time::Duration};
+
+/// Implemented by subscribers as `(vec TokenEvent) -> ()`.
+const ON_EVENTS: &str = "icrc7_on_events";
+/// Most events delivered in a single call.
+const BATCH_SIZE: usize = 100;
+/// Failed attempts a batch gets before it is dead-lettered.
+const MAX_ATTEMPTS: u32 = 6;
+/// First retry delay, doubled on every further failure.
+const RETRY_BASE: Duration = Duration::from_secs(5);
+const MAX_BACKOFF: Duration = Duration::from_secs(3_600);
+pub const MAX_SUBSCRIPTIONS: u64 = 100;
+/// Keeps a single canister from taking every subscription slot.
+pub const MAX_SUBSCRIPTIONS_PER_SUBSCRIBER: u64 = 10;
+
+thread_local! {
+    /// Subscriptions with a call out, so a timer slice does not send the
+    /// same batch twice.
+    static IN_FLIGHT: RefCell<BTreeSet<u64>> = RefCell::default();
+    /// Next delivery slice and when it fires, replaced by an earlier one.
+    static DELIVERY_TIMER: RefCell<Option<(ic_cdk_timers::TimerId, u64)>> = RefCell::default();
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventType {
+    Mint,
+    Transfer,
+    Approval,
+    Burn,
+}
+
+impl From<&TokenEventKind> for EventType {
+    fn from(kind: &TokenEventKind) -> Self {
+        match kind {
+            TokenEventKind::Mint { .. } => EventType::Mint,
+            TokenEventKind::Transfer { .. } => EventType::Transfer,
+            TokenEventKind::Approval { .. } => EventType::Approval,
+            TokenEventKind::Burn { .. } => EventType::Burn,
+        }
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Subscription {
+    pub id: u64,
+    pub subscriber: Principal,
+    pub events: Vec<EventType>,
+    /// Only events of these tokens, `None` for every token.
+    pub token_ids: Option<Vec<u128>>,
+    /// Only events this account takes part in, `None` for every account.
+    pub account: Option<ICRCAccount>,
+    pub created_at: u64,
+    pub delivered: u64,
+    pub dead_lettered: u64,
+    /// Failed attempts at the current batch.
+    pub attempts: u32,
+    pub next_attempt_at: u64,
+    pub last_error: Option<String>,
+}
+
+impl Storable for Subscription {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Subscription {
+    fn matches(&self, event: &TokenEvent) -> bool {
+        if !self.events.contains(&EventType::from(&event.kind)) {
+            return false;
+        }
+        if let Some(token_ids) = &self.token_ids {
+            if !token_ids.contains(&event.token_id) {
+                return false;
+            }
+        }
+        match &self.account {
+            None => true,
+            Some(account) => match &event.kind {
+                TokenEventKind::Mint { to } => to == account,
+                TokenEventKind::Transfer { from, to, .. } => from == account || to == account,
+                TokenEventKind::Approval { owner, spender, .. } => {
+                    owner == account || spender == account
+                }
+                TokenEventKind::Burn { from } => from == account,
+            },
+        }
+    }
+}
+
+/// Event a subscriber gave up on after `MAX_ATTEMPTS` failed deliveries.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct DeadLetter {
+    pub position: u64,
+    pub reason: String,
+    pub at: u64,
+}
+
+impl Storable for DeadLetter {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+pub fn subscription(id: &u64) -> Option<Subscription> {
+    SUBSCRIPTIONS.with(|s| s.borrow().get(id))
+}
+
+pub fn subscriptions(prev: Option<u64>, take: Option<u128>) -> Vec<Subscription> {
+    let take = take.unwrap_or(100) as usize;
+    let start = prev.map_or(0, |prev| prev + 1);
+
+    SUBSCRIPTIONS.with(|s| {
+        s.borrow()
+            .range(start..)
+            .map(|(_, subscription)| subscription)
+            .take(take)
+            .collect()
+    })
+}
+
+/// Events waiting to be delivered to a subscription.
+pub fn pending(id: u64) -> u64 {
+    SUBSCRIPTION_QUEUE.with(|q| q.borrow().range((id, 0)..=(id, u64::MAX)).count() as u64)
+}
+
+pub fn dead_letters(id: u64, prev: Option<u64>, take: Option<u128>) -> Vec<DeadLetter> {
+    let take = take.unwrap_or(100) as usize;
+    let start = prev.map_or(0, |prev| prev + 1);
+
+    DEAD_LETTERS.with(|d| {
+        d.borrow()
+            .range((id, start)..=(id, u64::MAX))
+            .map(|(_, letter)| letter)
+            .take(take)
+            .collect()
+    })
+}
+
+fn save(subscription: Subscription) {
+    SUBSCRIPTIONS.with(|s| s.borrow_mut().insert(subscription.id, subscription));
+}
+
+/// Subscription the caller may change, its own or any for an admin.
+fn own_subscription(id: u64) -> Result<Subscription, SubscriptionError> {
+    let subscription = subscription(&id).ok_or(SubscriptionError::NoSubscription)?;
+    let caller = ic_cdk::caller();
+
+    if subscription.subscriber != caller && !has_role(&caller, Role::Admin) {
+        return Err(SubscriptionError::Unauthorized);
+    }
+
+    Ok(subscription)
+}
+
+/// Ids are never handed out twice, so a delivery still out for a removed
+/// subscription can not land on a new one.
+fn next_subscription_id() -> u64 {
+    SUBSCRIPTION_ID.with(|i| {
+        let mut i = i.borrow_mut();
+        let id = *i.get();
+        i.set(id + 1).unwrap();
+        id
+    })
+}
+
+/// Only canisters holding `Role::Subscriber` are subscribed, by themselves
+/// or by an admin. Each canister holds at most
+/// `MAX_SUBSCRIPTIONS_PER_SUBSCRIBER` of them.
+pub fn subscribe(arg: SubscribeArgs) -> Result<u64, SubscriptionError> {
+    let caller = ic_cdk::caller();
+    let subscriber = arg.subscriber.unwrap_or(caller);
+
+    if subscriber != caller && !has_role(&caller, Role::Admin) {
+        return Err(SubscriptionError::Unauthorized);
+    }
+    if !is_canister(&subscriber) {
+        return Err(SubscriptionError::NotCanister);
+    }
+    if !has_role(&subscriber, Role::Subscriber) {
+        return Err(SubscriptionError::Unauthorized);
+    }
+    if arg.events.is_empty() {
+        return Err(SubscriptionError::NoEvents);
+    }
+    if SUBSCRIPTIONS.with(|s| s.borrow().len()) >= MAX_SUBSCRIPTIONS {
+        return Err(SubscriptionError::TooManySubscriptions {
+            max: MAX_SUBSCRIPTIONS,
+        });
+    }
+    let held = SUBSCRIPTIONS.with(|s| {
+        s.borrow()
+            .iter()
+            .filter(|(_, subscription)| subscription.subscriber == subscriber)
+            .count() as u64
+    });
+    if held >= MAX_SUBSCRIPTIONS_PER_SUBSCRIBER {
+        return Err(SubscriptionError::SubscriberLimitReached {
+            max: MAX_SUBSCRIPTIONS_PER_SUBSCRIBER,
+        });
+    }
+
+    let id = next_subscription_id();
+    save(Subscription {
+        id,
+        subscriber,
+        events: arg.events,
+        token_ids: arg.token_ids,
+        account: arg.account,
+        created_at: ic_cdk::api::time(),
+        delivered: 0,
+        dead_lettered: 0,
+        attempts: 0,
+        next_attempt_at: 0,
+        last_error: None,
+    });
+
+    Ok(id)
+}
+
+/// Drops the subscription along with its undelivered and dead-lettered
+/// events.
+pub fn unsubscribe(id: u64) -> Result<(), SubscriptionError> {
+    own_subscription(id)?;
+    remove(id);
+
+    Ok(())
+}
+
+fn remove(id: u64) {
+    SUBSCRIPTIONS.with(|s| s.borrow_mut().remove(&id));
+    for key in queued(id, usize::MAX) {
+        SUBSCRIPTION_QUEUE.with(|q| q.borrow_mut().remove(&(id, key)));
+    }
+    let letters: Vec<(u64, u64)> = DEAD_LETTERS.with(|d| {
+        d.borrow()
+            .range((id, 0)..=(id, u64::MAX))
+            .map(|(key, _)| key)
+            .collect()
+    });
+    for key in letters {
+        DEAD_LETTERS.with(|d| d.borrow_mut().remove(&key));
+    }
+}
+
+/// Admin only. Drops every subscription of `subscriber` and takes its
+/// `Role::Subscriber`, so it gets no further calls and can not subscribe
+/// again. Returns how many subscriptions were dropped.
+pub fn evict(subscriber: Principal) -> u64 {
+    let ids: Vec<u64> = SUBSCRIPTIONS.with(|s| {
+        s.borrow()
+            .iter()
+            .filter(|(_, subscription)| subscription.subscriber == subscriber)
+            .map(|(id, _)| id)
+            .collect()
+    });
+    for id in ids.iter() {
+        remove(*id);
+    }
+    if has_role(&subscriber, Role::Subscriber) {
+        revoke_role(subscriber, Role::Subscriber);
+    }
+
+    ids.len() as u64
+}
+
+/// Queues dead-lettered events for delivery again, returns how many.
+pub fn redeliver(id: u64) -> Result<u64, SubscriptionError> {
+    own_subscription(id)?;
+
+    let positions: Vec<u64> = DEAD_LETTERS.with(|d| {
+        d.borrow()
+            .range((id, 0)..=(id, u64::MAX))
+            .map(|((_, position), _)| position)
+            .collect()
+    });
+    for position in positions.iter() {
+        DEAD_LETTERS.with(|d| d.borrow_mut().remove(&(id, *position)));
+        SUBSCRIPTION_QUEUE.with(|q| q.borrow_mut().insert((id, *position), ()));
+    }
+    if !positions.is_empty() {
+        schedule(Duration::ZERO);
+    }
+
+    Ok(positions.len() as u64)
+}
+
+/// Queues a freshly logged event for every subscription it matches. Only
+/// sets a timer, delivery never holds up the call that caused the event.
+pub fn enqueue(event: &TokenEvent) {
+    let matching: Vec<u64> = SUBSCRIPTIONS.with(|s| {
+        s.borrow()
+            .iter()
+            .filter(|(_, subscription)| subscription.matches(event))
+            .map(|(id, _)| id)
+            .collect()
+    });
+
+    for id in matching.iter() {
+        SUBSCRIPTION_QUEUE.with(|q| q.borrow_mut().insert((*id, event.position), ()));
+    }
+    if !matching.is_empty() {
+        schedule(Duration::ZERO);
+    }
+}
+
+/// Oldest queued event positions of a subscription.
+fn queued(id: u64, take: usize) -> Vec<u64> {
+    SUBSCRIPTION_QUEUE.with(|q| {
+        q.borrow()
+            .range((id, 0)..=(id, u64::MAX))
+            .map(|((_, position), _)| position)
+            .take(take)
+            .collect()
+    })
+}
+
+fn schedule(delay: Duration) {
+    let at = ic_cdk::api::time() + delay.as_nanos() as u64;
+
+    DELIVERY_TIMER.with(|t| {
+        let mut t = t.borrow_mut();
+        if t.is_some_and(|(_, scheduled)| scheduled <= at) {
+            return;
+        }
+        if let Some((timer, _)) = t.take() {
+            ic_cdk_timers::clear_timer(timer);
+        }
+        *t = Some((ic_cdk_timers::set_timer(delay, run_pending), at));
+    });
+}
+
+/// Timer slice, sends the next batch of every subscription that is not
+/// backing off and sets a timer for the earliest one that is.
+fn run_pending() {
+    DELIVERY_TIMER.with(|t| t.borrow_mut().take());
+
+    let now = ic_cdk::api::time();
+    let mut next_at: Option<u64> = None;
+    let subscriptions: Vec<Subscription> = SUBSCRIPTIONS.with(|s| {
+        s.borrow()
+            .iter()
+            .map(|(_, subscription)| subscription)
+            .collect()
+    });
+
+    for subscription in subscriptions {
+        if IN_FLIGHT.with(|f| f.borrow().contains(&subscription.id)) {
+            continue;
+        }
+        // events keep queueing for a subscriber whose role was revoked
+        if !has_role(&subscription.subscriber, Role::Subscriber) {
+            continue;
+        }
+        let positions = queued(subscription.id, BATCH_SIZE);
+        if positions.is_empty() {
+            continue;
+        }
+        if subscription.next_attempt_at > now {
+            let at = subscription.next_attempt_at;
+            next_at = Some(next_at.map_or(at, |next_at| next_at.min(at)));
+            continue;
+        }
+
+        IN_FLIGHT.with(|f| f.borrow_mut().insert(subscription.id));
+        ic_cdk::spawn(deliver(subscription, positions));
+    }
+
+    if let Some(at) = next_at {
+        schedule(Duration::from_nanos(at.saturating_sub(now)));
+    }
+}
+
+/// The call waits for the subscriber's reply without a deadline, and a
+/// canister with a call out can not be stopped, so a subscriber that never
+/// replies holds up upgrades until it is stopped or uninstalled by its own
+/// controllers. Calls only go to holders of `Role::Subscriber`, which is
+/// meant for canisters the admins trust to reply. Admins `evict` a subscriber
+/// that does not, which keeps any further calls from going out.
+async fn deliver(subscription: Subscription, positions: Vec<u64>) {
+    let events: Vec<TokenEvent> = TOKEN_EVENTS.with(|e| {
+        let e = e.borrow();
+        positions
+            .iter()
+            .filter_map(|position| e.get(position))
+            .collect()
+    });
+
+    let result: Result<(), _> = ic_cdk::call(subscription.subscriber, ON_EVENTS, (events,)).await;
+
+    IN_FLIGHT.with(|f| f.borrow_mut().remove(&subscription.id));
+
+    // unsubscribed while the call was out
+    let mut subscription = match self::subscription(&subscription.id) {
+        None => return,
+        Some(subscription) => subscription,
+    };
+    let now = ic_cdk::api::time();
+
+    match result {
+        Ok(()) => {
+            for position in positions.iter() {
+                SUBSCRIPTION_QUEUE.with(|q| q.borrow_mut().remove(&(subscription.id, *position)));
+            }
+            subscription.delivered += positions.len() as u64;
+            subscription.attempts = 0;
+            subscription.next_attempt_at = 0;
+        }
+        Err((_, msg)) if subscription.attempts + 1 >= MAX_ATTEMPTS => {
+            for position in positions.iter() {
+                SUBSCRIPTION_QUEUE.with(|q| q.borrow_mut().remove(&(subscription.id, *position)));
+                DEAD_LETTERS.with(|d| {
+                    d.borrow_mut().insert(
+                        (subscription.id, *position),
+                        DeadLetter {
+                            position: *position,
+                            reason: msg.clone(),
+                            at: now,
+                        },
+                    )
+                });
+            }
+            subscription.dead_lettered += positions.len() as u64;
+            subscription.attempts = 0;
+            subscription.next_attempt_at = 0;
+            subscription.last_error = Some(msg);
+        }
+        Err((_, msg)) => {
+            let backoff = RETRY_BASE
+                .saturating_mul(2u32.saturating_pow(subscription.attempts))
+                .min(MAX_BACKOFF);
+            subscription.attempts += 1;
+            subscription.next_attempt_at = now + backoff.as_nanos() as u64;
+            subscription.last_error = Some(msg);
+        }
+    }
+    save(subscription);
+
+    schedule_all();
+}
+
+/// Timers do not survive upgrades, queued events are picked up again.
+pub fn schedule_all() {
+    if SUBSCRIPTION_QUEUE.with(|q| !q.borrow().is_empty()) {
+        schedule(Duration::ZERO);
+    }
+}
@@ -0,0 +1,668 @@
+use crate::{
+    errors::SwapError,
+    market::move_token,
+    pause::pause_state,
+    payments::{balance_of, collect_into, ledger_fee, transfer},
+    state::{SWAPS, SWAP_ID, TOKENS},
+    types::{DepositSwapArgs, ProposeSwapArgs, TransferArgs},
+};
+use b3_utils::{
+    call::InterCall,
+    ledger::ICRCAccount,
+    memory::types::{Bound, Storable},
+    Subaccount,
+};
+use candid::{CandidType, Decode, Encode, Nat, Principal, Reserved};
+use serde_derive::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, BTreeSet},
+    time::Duration,
+};
+
+/// Most assets a single side of a swap may put up.
+pub const MAX_SWAP_ASSETS: usize = 10;
+/// Longest a swap may stay open, 30 days in nanoseconds.
+pub const MAX_SWAP_DURATION: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+/// Most open swaps a single principal may have proposed.
+pub const MAX_OPEN_SWAPS_PER_PROPOSER: u64 = 10;
+/// Delay before releases that failed are retried.
+const RELEASE_RETRY: Duration = Duration::from_secs(60);
+/// First byte of every swap escrow subaccount.
+const ESCROW_DOMAIN: u8 = 1;
+
+thread_local! {
+    /// Swaps with a deposit or release in flight.
+    static BUSY: RefCell<BTreeSet<u64>> = RefCell::default();
+    /// Open swaps by proposer, kept in step by `save` and rebuilt after
+    /// upgrades by `schedule_all`.
+    static OPEN_SWAPS: RefCell<BTreeSet<(Principal, u64)>> = RefCell::default();
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum SwapAsset {
+    /// A token of an ICRC-7 collection, this one or another. Other
+    /// collections are trusted to report ownership honestly.
+    Nft {
+        collection: Principal,
+        token_id: u128,
+    },
+    /// An amount on an ICRC-1 ledger. The fee of the final transfer out of
+    /// escrow is taken from it.
+    Fungible { ledger: Principal, amount: u128 },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct SwapSide {
+    pub account: ICRCAccount,
+    /// Canister account holding this side's assets, tokens of other
+    /// collections have to be transferred here before depositing.
+    pub escrow: ICRCAccount,
+    pub assets: Vec<SwapAsset>,
+    /// Parallel to `assets`, set once the asset sits in escrow.
+    pub escrowed: Vec<bool>,
+    /// Parallel to `assets`, set once the asset left escrow again.
+    pub released: Vec<bool>,
+}
+
+impl SwapSide {
+    fn new(swap_id: u64, side: u8, account: ICRCAccount, assets: Vec<SwapAsset>) -> Self {
+        let len = assets.len();
+
+        SwapSide {
+            account,
+            escrow: ICRCAccount::new(ic_cdk::id(), Some(escrow_subaccount(swap_id, side))),
+            assets,
+            escrowed: vec![false; len],
+            released: vec![false; len],
+        }
+    }
+
+    fn is_escrowed(&self) -> bool {
+        self.escrowed.iter().all(|e| *e)
+    }
+
+    fn is_released(&self) -> bool {
+        self.escrowed
+            .iter()
+            .zip(self.released.iter())
+            .all(|(escrowed, released)| !escrowed || *released)
+    }
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwapStatus {
+    /// Waiting for both sides to deposit.
+    Open,
+    /// Everything is escrowed, each side is being delivered to the other.
+    Settling,
+    Settled,
+    /// Cancelled or expired, escrowed assets are going back.
+    Refunding,
+    Refunded,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct Swap {
+    pub id: u64,
+    pub proposer: SwapSide,
+    pub counterparty: SwapSide,
+    pub created_at: u64,
+    pub expires_at: u64,
+    pub status: SwapStatus,
+}
+
+impl Storable for Swap {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl Swap {
+    fn side_mut(&mut self, account: &ICRCAccount) -> Option<&mut SwapSide> {
+        if self.proposer.account == *account {
+            Some(&mut self.proposer)
+        } else if self.counterparty.account == *account {
+            Some(&mut self.counterparty)
+        } else {
+            None
+        }
+    }
+
+    fn is_party(&self, principal: &Principal) -> bool {
+        self.proposer.account.owner() == *principal
+            || self.counterparty.account.owner() == *principal
+    }
+}
+
+/// `[ESCROW_DOMAIN, side, 0.., swap_id]`, never collides with other
+/// subaccounts of the canister.
+pub fn escrow_subaccount(swap_id: u64, side: u8) -> Subaccount {
+    let mut bytes = [0u8; 32];
+    bytes[0] = ESCROW_DOMAIN;
+    bytes[1] = side;
+    bytes[24..].copy_from_slice(&swap_id.to_be_bytes());
+    Subaccount(bytes)
+}
+
+pub fn swap(id: &u64) -> Option<Swap> {
+    SWAPS.with(|s| s.borrow().get(id))
+}
+
+pub fn swaps(prev: Option<u64>, take: Option<u128>) -> Vec<Swap> {
+    let take = take.unwrap_or(100) as usize;
+
+    SWAPS.with(|s| {
+        let s = s.borrow();
+        match prev {
+            None => s.iter().map(|(_, swap)| swap).take(take).collect(),
+            Some(prev) => s
+                .range(prev..)
+                .filter(|(id, _)| *id != prev)
+                .map(|(_, swap)| swap)
+                .take(take)
+                .collect(),
+        }
+    })
+}
+
+fn save(swap: &Swap) {
+    index_open(swap);
+    SWAPS.with(|s| s.borrow_mut().insert(swap.id, swap.clone()));
+}
+
+fn index_open(swap: &Swap) {
+    let key = (swap.proposer.account.owner(), swap.id);
+
+    OPEN_SWAPS.with(|o| match swap.status {
+        SwapStatus::Open => o.borrow_mut().insert(key),
+        _ => o.borrow_mut().remove(&key),
+    });
+}
+
+fn open_swaps_of(proposer: Principal) -> u64 {
+    OPEN_SWAPS.with(|o| {
+        o.borrow()
+            .range((proposer, 0)..=(proposer, u64::MAX))
+            .count() as u64
+    })
+}
+
+fn next_swap_id() -> u64 {
+    SWAP_ID.with(|s| {
+        let mut s = s.borrow_mut();
+        let id = *s.get();
+        s.set(id + 1).unwrap();
+        id
+    })
+}
+
+fn try_begin(id: u64) -> bool {
+    BUSY.with(|b| b.borrow_mut().insert(id))
+}
+
+fn end(id: &u64) {
+    BUSY.with(|b| b.borrow_mut().remove(id));
+}
+
+fn validate_assets(assets: &[SwapAsset]) -> Result<(), SwapError> {
+    if assets.is_empty() || assets.len() > MAX_SWAP_ASSETS {
+        return Err(SwapError::InvalidSwap);
+    }
+    for (i, asset) in assets.iter().enumerate() {
+        if assets[..i].contains(asset) {
+            return Err(SwapError::InvalidSwap);
+        }
+        let valid = match asset {
+            SwapAsset::Nft {
+                collection,
+                token_id,
+            } if *collection == ic_cdk::id() => {
+                TOKENS.with(|tokens| tokens.borrow().contains_key(token_id))
+            }
+            SwapAsset::Nft { .. } => true,
+            SwapAsset::Fungible { amount, .. } => *amount > 0,
+        };
+        if !valid {
+            return Err(SwapError::InvalidSwap);
+        }
+    }
+    Ok(())
+}
+
+pub fn propose(arg: ProposeSwapArgs) -> Result<u64, SwapError> {
+    let proposer = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let now = ic_cdk::api::time();
+
+    if proposer == arg.counterparty {
+        return Err(SwapError::InvalidSwap);
+    }
+    if arg.expires_at <= now || arg.expires_at > now + MAX_SWAP_DURATION {
+        return Err(SwapError::InvalidSwap);
+    }
+    validate_assets(&arg.give)?;
+    validate_assets(&arg.take)?;
+    if open_swaps_of(proposer.owner()) >= MAX_OPEN_SWAPS_PER_PROPOSER {
+        return Err(SwapError::TooManySwaps {
+            max: MAX_OPEN_SWAPS_PER_PROPOSER,
+        });
+    }
+
+    let id = next_swap_id();
+    let swap = Swap {
+        id,
+        proposer: SwapSide::new(id, 0, proposer, arg.give),
+        counterparty: SwapSide::new(id, 1, arg.counterparty, arg.take),
+        created_at: now,
+        expires_at: arg.expires_at,
+        status: SwapStatus::Open,
+    };
+    save(&swap);
+    schedule_expiry(&swap);
+
+    Ok(id)
+}
+
+/// Escrows every asset of the caller's side that is not escrowed yet.
+/// Once both sides are complete the swap settles right away.
+pub async fn deposit(arg: DepositSwapArgs) -> Result<SwapStatus, SwapError> {
+    let depositor = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let swap = swap(&arg.swap_id).ok_or(SwapError::NoSwap)?;
+
+    if swap.status != SwapStatus::Open {
+        return Err(SwapError::NotOpen);
+    }
+    if swap.expires_at <= ic_cdk::api::time() {
+        return Err(SwapError::Expired);
+    }
+    if !try_begin(swap.id) {
+        return Err(SwapError::Busy);
+    }
+
+    let result = escrow_side(swap.id, &depositor).await;
+    end(&swap.id);
+    result?;
+
+    let mut swap = self::swap(&arg.swap_id).unwrap();
+    // past expiry the timer refunds whatever made it into escrow
+    if swap.status == SwapStatus::Open
+        && swap.expires_at > ic_cdk::api::time()
+        && swap.proposer.is_escrowed()
+        && swap.counterparty.is_escrowed()
+    {
+        swap.status = SwapStatus::Settling;
+        save(&swap);
+        release(swap.id).await;
+    }
+
+    Ok(self::swap(&arg.swap_id).unwrap().status)
+}
+
+async fn escrow_side(swap_id: u64, depositor: &ICRCAccount) -> Result<(), SwapError> {
+    let side = swap(&swap_id)
+        .unwrap()
+        .side_mut(depositor)
+        .cloned()
+        .ok_or(SwapError::Unauthorized)?;
+
+    for (index, asset) in side.assets.iter().enumerate() {
+        if side.escrowed[index] {
+            continue;
+        }
+
+        escrow(asset, depositor, &side.escrow).await?;
+
+        // the swap may have expired while the call was in flight, the
+        // refund picks this asset up as long as it is marked
+        let mut swap = swap(&swap_id).unwrap();
+        swap.side_mut(depositor).unwrap().escrowed[index] = true;
+        save(&swap);
+    }
+
+    Ok(())
+}
+
+async fn escrow(
+    asset: &SwapAsset,
+    depositor: &ICRCAccount,
+    escrow: &ICRCAccount,
+) -> Result<(), SwapError> {
+    match asset {
+        SwapAsset::Nft {
+            collection,
+            token_id,
+        } if *collection == ic_cdk::id() => {
+            if pause_state().transfers {
+                return Err(SwapError::DepositFailed {
+                    msg: "Transfers Paused".into(),
+                });
+            }
+            let owner = TOKENS.with(|tokens| tokens.borrow().get(token_id).map(|t| t.owner));
+            if owner.as_ref() != Some(depositor) {
+                return Err(SwapError::Unauthorized);
+            }
+            move_token(*token_id, depositor, escrow.clone())
+                .map(|_| ())
+                .map_err(|_| SwapError::DepositFailed {
+                    msg: format!("Token {} cannot move", token_id),
+                })
+        }
+        SwapAsset::Nft {
+            collection,
+            token_id,
+        } => {
+            let owner: ICRCAccount = InterCall::from(*collection)
+                .call("icrc7_owner_of", *token_id)
+                .await
+                .map_err(|e| SwapError::DepositFailed { msg: e.to_string() })?;
+
+            if owner != *escrow {
+                return Err(SwapError::DepositFailed {
+                    msg: format!("Token {} is not in escrow", token_id),
+                });
+            }
+            Ok(())
+        }
+        SwapAsset::Fungible { ledger, amount } => {
+            collect_into(*ledger, depositor.clone(), escrow.subaccount(), *amount)
+                .await
+                .map_err(|msg| SwapError::DepositFailed { msg })
+        }
+    }
+}
+
+/// Sends an escrowed asset to `to`.
+async fn release_asset(asset: &SwapAsset, escrow: &ICRCAccount, to: ICRCAccount) -> bool {
+    match asset {
+        SwapAsset::Nft {
+            collection,
+            token_id,
+        } if *collection == ic_cdk::id() => move_token(*token_id, escrow, to).is_ok(),
+        SwapAsset::Nft {
+            collection,
+            token_id,
+        } => {
+            let arg = TransferArgs {
+                spender_subaccount: escrow.subaccount(),
+                from: escrow.clone(),
+                to,
+                token_ids: vec![*token_id],
+                memo: None,
+                created_at_time: None,
+                is_atomic: None,
+            };
+            let result: Result<Result<Nat, Reserved>, _> = InterCall::from(*collection)
+                .call("icrc7_transfer", arg)
+                .await;
+
+            matches!(result, Ok(Ok(_)))
+        }
+        SwapAsset::Fungible { ledger, amount } => match ledger_fee(*ledger).await {
+            Ok(fee) if *amount > fee => transfer(*ledger, escrow.subaccount(), to, amount - fee)
+                .await
+                .is_ok(),
+            // nothing left to send once the fee is paid
+            Ok(_) => true,
+            Err(_) => false,
+        },
+    }
+}
+
+/// Tokens of other collections reach escrow before `deposit` marks them, a
+/// refund asks their collection so that none is left behind. Returns false
+/// if a collection could not be asked.
+async fn mark_unrecorded_deposits(id: u64) -> bool {
+    let swap = swap(&id).unwrap();
+    let mut answered = true;
+
+    for (is_proposer, side) in [(true, &swap.proposer), (false, &swap.counterparty)] {
+        for (index, asset) in side.assets.iter().enumerate() {
+            let (collection, token_id) = match asset {
+                SwapAsset::Nft {
+                    collection,
+                    token_id,
+                } if !side.escrowed[index] && *collection != ic_cdk::id() => (collection, token_id),
+                _ => continue,
+            };
+
+            let owner: Result<ICRCAccount, _> = InterCall::from(*collection)
+                .call("icrc7_owner_of", *token_id)
+                .await;
+
+            match owner {
+                Ok(owner) if owner == side.escrow => {
+                    let mut current = self::swap(&id).unwrap();
+                    match is_proposer {
+                        true => current.proposer.escrowed[index] = true,
+                        false => current.counterparty.escrowed[index] = true,
+                    }
+                    save(&current);
+                }
+                Ok(_) => {}
+                Err(_) => answered = false,
+            }
+        }
+    }
+
+    answered
+}
+
+/// Indexes of the escrowed assets of `side` that are not all in its escrow
+/// account any more. Fails if a collection or ledger could not be asked.
+async fn missing_assets(side: &SwapSide) -> Result<Vec<usize>, String> {
+    let mut missing = Vec::new();
+    // amount owed and the assets making it up, per ledger
+    let mut required: BTreeMap<Principal, (u128, Vec<usize>)> = BTreeMap::new();
+
+    for (index, asset) in side.assets.iter().enumerate() {
+        if !side.escrowed[index] {
+            continue;
+        }
+        match asset {
+            SwapAsset::Nft {
+                collection,
+                token_id,
+            } if *collection == ic_cdk::id() => {
+                let owner = TOKENS.with(|tokens| tokens.borrow().get(token_id).map(|t| t.owner));
+                if owner.as_ref() != Some(&side.escrow) {
+                    missing.push(index);
+                }
+            }
+            SwapAsset::Nft {
+                collection,
+                token_id,
+            } => {
+                let owner: ICRCAccount = InterCall::from(*collection)
+                    .call("icrc7_owner_of", *token_id)
+                    .await
+                    .map_err(|e| e.to_string())?;
+                if owner != side.escrow {
+                    missing.push(index);
+                }
+            }
+            SwapAsset::Fungible { ledger, amount } => {
+                let (total, indexes) = required.entry(*ledger).or_default();
+                *total = total.saturating_add(*amount);
+                indexes.push(index);
+            }
+        }
+    }
+
+    for (ledger, (total, indexes)) in required {
+        if balance_of(ledger, &side.escrow).await? < total {
+            missing.extend(indexes);
+        }
+    }
+
+    Ok(missing)
+}
+
+/// Checks everything escrowed for a settling swap is still there before the
+/// first asset goes out. Other collections are asked again, a token they no
+/// longer report in escrow calls the swap off and is not counted as escrowed
+/// for the refund. Returns false if a collection or ledger could not be asked.
+async fn check_escrow(id: u64) -> bool {
+    let swap = swap(&id).unwrap();
+    if swap.proposer.released.contains(&true) || swap.counterparty.released.contains(&true) {
+        return true;
+    }
+
+    let (proposer, counterparty) = match (
+        missing_assets(&swap.proposer).await,
+        missing_assets(&swap.counterparty).await,
+    ) {
+        (Ok(proposer), Ok(counterparty)) => (proposer, counterparty),
+        _ => return false,
+    };
+    if proposer.is_empty() && counterparty.is_empty() {
+        return true;
+    }
+
+    let mut swap = self::swap(&id).unwrap();
+    for (side, missing) in [
+        (&mut swap.proposer, proposer),
+        (&mut swap.counterparty, counterparty),
+    ] {
+        for index in missing {
+            // fungible assets stay marked, their refund is retried until
+            // the ledger pays it out
+            if matches!(side.assets[index], SwapAsset::Nft { .. }) {
+                side.escrowed[index] = false;
+            }
+        }
+    }
+    swap.status = SwapStatus::Refunding;
+    save(&swap);
+
+    true
+}
+
+/// Settles or refunds every escrowed asset not released yet. Whatever fails
+/// is retried by a timer until the swap is done.
+async fn release(id: u64) {
+    if !try_begin(id) {
+        return;
+    }
+
+    if swap(&id).unwrap().status == SwapStatus::Settling && !check_escrow(id).await {
+        schedule_release(id, RELEASE_RETRY);
+        return end(&id);
+    }
+
+    let settling = swap(&id).unwrap().status == SwapStatus::Settling;
+    let answered = settling || mark_unrecorded_deposits(id).await;
+    let swap = swap(&id).unwrap();
+
+    for (is_proposer, side) in [(true, &swap.proposer), (false, &swap.counterparty)] {
+        let to = match (settling, is_proposer) {
+            (true, true) => swap.counterparty.account.clone(),
+            (true, false) => swap.proposer.account.clone(),
+            (false, _) => side.account.clone(),
+        };
+
+        for (index, asset) in side.assets.iter().enumerate() {
+            if !side.escrowed[index] || side.released[index] {
+                continue;
+            }
+
+            if release_asset(asset, &side.escrow, to.clone()).await {
+                let mut current = self::swap(&id).unwrap();
+                match is_proposer {
+                    true => current.proposer.released[index] = true,
+                    false => current.counterparty.released[index] = true,
+                }
+                save(&current);
+            }
+        }
+    }
+
+    let mut swap = self::swap(&id).unwrap();
+    if answered && swap.proposer.is_released() && swap.counterparty.is_released() {
+        swap.status = match settling {
+            true => SwapStatus::Settled,
+            false => SwapStatus::Refunded,
+        };
+        save(&swap);
+    } else {
+        schedule_release(id, RELEASE_RETRY);
+    }
+
+    end(&id);
+}
+
+/// Either party may call off a swap that has not settled yet.
+pub async fn cancel(id: u64) -> Result<(), SwapError> {
+    let mut swap = swap(&id).ok_or(SwapError::NoSwap)?;
+
+    if !swap.is_party(&ic_cdk::caller()) {
+        return Err(SwapError::Unauthorized);
+    }
+    if swap.status != SwapStatus::Open {
+        return Err(SwapError::NotOpen);
+    }
+    if BUSY.with(|b| b.borrow().contains(&id)) {
+        return Err(SwapError::Busy);
+    }
+
+    swap.status = SwapStatus::Refunding;
+    save(&swap);
+    release(id).await;
+
+    Ok(())
+}
+
+fn schedule_expiry(swap: &Swap) {
+    let id = swap.id;
+    let delay = swap.expires_at.saturating_sub(ic_cdk::api::time());
+
+    ic_cdk_timers::set_timer(Duration::from_nanos(delay), move || {
+        ic_cdk::spawn(expire(id))
+    });
+}
+
+fn schedule_release(id: u64, delay: Duration) {
+    ic_cdk_timers::set_timer(delay, move || ic_cdk::spawn(release(id)));
+}
+
+async fn expire(id: u64) {
+    let mut swap = match swap(&id) {
+        Some(swap) if swap.status == SwapStatus::Open => swap,
+        _ => return,
+    };
+
+    // a deposit in flight either completes the swap or is refunded next round
+    if BUSY.with(|b| b.borrow().contains(&id)) {
+        return schedule_expiry_retry(id);
+    }
+
+    swap.status = SwapStatus::Refunding;
+    save(&swap);
+    release(id).await;
+}
+
+fn schedule_expiry_retry(id: u64) {
+    ic_cdk_timers::set_timer(RELEASE_RETRY, move || ic_cdk::spawn(expire(id)));
+}
+
+/// Timers do not survive upgrades, pending expiries and releases are
+/// scheduled again.
+pub fn schedule_all() {
+    SWAPS.with(|s| {
+        for (id, swap) in s.borrow().iter() {
+            index_open(&swap);
+            match swap.status {
+                SwapStatus::Open => schedule_expiry(&swap),
+                SwapStatus::Settling | SwapStatus::Refunding => {
+                    schedule_release(id, Duration::ZERO)
+                }
+                SwapStatus::Settled | SwapStatus::Refunded => {}
+            }
+        }
+    });
+}
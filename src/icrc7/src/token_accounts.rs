@@ -0,0 +1,122 @@
+use crate::{
+    errors::WithdrawError,
+    locks::{lock_token, unlock_token, Lock},
+    pause::is_frozen,
+    payments::{payment_ledger, transfer},
+    state::{BURNED_TOKENS, TOKENS},
+    types::WithdrawArgs,
+};
+use b3_utils::{
+    ledger::ICRCAccount,
+    memory::types::{Bound, Storable},
+    Subaccount,
+};
+use candid::{CandidType, Decode, Encode};
+use serde_derive::{Deserialize, Serialize};
+
+/// First byte of every token-bound subaccount.
+const TOKEN_ACCOUNT_DOMAIN: u8 = 2;
+
+/// The last owner of a burned token, who keeps access to its account.
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct BurnedToken {
+    pub owner: ICRCAccount,
+    pub burned_at: u64,
+}
+
+impl Storable for BurnedToken {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+/// `[TOKEN_ACCOUNT_DOMAIN, 0.., token_id]`, stable for the life of the token
+/// so whatever it holds moves along with ownership.
+pub fn token_subaccount(token_id: u128) -> Subaccount {
+    let mut bytes = [0u8; 32];
+    bytes[0] = TOKEN_ACCOUNT_DOMAIN;
+    bytes[16..].copy_from_slice(&token_id.to_be_bytes());
+    Subaccount(bytes)
+}
+
+/// The canister account a token holds fungible assets in, `None` for tokens
+/// that were never minted.
+pub fn token_account(token_id: &u128) -> Option<ICRCAccount> {
+    if !TOKENS.with(|tokens| tokens.borrow().contains_key(token_id)) && burned(token_id).is_none() {
+        return None;
+    }
+
+    Some(ICRCAccount::new(
+        ic_cdk::id(),
+        Some(token_subaccount(*token_id)),
+    ))
+}
+
+pub fn burned(token_id: &u128) -> Option<BurnedToken> {
+    BURNED_TOKENS.with(|b| b.borrow().get(token_id))
+}
+
+/// Called as a token is burned, whatever its account still holds or receives
+/// later stays withdrawable by `owner`. Minting the id again hands the
+/// account to the new token.
+pub fn record_burn(token_id: u128, owner: ICRCAccount) {
+    let burned = BurnedToken {
+        owner,
+        burned_at: ic_cdk::api::time(),
+    };
+
+    BURNED_TOKENS.with(|b| b.borrow_mut().insert(token_id, burned));
+}
+
+/// Sends `amount` out of the token-bound account on `arg.ledger`, so assets
+/// left on an earlier payment ledger or sent on any other ledger can be taken
+/// out too. The ledger fee is paid on top by the token account. The token
+/// stays locked while the ledger call is in flight so it cannot change hands
+/// mid-withdrawal.
+pub async fn withdraw(arg: WithdrawArgs) -> Result<(), WithdrawError> {
+    let caller = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let ledger = match arg.ledger {
+        Some(ledger) => ledger,
+        None => payment_ledger().ok_or(WithdrawError::NoPaymentLedger)?,
+    };
+
+    let owner = match TOKENS.with(|tokens| tokens.borrow().get(&arg.token_id)) {
+        Some(token) => token.owner,
+        None => match burned(&arg.token_id) {
+            Some(burned) => burned.owner,
+            None => ic_cdk::trap("Invalid Id"),
+        },
+    };
+    if owner != caller {
+        return Err(WithdrawError::Unauthorized {
+            tokens_ids: vec![arg.token_id],
+        });
+    }
+    if is_frozen(&arg.token_id) {
+        return Err(WithdrawError::TokenFrozen {
+            tokens_ids: vec![arg.token_id],
+        });
+    }
+    if !lock_token(arg.token_id, Lock::Withdrawal) {
+        return Err(WithdrawError::TokenLocked {
+            tokens_ids: vec![arg.token_id],
+        });
+    }
+
+    let result = transfer(
+        ledger,
+        Some(token_subaccount(arg.token_id)),
+        arg.to,
+        arg.amount,
+    )
+    .await;
+    unlock_token(&arg.token_id);
+
+    result.map_err(|msg| WithdrawError::WithdrawFailed { msg })
+}
@@ -1,6 +1,19 @@
+use crate::{
+    auction::AuctionKind,
+    cycles::TopUp,
+    rentals::RentalTerms,
+    reveal::TokenMetadata,
+    roles::Role,
+    sale::{Eligibility, PriceSchedule},
+    state::{CollectionConfig, Features, Transferability},
+    subscriptions::EventType,
+    swaps::SwapAsset,
+    vouchers::Voucher,
+};
 use b3_utils::{ledger::ICRCAccount, Subaccount};
-use candid::CandidType;
-use serde_derive::Deserialize;
+use candid::{CandidType, Principal};
+use serde_bytes::ByteBuf;
+use serde_derive::{Deserialize, Serialize};
 
 #[derive(CandidType)]
 pub struct CollectionMetadata {
@@ -12,6 +25,8 @@ pub struct CollectionMetadata {
     pub icrc7_image: Option<String>,
     pub icrc7_total_supply: u128,
     pub icrc7_supply_cap: Option<u128>,
+    pub icrc7_transferability: Transferability,
+    pub icrc7_payment_ledger: Option<Principal>,
 }
 
 #[derive(CandidType)]
@@ -31,6 +46,36 @@ pub struct TransferArgs {
     pub is_atomic: Option<bool>,
 }
 
+#[derive(CandidType, Deserialize)]
+pub struct TransferAndCallArgs {
+    pub spender_subaccount: Option<Subaccount>,
+    pub from: ICRCAccount,
+    pub to: ICRCAccount,
+    pub token_ids: Vec<u128>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+    /// Handed to the recipient's `icrc7_on_received` as is.
+    pub data: Vec<u8>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct SubscribeArgs {
+    /// Canister notified, the caller when `None`. Only admins may subscribe
+    /// another canister.
+    pub subscriber: Option<Principal>,
+    pub events: Vec<EventType>,
+    pub token_ids: Option<Vec<u128>>,
+    pub account: Option<ICRCAccount>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct SetCyclesConfigArgs {
+    /// Balance below which an alert is raised, `None` turns monitoring off.
+    pub low_water_mark: Option<u128>,
+    /// Refill while below the low-water mark, `None` only alerts.
+    pub top_up: Option<TopUp>,
+}
+
 #[derive(CandidType, Deserialize)]
 pub struct ApprovalArgs {
     pub from_subaccount: Option<Subaccount>,
@@ -48,4 +93,249 @@ pub struct MintArgs {
     pub description: Option<String>,
     pub image: Option<Vec<u8>>,
     pub to: ICRCAccount,
+    pub transferability: Option<Transferability>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct BurnArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_ids: Vec<u128>,
+}
+
+/// Every flag left as `None` keeps its current value.
+#[derive(CandidType, Deserialize)]
+pub struct PauseArgs {
+    pub transfers: Option<bool>,
+    pub approvals: Option<bool>,
+    pub mints: Option<bool>,
+    pub burns: Option<bool>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct RoleArgs {
+    pub principal: Principal,
+    pub role: Role,
+}
+
+/// Every field left as `None` keeps its current value, the optional ones are
+/// cleared with `Some(None)`.
+#[derive(CandidType, Serialize, Deserialize, Clone)]
+pub struct UpdateCollectionConfigArgs {
+    pub name: Option<String>,
+    pub description: Option<Option<String>>,
+    pub image: Option<Option<String>>,
+    pub royalties: Option<Option<u16>>,
+    pub royalty_recipient: Option<Option<ICRCAccount>>,
+    pub supply_cap: Option<Option<u128>>,
+    pub tx_window: Option<u64>,
+    pub permitted_drift: Option<u64>,
+    pub transferability: Option<Transferability>,
+    pub payment_ledger: Option<Option<Principal>>,
+}
+
+impl UpdateCollectionConfigArgs {
+    /// Touches the name, description or image.
+    pub fn changes_metadata(&self) -> bool {
+        self.name.is_some() || self.description.is_some() || self.image.is_some()
+    }
+
+    /// Touches anything besides the metadata.
+    pub fn changes_settings(&self) -> bool {
+        self.royalties.is_some()
+            || self.royalty_recipient.is_some()
+            || self.supply_cap.is_some()
+            || self.tx_window.is_some()
+            || self.permitted_drift.is_some()
+            || self.transferability.is_some()
+            || self.payment_ledger.is_some()
+    }
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct UpgradeArgs {
+    pub config: Option<UpdateCollectionConfigArgs>,
+    pub features: Option<Features>,
+}
+
+/// The canister argument, `Init` on install and `Upgrade` on upgrade.
+#[derive(CandidType, Deserialize)]
+pub enum CollectionArgs {
+    Init(CollectionConfig),
+    Upgrade(Option<UpgradeArgs>),
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct ListArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    pub price: u128,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct BuyArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    /// Guards against the seller changing the price before the call lands.
+    pub price: u128,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct CreateAuctionArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    pub kind: AuctionKind,
+    /// Nanoseconds from now until the auction closes.
+    pub duration: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct BidArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    pub amount: u128,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct MakeOfferArgs {
+    pub from_subaccount: Option<Subaccount>,
+    /// `None` makes a floor bid on any token of the collection.
+    pub token_id: Option<u128>,
+    pub amount: u128,
+    pub expires_at: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct AcceptOfferArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub offer_id: u64,
+    /// Token sold into the offer, required for collection-wide offers.
+    pub token_id: u128,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct ProposeSwapArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub counterparty: ICRCAccount,
+    /// Assets the proposer puts up.
+    pub give: Vec<SwapAsset>,
+    /// Assets the counterparty puts up.
+    pub take: Vec<SwapAsset>,
+    pub expires_at: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct DepositSwapArgs {
+    pub swap_id: u64,
+    pub from_subaccount: Option<Subaccount>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct WithdrawArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    /// ICRC-1 ledger to withdraw from, `None` for the payment ledger.
+    pub ledger: Option<Principal>,
+    pub to: ICRCAccount,
+    pub amount: u128,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct SetUserArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    pub user: ICRCAccount,
+    pub expires_at: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct SetRentalTermsArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    pub terms: Option<RentalTerms>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct RentArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    pub periods: u64,
+    /// Total the renter expects to pay, guards against terms changing.
+    pub price: u128,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct FractionalizeArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    pub shares: u128,
+    /// Price anyone may buy the token out at, on the payment ledger.
+    pub reserve_price: Option<u128>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct FractionArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct StakeArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+    /// Lock duration in nanoseconds.
+    pub duration: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct UnstakeArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub token_id: u128,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct FundRewardsArgs {
+    pub from_subaccount: Option<Subaccount>,
+    pub amount: u128,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct AddMintPhaseArgs {
+    pub name: String,
+    pub eligibility: Eligibility,
+    pub per_account_limit: Option<u32>,
+    pub price: Option<PriceSchedule>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct CommitRevealArgs {
+    pub placeholder: TokenMetadata,
+    /// sha256 over the concatenated `TokenMetadata::hash` of the final
+    /// metadata, in the order it will be uploaded.
+    pub commitment: ByteBuf,
+    pub size: u64,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct RedeemVoucherArgs {
+    pub voucher: Voucher,
+    /// Over `Voucher::digest`, by any registered minter key.
+    pub signature: ByteBuf,
+    pub metadata: TokenMetadata,
+    /// Subaccount of the caller receiving the token, unless the voucher
+    /// names a recipient.
+    pub to_subaccount: Option<Subaccount>,
+    /// Subaccount of the caller paying the price.
+    pub from_subaccount: Option<Subaccount>,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct SelfMintArgs {
+    /// Subaccount of the caller receiving the token.
+    pub to_subaccount: Option<Subaccount>,
+    /// Merkle proof for phases with a Merkle root.
+    pub proof: Option<Vec<ByteBuf>>,
+    /// Subaccount of the caller paying for the mint.
+    pub from_subaccount: Option<Subaccount>,
+    /// Most the caller is willing to pay, guards against the price moving.
+    pub max_price: Option<u128>,
 }
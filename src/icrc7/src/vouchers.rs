@@ -0,0 +1,267 @@
+use crate::{
+    audit::{log_event, AuditEvent},
+    errors::VoucherError,
+    pause::pause_state,
+    payments::{collect, ledger_fee, pay_out, payment_ledger},
+    reveal::TokenMetadata,
+    sale::sale_state,
+    state::{
+        account_key, get_total_supply, insert_minted, Token, CONFIG, MINTER_KEYS, TOKENS,
+        USED_NONCES,
+    },
+    types::RedeemVoucherArgs,
+};
+use b3_utils::{
+    ledger::{raw_sha256, ICRCAccount},
+    memory::types::{Bound, Storable},
+};
+use candid::{CandidType, Decode, Encode};
+use serde_bytes::ByteBuf;
+use serde_derive::{Deserialize, Serialize};
+
+/// Prefix of every signed voucher.
+const VOUCHER_DOMAIN: &[u8] = b"icrc7-voucher";
+
+/// Public key a minting authority signs vouchers with.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub enum MinterKey {
+    /// 32-byte public key.
+    Ed25519(ByteBuf),
+    /// SEC1 encoded public key, compressed or not. Signatures are 64 bytes
+    /// `r || s` with a low `s`.
+    Secp256k1(ByteBuf),
+}
+
+impl Storable for MinterKey {
+    const BOUND: Bound = Bound::Unbounded;
+
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        std::borrow::Cow::Owned(Encode!(&self).unwrap())
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap()
+    }
+}
+
+impl MinterKey {
+    fn is_valid(&self) -> bool {
+        match self {
+            MinterKey::Ed25519(key) => ed25519_key(key).is_some(),
+            MinterKey::Secp256k1(key) => k256::ecdsa::VerifyingKey::from_sec1_bytes(key).is_ok(),
+        }
+    }
+
+    fn verify(&self, digest: &[u8], signature: &[u8]) -> bool {
+        match self {
+            MinterKey::Ed25519(key) => {
+                let signature = match ed25519_dalek::Signature::from_slice(signature) {
+                    Ok(signature) => signature,
+                    Err(_) => return false,
+                };
+                ed25519_key(key).is_some_and(|key| key.verify_strict(digest, &signature).is_ok())
+            }
+            MinterKey::Secp256k1(key) => {
+                use k256::ecdsa::signature::hazmat::PrehashVerifier;
+
+                let signature = match k256::ecdsa::Signature::from_slice(signature) {
+                    Ok(signature) => signature,
+                    Err(_) => return false,
+                };
+                k256::ecdsa::VerifyingKey::from_sec1_bytes(key)
+                    .is_ok_and(|key| key.verify_prehash(digest, &signature).is_ok())
+            }
+        }
+    }
+}
+
+fn ed25519_key(key: &[u8]) -> Option<ed25519_dalek::VerifyingKey> {
+    let key: &[u8; 32] = key.try_into().ok()?;
+    ed25519_dalek::VerifyingKey::from_bytes(key).ok()
+}
+
+/// Signed off-chain by a minting authority, redeemable only by the owner of
+/// `recipient` when one is set.
+#[derive(CandidType, Serialize, Deserialize, Clone, Debug)]
+pub struct Voucher {
+    pub token_id: u128,
+    /// `TokenMetadata::hash` of the metadata the token is minted with.
+    pub metadata_hash: ByteBuf,
+    /// `None` lets anyone redeem to an account of their own.
+    pub recipient: Option<ICRCAccount>,
+    /// Paid on the payment ledger to the treasury, `0` mints for free.
+    pub price: u128,
+    pub expires_at: u64,
+    /// Redeemable once, across all vouchers.
+    pub nonce: u64,
+}
+
+impl Voucher {
+    /// sha256 of what the authority signs: the `icrc7-voucher` prefix, the
+    /// collection canister id prefixed by its length, then the token id, the
+    /// metadata hash, `0` or `1` followed by the recipient's owner length,
+    /// owner padded to 29 bytes and subaccount, the price, the expiry and the
+    /// nonce, integers big-endian.
+    pub fn digest(&self) -> Vec<u8> {
+        let canister = ic_cdk::id();
+        let mut bytes = VOUCHER_DOMAIN.to_vec();
+        bytes.push(canister.as_slice().len() as u8);
+        bytes.extend_from_slice(canister.as_slice());
+        bytes.extend_from_slice(&self.token_id.to_be_bytes());
+        bytes.extend_from_slice(&self.metadata_hash);
+        match &self.recipient {
+            None => bytes.push(0),
+            Some(recipient) => {
+                bytes.push(1);
+                bytes.extend_from_slice(&account_key(recipient));
+            }
+        }
+        bytes.extend_from_slice(&self.price.to_be_bytes());
+        bytes.extend_from_slice(&self.expires_at.to_be_bytes());
+        bytes.extend_from_slice(&self.nonce.to_be_bytes());
+        raw_sha256(&bytes)
+    }
+}
+
+pub fn minter_keys() -> Vec<(u32, MinterKey)> {
+    MINTER_KEYS.with(|k| k.borrow().iter().collect())
+}
+
+pub fn add_minter_key(key: MinterKey) -> Result<u32, VoucherError> {
+    if !key.is_valid() {
+        return Err(VoucherError::InvalidKey);
+    }
+
+    let key_id = MINTER_KEYS.with(|k| {
+        let mut k = k.borrow_mut();
+        let key_id = k.last_key_value().map_or(0, |(id, _)| id + 1);
+        k.insert(key_id, key.clone());
+        key_id
+    });
+    log_event(AuditEvent::MinterKeyAdded { key_id, key });
+
+    Ok(key_id)
+}
+
+pub fn remove_minter_key(key_id: u32) {
+    MINTER_KEYS.with(|k| k.borrow_mut().remove(&key_id));
+    log_event(AuditEvent::MinterKeyRemoved { key_id });
+}
+
+pub fn is_nonce_used(nonce: u64) -> bool {
+    USED_NONCES.with(|n| n.borrow().contains_key(&nonce))
+}
+
+fn set_nonce_used(nonce: u64, used: bool) {
+    USED_NONCES.with(|n| {
+        let mut n = n.borrow_mut();
+        if used {
+            n.insert(nonce, ());
+        } else {
+            n.remove(&nonce);
+        }
+    });
+}
+
+/// Checks what a mint depends on, again after a payment since the token id
+/// may have been taken meanwhile.
+fn check_mintable(token_id: u128) -> Result<(), VoucherError> {
+    if pause_state().mints {
+        return Err(VoucherError::Paused);
+    }
+    let supply_cap = CONFIG.with(|c| c.borrow().get().supply_cap);
+    if supply_cap.is_some_and(|cap| get_total_supply() >= cap) {
+        return Err(VoucherError::SoldOut);
+    }
+    if TOKENS.with(|tokens| tokens.borrow().contains_key(&token_id)) {
+        return Err(VoucherError::TokenExists);
+    }
+    Ok(())
+}
+
+fn mint(token_id: u128, metadata: TokenMetadata, to: ICRCAccount) -> u128 {
+    insert_minted(Token {
+        id: token_id,
+        owner: to,
+        name: metadata.name,
+        image: metadata.image,
+        description: metadata.description,
+        approvals: Vec::new(),
+        transferability: None,
+        user: None,
+    });
+
+    token_id
+}
+
+/// Mints the token a voucher describes once its signature checks out against
+/// a registered minter key. The nonce is spent before any payment is made and
+/// given back when the redemption fails.
+pub async fn redeem(arg: RedeemVoucherArgs) -> Result<u128, VoucherError> {
+    let caller = ic_cdk::caller();
+    let voucher = arg.voucher;
+
+    let to = match &voucher.recipient {
+        Some(recipient) if recipient.owner() != caller => return Err(VoucherError::NotRecipient),
+        Some(recipient) => recipient.clone(),
+        None => ICRCAccount::new(caller, arg.to_subaccount),
+    };
+    if voucher.expires_at <= ic_cdk::api::time() {
+        return Err(VoucherError::Expired);
+    }
+    if arg.metadata.hash() != voucher.metadata_hash.as_slice() {
+        return Err(VoucherError::MetadataMismatch);
+    }
+
+    let digest = voucher.digest();
+    let signed = minter_keys()
+        .iter()
+        .any(|(_, key)| key.verify(&digest, &arg.signature));
+    if !signed {
+        return Err(VoucherError::InvalidSignature);
+    }
+
+    if is_nonce_used(voucher.nonce) {
+        return Err(VoucherError::NonceUsed);
+    }
+    check_mintable(voucher.token_id)?;
+
+    set_nonce_used(voucher.nonce, true);
+
+    if voucher.price == 0 {
+        return Ok(mint(voucher.token_id, arg.metadata, to));
+    }
+
+    let payment = match (payment_ledger(), sale_state().treasury) {
+        (None, _) => Err(VoucherError::NoPaymentLedger),
+        (_, None) => Err(VoucherError::NoTreasury),
+        (Some(ledger), Some(treasury)) => ledger_fee(ledger)
+            .await
+            .map(|fee| (ledger, treasury, fee))
+            .map_err(|msg| VoucherError::PaymentFailed { msg }),
+    };
+    let (ledger, treasury, fee) = match payment {
+        Ok(payment) => payment,
+        Err(e) => {
+            set_nonce_used(voucher.nonce, false);
+            return Err(e);
+        }
+    };
+
+    let buyer = ICRCAccount::new(caller, arg.from_subaccount);
+    if let Err(msg) = collect(ledger, buyer.clone(), voucher.price).await {
+        set_nonce_used(voucher.nonce, false);
+        return Err(VoucherError::PaymentFailed { msg });
+    }
+
+    if let Err(e) = check_mintable(voucher.token_id) {
+        set_nonce_used(voucher.nonce, false);
+        pay_out(ledger, fee, buyer, voucher.price).await;
+        return Err(e);
+    }
+
+    let token_id = mint(voucher.token_id, arg.metadata, to);
+    pay_out(ledger, fee, treasury, voucher.price).await;
+
+    Ok(token_id)
+}
@@ -0,0 +1,258 @@
+use b3_utils::ledger::{ICRC1MetadataValue, ICRC1TransferArgs, ICRC1TransferError, ICRCAccount};
+use candid::{CandidType, Deserialize, Nat, Principal};
+use ic_cdk::{init, post_upgrade, pre_upgrade, query, update};
+use std::{cell::RefCell, collections::BTreeMap};
+
+/// Shares are whole units and move without fees.
+const DECIMALS: u8 = 0;
+const FEE: u64 = 0;
+
+/// Install argument, passed by the collection when it fractionalizes a token.
+#[derive(CandidType, Deserialize)]
+pub struct ShareLedgerArgs {
+    pub name: String,
+    pub symbol: String,
+    pub token_id: u128,
+    /// Receives the whole supply.
+    pub holder: ICRCAccount,
+    pub supply: Nat,
+}
+
+#[derive(CandidType, Deserialize)]
+pub struct BurnFromArgs {
+    pub from: ICRCAccount,
+    pub amount: Nat,
+}
+
+#[derive(CandidType, Deserialize)]
+struct State {
+    name: String,
+    symbol: String,
+    /// The collection, its default account is the minting account.
+    collection: Principal,
+    token_id: u128,
+    balances: BTreeMap<ICRCAccount, Nat>,
+    total_supply: Nat,
+    tx_index: u64,
+}
+
+impl Default for State {
+    fn default() -> Self {
+        State {
+            name: String::new(),
+            symbol: String::new(),
+            collection: Principal::anonymous(),
+            token_id: 0,
+            balances: BTreeMap::new(),
+            total_supply: Nat::from(0_u64),
+            tx_index: 0,
+        }
+    }
+}
+
+thread_local! {
+    static STATE: RefCell<State> = RefCell::default();
+}
+
+/// `None` and the all-zero subaccount are the same account.
+fn normalize(account: &ICRCAccount) -> ICRCAccount {
+    ICRCAccount::new(
+        account.owner(),
+        Some(account.effective_subaccount().clone()),
+    )
+}
+
+fn minting_account() -> ICRCAccount {
+    STATE.with(|s| normalize(&ICRCAccount::new(s.borrow().collection, None)))
+}
+
+fn balance(account: &ICRCAccount) -> Nat {
+    STATE.with(|s| {
+        s.borrow()
+            .balances
+            .get(&normalize(account))
+            .cloned()
+            .unwrap_or_default()
+    })
+}
+
+fn set_balance(account: &ICRCAccount, amount: Nat) {
+    STATE.with(|s| {
+        let balances = &mut s.borrow_mut().balances;
+        if amount == 0_u64 {
+            balances.remove(&normalize(account));
+        } else {
+            balances.insert(normalize(account), amount);
+        }
+    });
+}
+
+fn next_tx_index() -> Nat {
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        s.tx_index += 1;
+        Nat::from(s.tx_index)
+    })
+}
+
+#[init]
+fn init(arg: ShareLedgerArgs) {
+    STATE.with(|s| {
+        *s.borrow_mut() = State {
+            name: arg.name,
+            symbol: arg.symbol,
+            collection: ic_cdk::caller(),
+            token_id: arg.token_id,
+            total_supply: arg.supply.clone(),
+            ..Default::default()
+        }
+    });
+    set_balance(&arg.holder, arg.supply);
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    STATE.with(|s| ic_cdk::storage::stable_save((&*s.borrow(),)).unwrap());
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    let (state,): (State,) = ic_cdk::storage::stable_restore().unwrap();
+    STATE.with(|s| *s.borrow_mut() = state);
+}
+
+#[query]
+fn icrc1_name() -> String {
+    STATE.with(|s| s.borrow().name.clone())
+}
+
+#[query]
+fn icrc1_symbol() -> String {
+    STATE.with(|s| s.borrow().symbol.clone())
+}
+
+#[query]
+fn icrc1_decimals() -> u8 {
+    DECIMALS
+}
+
+#[query]
+fn icrc1_fee() -> Nat {
+    Nat::from(FEE)
+}
+
+/// The ICRC-1 fields, plus the collection and token the shares stand for.
+#[query]
+fn icrc1_metadata() -> Vec<(String, ICRC1MetadataValue)> {
+    STATE.with(|s| {
+        let s = s.borrow();
+        vec![
+            (
+                "icrc1:name".into(),
+                ICRC1MetadataValue::Text(s.name.clone()),
+            ),
+            (
+                "icrc1:symbol".into(),
+                ICRC1MetadataValue::Text(s.symbol.clone()),
+            ),
+            (
+                "icrc1:decimals".into(),
+                ICRC1MetadataValue::Nat(Nat::from(DECIMALS)),
+            ),
+            ("icrc1:fee".into(), ICRC1MetadataValue::Nat(Nat::from(FEE))),
+            (
+                "icrc7:collection".into(),
+                ICRC1MetadataValue::Text(s.collection.to_text()),
+            ),
+            (
+                "icrc7:token_id".into(),
+                ICRC1MetadataValue::Nat(Nat::from(s.token_id)),
+            ),
+        ]
+    })
+}
+
+#[query]
+fn icrc1_total_supply() -> Nat {
+    STATE.with(|s| s.borrow().total_supply.clone())
+}
+
+#[query]
+fn icrc1_minting_account() -> Option<ICRCAccount> {
+    Some(minting_account())
+}
+
+#[query]
+fn icrc1_balance_of(account: ICRCAccount) -> Nat {
+    balance(&account)
+}
+
+#[query]
+fn icrc1_supported_standards() -> Vec<(String, String)> {
+    vec![("ICRC-1".into(), "https://github.com/dfinity/ICRC-1".into())]
+}
+
+/// Transfers from the minting account mint. Shares can not be burned by
+/// sending them to the minting account, the collection burns them when they
+/// are redeemed or claimed, so the supply always matches its count.
+/// `memo` and `created_at_time` are not used for deduplication.
+#[update]
+fn icrc1_transfer(arg: ICRC1TransferArgs) -> Result<Nat, ICRC1TransferError> {
+    let from = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+    let minting_account = minting_account();
+
+    if arg.fee.as_ref().is_some_and(|fee| *fee != FEE) {
+        return Err(ICRC1TransferError::BadFee {
+            expected_fee: Nat::from(FEE),
+        });
+    }
+    if normalize(&arg.to) == minting_account {
+        return Err(ICRC1TransferError::GenericError {
+            error_code: Nat::from(0_u64),
+            message: "Shares are burned by the collection".into(),
+        });
+    }
+
+    if normalize(&from) == minting_account {
+        STATE.with(|s| {
+            let mut s = s.borrow_mut();
+            s.total_supply = s.total_supply.clone() + arg.amount.clone();
+        });
+    } else {
+        let from_balance = balance(&from);
+        if from_balance < arg.amount {
+            return Err(ICRC1TransferError::InsufficientFunds {
+                balance: from_balance,
+            });
+        }
+        set_balance(&from, from_balance - arg.amount.clone());
+    }
+    set_balance(&arg.to, balance(&arg.to) + arg.amount);
+
+    Ok(next_tx_index())
+}
+
+/// Burns `amount` out of `from`, only the collection may call it.
+#[update]
+fn burn_from(arg: BurnFromArgs) -> Result<Nat, ICRC1TransferError> {
+    if ic_cdk::caller() != STATE.with(|s| s.borrow().collection) {
+        ic_cdk::trap("Unauthorized");
+    }
+
+    let from_balance = balance(&arg.from);
+    if from_balance < arg.amount {
+        return Err(ICRC1TransferError::InsufficientFunds {
+            balance: from_balance,
+        });
+    }
+
+    set_balance(&arg.from, from_balance - arg.amount.clone());
+    STATE.with(|s| {
+        let mut s = s.borrow_mut();
+        s.total_supply = s.total_supply.clone() - arg.amount;
+    });
+
+    Ok(next_tx_index())
+}
+
+ic_cdk::export_candid!();
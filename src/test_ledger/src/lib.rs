@@ -0,0 +1,241 @@
+use b3_utils::ledger::{
+    ICRC1TransferArgs, ICRC1TransferError, ICRC2Allowance, ICRC2AllowanceArgs, ICRC2ApproveArgs,
+    ICRC2ApproveError, ICRC2TransferFromArgs, ICRC2TransferFromError, ICRCAccount,
+};
+use candid::{CandidType, Deserialize, Nat, Principal};
+use ic_cdk::api::management_canister::main::{deposit_cycles, CanisterIdRecord};
+use ic_cdk::{query, update};
+use std::{cell::RefCell, collections::BTreeMap};
+
+const FEE: u64 = 10_000;
+
+thread_local! {
+    static BALANCES: RefCell<BTreeMap<ICRCAccount, Nat>> = RefCell::default();
+    static ALLOWANCES: RefCell<BTreeMap<(ICRCAccount, ICRCAccount), Nat>> = RefCell::default();
+    static TX_INDEX: RefCell<u64> = RefCell::default();
+}
+
+/// `None` and the all-zero subaccount are the same account.
+fn normalize(account: &ICRCAccount) -> ICRCAccount {
+    ICRCAccount::new(
+        account.owner(),
+        Some(account.effective_subaccount().clone()),
+    )
+}
+
+fn balance(account: &ICRCAccount) -> Nat {
+    BALANCES.with(|b| {
+        b.borrow()
+            .get(&normalize(account))
+            .cloned()
+            .unwrap_or_default()
+    })
+}
+
+fn set_balance(account: &ICRCAccount, amount: Nat) {
+    BALANCES.with(|b| b.borrow_mut().insert(normalize(account), amount));
+}
+
+fn next_tx_index() -> Nat {
+    TX_INDEX.with(|i| {
+        let mut i = i.borrow_mut();
+        *i += 1;
+        Nat::from(*i)
+    })
+}
+
+fn check_fee(fee: &Option<Nat>) -> Result<(), Nat> {
+    match fee {
+        Some(fee) if *fee != FEE => Err(Nat::from(FEE)),
+        _ => Ok(()),
+    }
+}
+
+#[query]
+fn icrc1_name() -> String {
+    "Test Token".into()
+}
+
+#[query]
+fn icrc1_symbol() -> String {
+    "TEST".into()
+}
+
+#[query]
+fn icrc1_decimals() -> u8 {
+    8
+}
+
+#[query]
+fn icrc1_fee() -> Nat {
+    Nat::from(FEE)
+}
+
+#[query]
+fn icrc1_balance_of(account: ICRCAccount) -> Nat {
+    balance(&account)
+}
+
+#[query]
+fn icrc1_supported_standards() -> Vec<(String, String)> {
+    vec![
+        ("ICRC-1".into(), "https://github.com/dfinity/ICRC-1".into()),
+        ("ICRC-2".into(), "https://github.com/dfinity/ICRC-1".into()),
+    ]
+}
+
+/// Credits `amount` to `to` out of thin air, tests only.
+#[update]
+fn mint(to: ICRCAccount, amount: Nat) -> Nat {
+    set_balance(&to, balance(&to) + amount);
+    next_tx_index()
+}
+
+#[update]
+fn icrc1_transfer(arg: ICRC1TransferArgs) -> Result<Nat, ICRC1TransferError> {
+    let from = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+
+    if let Err(expected_fee) = check_fee(&arg.fee) {
+        return Err(ICRC1TransferError::BadFee { expected_fee });
+    }
+
+    let from_balance = balance(&from);
+    let total = arg.amount.clone() + FEE;
+    if from_balance < total {
+        return Err(ICRC1TransferError::InsufficientFunds {
+            balance: from_balance,
+        });
+    }
+
+    set_balance(&from, from_balance - total);
+    set_balance(&arg.to, balance(&arg.to) + arg.amount);
+
+    Ok(next_tx_index())
+}
+
+#[update]
+fn icrc2_approve(arg: ICRC2ApproveArgs) -> Result<Nat, ICRC2ApproveError> {
+    let from = ICRCAccount::new(ic_cdk::caller(), arg.from_subaccount);
+
+    if let Err(expected_fee) = check_fee(&arg.fee) {
+        return Err(ICRC2ApproveError::BadFee { expected_fee });
+    }
+
+    let from_balance = balance(&from);
+    if from_balance < FEE {
+        return Err(ICRC2ApproveError::InsufficientFunds {
+            balance: from_balance,
+        });
+    }
+
+    let key = (normalize(&from), normalize(&arg.spender));
+    let current = ALLOWANCES.with(|a| a.borrow().get(&key).cloned().unwrap_or_default());
+    if let Some(expected) = arg.expected_allowance {
+        if expected != current {
+            return Err(ICRC2ApproveError::AllowanceChanged {
+                current_allowance: current,
+            });
+        }
+    }
+
+    set_balance(&from, from_balance - FEE);
+    ALLOWANCES.with(|a| a.borrow_mut().insert(key, arg.amount));
+
+    Ok(next_tx_index())
+}
+
+#[query]
+fn icrc2_allowance(arg: ICRC2AllowanceArgs) -> ICRC2Allowance {
+    let key = (normalize(&arg.account), normalize(&arg.spender));
+
+    ICRC2Allowance {
+        allowance: ALLOWANCES.with(|a| a.borrow().get(&key).cloned().unwrap_or_default()),
+        expires_at: None,
+    }
+}
+
+#[update]
+fn icrc2_transfer_from(arg: ICRC2TransferFromArgs) -> Result<Nat, ICRC2TransferFromError> {
+    let spender = ICRCAccount::new(ic_cdk::caller(), arg.spender_subaccount);
+
+    if let Err(expected_fee) = check_fee(&arg.fee) {
+        return Err(ICRC2TransferFromError::BadFee { expected_fee });
+    }
+
+    let total = arg.amount.clone() + FEE;
+
+    let key = (normalize(&arg.from), normalize(&spender));
+    let allowance = ALLOWANCES.with(|a| a.borrow().get(&key).cloned().unwrap_or_default());
+    if allowance < total {
+        return Err(ICRC2TransferFromError::InsufficientAllowance { allowance });
+    }
+
+    let from_balance = balance(&arg.from);
+    if from_balance < total {
+        return Err(ICRC2TransferFromError::InsufficientFunds {
+            balance: from_balance,
+        });
+    }
+
+    ALLOWANCES.with(|a| a.borrow_mut().insert(key, allowance - total.clone()));
+    set_balance(&arg.from, from_balance - total);
+    set_balance(&arg.to, balance(&arg.to) + arg.amount);
+
+    Ok(next_tx_index())
+}
+
+#[derive(CandidType, Deserialize)]
+struct WithdrawFromArgs {
+    spender_subaccount: Option<Vec<u8>>,
+    from: ICRCAccount,
+    to: Principal,
+    amount: Nat,
+    created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Deserialize)]
+enum WithdrawFromError {
+    InsufficientAllowance { allowance: Nat },
+    InsufficientFunds { balance: Nat },
+    FailedToWithdrawFrom { rejection_reason: String },
+}
+
+/// Cycles ledger `withdraw_from`: balances stand for cycles, paid out of the
+/// stand-in's own cycles to the `to` canister.
+#[update]
+async fn withdraw_from(arg: WithdrawFromArgs) -> Result<Nat, WithdrawFromError> {
+    let spender = ICRCAccount::new(ic_cdk::caller(), None);
+    let total = arg.amount.clone() + FEE;
+
+    let key = (normalize(&arg.from), normalize(&spender));
+    let allowance = ALLOWANCES.with(|a| a.borrow().get(&key).cloned().unwrap_or_default());
+    if allowance < total {
+        return Err(WithdrawFromError::InsufficientAllowance { allowance });
+    }
+
+    let from_balance = balance(&arg.from);
+    if from_balance < total {
+        return Err(WithdrawFromError::InsufficientFunds {
+            balance: from_balance,
+        });
+    }
+
+    ALLOWANCES.with(|a| a.borrow_mut().insert(key, allowance - total.clone()));
+    set_balance(&arg.from, from_balance - total);
+
+    let cycles: u128 = arg.amount.0.try_into().unwrap_or(u128::MAX);
+    if let Err((_, rejection_reason)) = deposit_cycles(
+        CanisterIdRecord {
+            canister_id: arg.to,
+        },
+        cycles,
+    )
+    .await
+    {
+        return Err(WithdrawFromError::FailedToWithdrawFrom { rejection_reason });
+    }
+
+    Ok(next_tx_index())
+}
+
+ic_cdk::export_candid!();
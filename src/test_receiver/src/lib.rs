@@ -0,0 +1,50 @@
+use b3_utils::ledger::ICRCAccount;
+use candid::{CandidType, Deserialize};
+use ic_cdk::{query, update};
+use std::cell::RefCell;
+
+/// `data` that makes the hook reject the tokens.
+const REJECT: &[u8] = b"reject";
+
+thread_local! {
+    static RECEIVED: RefCell<Vec<(ICRCAccount, Vec<u128>)>> = RefCell::default();
+    static EVENTS: RefCell<Vec<Event>> = RefCell::default();
+}
+
+/// The part of a notified token event the tests look at, other fields are
+/// skipped when decoding.
+#[derive(CandidType, Deserialize, Clone)]
+struct Event {
+    position: u64,
+    token_id: u128,
+}
+
+#[update]
+fn icrc7_on_received(from: ICRCAccount, token_ids: Vec<u128>, data: Vec<u8>) -> Result<(), String> {
+    if data == REJECT {
+        return Err("Rejected".into());
+    }
+
+    RECEIVED.with(|r| r.borrow_mut().push((from, token_ids)));
+
+    Ok(())
+}
+
+/// Every accepted delivery, oldest first.
+#[query]
+fn received() -> Vec<(ICRCAccount, Vec<u128>)> {
+    RECEIVED.with(|r| r.borrow().clone())
+}
+
+#[update]
+fn icrc7_on_events(events: Vec<Event>) {
+    EVENTS.with(|e| e.borrow_mut().extend(events));
+}
+
+/// Every notified event, in delivery order.
+#[query]
+fn events() -> Vec<Event> {
+    EVENTS.with(|e| e.borrow().clone())
+}
+
+ic_cdk::export_candid!();
@@ -2,6 +2,22 @@ const { HttpAgent, Actor } = require("@dfinity/agent")
 
 const HOST = "http://127.0.0.1:4943/"
 
+const getAgent = (identity) => {
+  const agent = new HttpAgent({
+    host: HOST,
+    identity: identity,
+  })
+
+  agent.fetchRootKey().catch((err) => {
+    console.warn(
+      "Unable to fetch root key. Check to ensure that your local replica is running"
+    )
+    console.error(err)
+  })
+
+  return agent
+}
+
 const getActor = async (canisterId, idlFactory, identity) => {
   if (canisterId === undefined) {
     console.log("canisterId: ", canisterId)
@@ -17,17 +33,7 @@ const getActor = async (canisterId, idlFactory, identity) => {
     console.log("identity:", identity)
   }
 
-  const agent = new HttpAgent({
-    host: HOST,
-    identity: identity,
-  })
-
-  agent.fetchRootKey().catch((err) => {
-    console.warn(
-      "Unable to fetch root key. Check to ensure that your local replica is running"
-    )
-    console.error(err)
-  })
+  const agent = getAgent(identity)
 
   const actor = Actor.createActor(idlFactory, {
     agent: agent,
@@ -38,5 +44,6 @@ const getActor = async (canisterId, idlFactory, identity) => {
 }
 
 module.exports = {
+  getAgent,
   getActor,
 }
@@ -25,9 +25,12 @@
   });
   const ApprovalError = IDL.Variant({
     'GenericError' : IDL.Record({ 'msg' : IDL.Text, 'error_code' : IDL.Nat }),
+    'Paused' : IDL.Null,
     'TemporaryUnavailable' : IDL.Null,
     'Unauthorized' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'NonTransferable' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
     'TooOld' : IDL.Null,
+    'TokenLocked' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
   });
   const Result = IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : ApprovalError });
   const CollectionMetadata = IDL.Record({
@@ -46,14 +49,45 @@
     'Blob' : IDL.Vec(IDL.Nat8),
     'Text' : IDL.Text,
   });
+  const Transferability = IDL.Variant({
+    'Soulbound' : IDL.Null,
+    'BurnOnlyByIssuer' : IDL.Null,
+    'Transferable' : IDL.Null,
+  });
+  const MigrationStatus = IDL.Record({
+    'cursor' : IDL.Opt(IDL.Nat),
+    'schema_version' : IDL.Nat32,
+    'latest_version' : IDL.Nat32,
+  });
   const MintArgs = IDL.Record({
     'id' : IDL.Nat,
     'to' : ICRCAccount,
     'name' : IDL.Text,
     'description' : IDL.Opt(IDL.Text),
     'image' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'transferability' : IDL.Opt(Transferability),
+  });
+  const Role = IDL.Variant({
+    'MetadataUpdater' : IDL.Null,
+    'Pauser' : IDL.Null,
+    'Minter' : IDL.Null,
+    'Admin' : IDL.Null,
+    'Subscriber' : IDL.Null,
+    'Fractionalizer' : IDL.Null,
   });
+  const RoleArgs = IDL.Record({ 'principal' : IDL.Principal, 'role' : Role });
   const Standard = IDL.Record({ 'url' : IDL.Text, 'name' : IDL.Text });
+  const HttpRequest = IDL.Record({
+    'url' : IDL.Text,
+    'method' : IDL.Text,
+    'body' : IDL.Vec(IDL.Nat8),
+    'headers' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
+  });
+  const HttpResponse = IDL.Record({
+    'body' : IDL.Vec(IDL.Nat8),
+    'headers' : IDL.Vec(IDL.Tuple(IDL.Text, IDL.Text)),
+    'status_code' : IDL.Nat16,
+  });
   const TransferArgs = IDL.Record({
     'to' : ICRCAccount,
     'spender_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
@@ -65,35 +99,690 @@
   });
   const TransferError = IDL.Variant({
     'GenericError' : IDL.Record({ 'msg' : IDL.Text, 'error_code' : IDL.Nat }),
+    'Paused' : IDL.Null,
+    'TokenFrozen' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
     'TemporaryUnavailable' : IDL.Null,
     'Duplicate' : IDL.Record({ 'duplicate_of' : IDL.Nat }),
     'Unauthorized' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
     'CreatedInFuture' : IDL.Record({ 'ledger_time' : IDL.Nat64 }),
+    'NonTransferable' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'TokenLocked' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
     'TooOld' : IDL.Null,
   });
   const Result_1 = IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : TransferError });
+  const BurnArgs = IDL.Record({
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'token_ids' : IDL.Vec(IDL.Nat),
+  });
+  const BurnError = IDL.Variant({
+    'GenericError' : IDL.Record({ 'msg' : IDL.Text, 'error_code' : IDL.Nat }),
+    'Paused' : IDL.Null,
+    'TokenFrozen' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'Unauthorized' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'TokenLocked' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+  });
+  const BurnResult = IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : BurnError });
+  const PauseArgs = IDL.Record({
+    'mints' : IDL.Opt(IDL.Bool),
+    'transfers' : IDL.Opt(IDL.Bool),
+    'burns' : IDL.Opt(IDL.Bool),
+    'approvals' : IDL.Opt(IDL.Bool),
+  });
+  const PauseState = IDL.Record({
+    'mints' : IDL.Bool,
+    'transfers' : IDL.Bool,
+    'burns' : IDL.Bool,
+    'approvals' : IDL.Bool,
+  });
+  const UpdateCollectionConfigArgs = IDL.Record({
+    'supply_cap' : IDL.Opt(IDL.Opt(IDL.Nat)),
+    'tx_window' : IDL.Opt(IDL.Nat64),
+    'permitted_drift' : IDL.Opt(IDL.Nat64),
+    'name' : IDL.Opt(IDL.Text),
+    'description' : IDL.Opt(IDL.Opt(IDL.Text)),
+    'royalties' : IDL.Opt(IDL.Opt(IDL.Nat16)),
+    'royalty_recipient' : IDL.Opt(IDL.Opt(ICRCAccount)),
+    'image' : IDL.Opt(IDL.Opt(IDL.Text)),
+    'transferability' : IDL.Opt(Transferability),
+    'payment_ledger' : IDL.Opt(IDL.Opt(IDL.Principal)),
+  });
+  const ConfigError = IDL.Variant({
+    'InvalidPermittedDrift' : IDL.Record({ 'max_permitted_drift' : IDL.Nat64 }),
+    'InvalidName' : IDL.Null,
+    'InvalidTxWindow' : IDL.Record({ 'max_tx_window' : IDL.Nat64 }),
+    'SupplyCapBelowTotalSupply' : IDL.Record({ 'total_supply' : IDL.Nat }),
+    'InvalidRoyalties' : IDL.Record({ 'max_royalties' : IDL.Nat16 }),
+  });
+  const Result_3 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : ConfigError });
+  const ListArgs = IDL.Record({
+    'token_id' : IDL.Nat,
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'price' : IDL.Nat,
+  });
+  const BuyArgs = IDL.Record({
+    'token_id' : IDL.Nat,
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'price' : IDL.Nat,
+  });
+  const MarketError = IDL.Variant({
+    'GenericError' : IDL.Record({ 'msg' : IDL.Text, 'error_code' : IDL.Nat }),
+    'NoPaymentLedger' : IDL.Null,
+    'PaymentFailed' : IDL.Record({ 'msg' : IDL.Text }),
+    'NotListed' : IDL.Null,
+    'Paused' : IDL.Null,
+    'InvalidPrice' : IDL.Null,
+    'TokenFrozen' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'Unauthorized' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'NonTransferable' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'PriceChanged' : IDL.Record({ 'price' : IDL.Nat }),
+    'TokenLocked' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'NoAuction' : IDL.Null,
+    'InvalidAuction' : IDL.Null,
+    'AuctionEnded' : IDL.Null,
+    'AuctionNotEnded' : IDL.Null,
+    'BidTooLow' : IDL.Record({ 'min_bid' : IDL.Nat }),
+    'HasBids' : IDL.Null,
+    'NoOffer' : IDL.Null,
+    'OfferExpired' : IDL.Null,
+    'InsufficientShares' : IDL.Record({ 'balance' : IDL.Nat }),
+    'NotFractionalized' : IDL.Null,
+    'InvalidShares' : IDL.Null,
+    'NoBuyout' : IDL.Null,
+    'BoughtOut' : IDL.Null,
+    'NotBoughtOut' : IDL.Null,
+    'SharesOutstanding' : IDL.Record({ 'outstanding' : IDL.Nat }),
+    'ShareLedgerFailed' : IDL.Record({ 'msg' : IDL.Text }),
+    'PayoutBelowFee' : IDL.Record({ 'fee' : IDL.Nat, 'payout' : IDL.Nat }),
+    'CyclesRequired' : IDL.Record({ 'cycles' : IDL.Nat }),
+    'TokenMoved' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+  });
+  const Result_4 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : MarketError });
+  const Result_5 = IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : MarketError });
+  const AuctionKind = IDL.Variant({
+    'English' : IDL.Record({
+      'reserve_price' : IDL.Nat,
+      'min_increment' : IDL.Nat,
+      'extension' : IDL.Nat64,
+    }),
+    'Dutch' : IDL.Record({
+      'start_price' : IDL.Nat,
+      'reserve_price' : IDL.Nat,
+    }),
+  });
+  const CreateAuctionArgs = IDL.Record({
+    'token_id' : IDL.Nat,
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'kind' : AuctionKind,
+    'duration' : IDL.Nat64,
+  });
+  const MakeOfferArgs = IDL.Record({
+    'token_id' : IDL.Opt(IDL.Nat),
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'amount' : IDL.Nat,
+    'expires_at' : IDL.Opt(IDL.Nat64),
+  });
+  const AcceptOfferArgs = IDL.Record({
+    'token_id' : IDL.Nat,
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'offer_id' : IDL.Nat64,
+  });
+  const Result_6 = IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : MarketError });
+  const Offer = IDL.Record({
+    'id' : IDL.Nat64,
+    'token_id' : IDL.Opt(IDL.Nat),
+    'created_at' : IDL.Nat64,
+    'ledger' : IDL.Principal,
+    'buyer' : ICRCAccount,
+    'amount' : IDL.Nat,
+    'expires_at' : IDL.Opt(IDL.Nat64),
+  });
+  const BidArgs = IDL.Record({
+    'token_id' : IDL.Nat,
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'amount' : IDL.Nat,
+  });
+  const SwapAsset = IDL.Variant({
+    'Nft' : IDL.Record({ 'token_id' : IDL.Nat, 'collection' : IDL.Principal }),
+    'Fungible' : IDL.Record({ 'ledger' : IDL.Principal, 'amount' : IDL.Nat }),
+  });
+  const ProposeSwapArgs = IDL.Record({
+    'give' : IDL.Vec(SwapAsset),
+    'take' : IDL.Vec(SwapAsset),
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'counterparty' : ICRCAccount,
+    'expires_at' : IDL.Nat64,
+  });
+  const DepositSwapArgs = IDL.Record({
+    'swap_id' : IDL.Nat64,
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
+  const SwapError = IDL.Variant({
+    'NotOpen' : IDL.Null,
+    'Busy' : IDL.Null,
+    'NoSwap' : IDL.Null,
+    'DepositFailed' : IDL.Record({ 'msg' : IDL.Text }),
+    'Unauthorized' : IDL.Null,
+    'InvalidSwap' : IDL.Null,
+    'Expired' : IDL.Null,
+    'TooManySwaps' : IDL.Record({ 'max' : IDL.Nat64 }),
+  });
+  const SwapStatus = IDL.Variant({
+    'Refunding' : IDL.Null,
+    'Refunded' : IDL.Null,
+    'Open' : IDL.Null,
+    'Settling' : IDL.Null,
+    'Settled' : IDL.Null,
+  });
+  const Result_7 = IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : SwapError });
+  const Result_8 = IDL.Variant({ 'Ok' : SwapStatus, 'Err' : SwapError });
+  const WithdrawArgs = IDL.Record({
+    'to' : ICRCAccount,
+    'token_id' : IDL.Nat,
+    'ledger' : IDL.Opt(IDL.Principal),
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'amount' : IDL.Nat,
+  });
+  const WithdrawError = IDL.Variant({
+    'WithdrawFailed' : IDL.Record({ 'msg' : IDL.Text }),
+    'TokenFrozen' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'Unauthorized' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'NoPaymentLedger' : IDL.Null,
+    'TokenLocked' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+  });
+  const Result_9 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : WithdrawError });
+  const SetUserArgs = IDL.Record({
+    'token_id' : IDL.Nat,
+    'user' : ICRCAccount,
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'expires_at' : IDL.Nat64,
+  });
+  const RentalError = IDL.Variant({
+    'TokenFrozen' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'PaymentFailed' : IDL.Record({ 'msg' : IDL.Text }),
+    'InvalidRental' : IDL.Null,
+    'PriceChanged' : IDL.Record({ 'price' : IDL.Nat }),
+    'NotForRent' : IDL.Null,
+    'Unauthorized' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'RentalActive' : IDL.Record({ 'expires_at' : IDL.Nat64 }),
+    'NoPaymentLedger' : IDL.Null,
+    'TokenLocked' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+  });
+  const Result_10 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : RentalError });
+  const Rental = IDL.Record({ 'user' : ICRCAccount, 'expires_at' : IDL.Nat64 });
+  const FractionalizeArgs = IDL.Record({
+    'shares' : IDL.Nat,
+    'token_id' : IDL.Nat,
+    'reserve_price' : IDL.Opt(IDL.Nat),
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
+  const FractionArgs = IDL.Record({
+    'token_id' : IDL.Nat,
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
+  const FractionStatus = IDL.Variant({
+    'Active' : IDL.Null,
+    'BoughtOut' : IDL.Null,
+    'Redeeming' : IDL.Null,
+    'BuyingOut' : IDL.Null,
+  });
+  const Fraction = IDL.Record({
+    'status' : FractionStatus,
+    'shares' : IDL.Nat,
+    'token_id' : IDL.Nat,
+    'vault' : ICRCAccount,
+    'reserve_price' : IDL.Opt(IDL.Nat),
+    'outstanding' : IDL.Nat,
+    'created_at' : IDL.Nat64,
+    'issuer' : ICRCAccount,
+    'ledger' : IDL.Opt(IDL.Principal),
+    'share_ledger' : IDL.Principal,
+  });
+  const StakingConfig = IDL.Record({
+    'reward_ledger' : IDL.Opt(IDL.Principal),
+    'period' : IDL.Nat64,
+    'max_duration' : IDL.Nat64,
+    'min_duration' : IDL.Nat64,
+    'reward_per_period' : IDL.Nat,
+  });
+  const StakingError = IDL.Variant({
+    'InvalidConfig' : IDL.Null,
+    'StakingDisabled' : IDL.Null,
+    'NotStaked' : IDL.Null,
+    'TokenFrozen' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'Unauthorized' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'StillLocked' : IDL.Record({ 'unlock_at' : IDL.Nat64 }),
+    'InvalidDuration' : IDL.Null,
+    'TokenLocked' : IDL.Record({ 'tokens_ids' : IDL.Vec(IDL.Nat) }),
+    'RewardsExhausted' : IDL.Record({ 'available' : IDL.Nat }),
+    'FundingFailed' : IDL.Record({ 'msg' : IDL.Text }),
+  });
+  const FundRewardsArgs = IDL.Record({
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'amount' : IDL.Nat,
+  });
+  const StakeArgs = IDL.Record({
+    'duration' : IDL.Nat64,
+    'token_id' : IDL.Nat,
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
+  const UnstakeArgs = IDL.Record({
+    'token_id' : IDL.Nat,
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
+  const StakeEventKind = IDL.Variant({
+    'Claimed' : IDL.Record({ 'amount' : IDL.Nat }),
+    'Unlocked' : IDL.Null,
+    'Locked' : IDL.Record({ 'unlock_at' : IDL.Nat64 }),
+  });
+  const StakeEvent = IDL.Record({
+    'at' : IDL.Nat64,
+    'id' : IDL.Nat64,
+    'token_id' : IDL.Nat,
+    'kind' : StakeEventKind,
+    'account' : ICRCAccount,
+  });
+  const Result_11 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : StakingError });
+  const Result_12 = IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : StakingError });
+  const Result_13 = IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : StakingError });
+  const Eligibility = IDL.Variant({
+    'Allowlist' : IDL.Null,
+    'MerkleRoot' : IDL.Vec(IDL.Nat8),
+    'Public' : IDL.Null,
+  });
+  const PriceTier = IDL.Record({ 'until_supply' : IDL.Nat, 'price' : IDL.Nat });
+  const PriceSchedule = IDL.Variant({
+    'Tiered' : IDL.Vec(PriceTier),
+    'Dutch' : IDL.Record({
+      'start_price' : IDL.Nat,
+      'duration' : IDL.Nat64,
+      'start_at' : IDL.Nat64,
+      'end_price' : IDL.Nat,
+    }),
+    'Fixed' : IDL.Nat,
+  });
+  const AddMintPhaseArgs = IDL.Record({
+    'name' : IDL.Text,
+    'eligibility' : Eligibility,
+    'price' : IDL.Opt(PriceSchedule),
+    'per_account_limit' : IDL.Opt(IDL.Nat32),
+  });
+  const MintError = IDL.Variant({
+    'Unauthorized' : IDL.Null,
+    'Paused' : IDL.Null,
+    'InvalidPhase' : IDL.Null,
+    'PaymentFailed' : IDL.Record({ 'msg' : IDL.Text }),
+    'SoldOut' : IDL.Null,
+    'LimitReached' : IDL.Record({ 'limit' : IDL.Nat32 }),
+    'PriceChanged' : IDL.Record({ 'price' : IDL.Nat }),
+    'NoPaymentLedger' : IDL.Null,
+    'NotEligible' : IDL.Null,
+    'NoTreasury' : IDL.Null,
+    'DropScheduled' : IDL.Null,
+    'NoActivePhase' : IDL.Null,
+    'TokenExists' : IDL.Null,
+  });
+  const SelfMintArgs = IDL.Record({
+    'to_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'max_price' : IDL.Opt(IDL.Nat),
+    'proof' : IDL.Opt(IDL.Vec(IDL.Vec(IDL.Nat8))),
+  });
+  const Result_14 = IDL.Variant({ 'Ok' : IDL.Nat32, 'Err' : MintError });
+  const Result_15 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : MintError });
+  const Result_16 = IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : MintError });
+  const SaleOutcome = IDL.Variant({
+    'Refunded' : IDL.Record({ 'reason' : IDL.Text }),
+    'PaymentFailed' : IDL.Record({ 'msg' : IDL.Text }),
+    'Minted' : IDL.Record({ 'token_id' : IDL.Nat }),
+  });
+  const SaleLog = IDL.Record({
+    'at' : IDL.Nat64,
+    'id' : IDL.Nat64,
+    'phase_id' : IDL.Nat32,
+    'buyer' : ICRCAccount,
+    'price' : IDL.Nat,
+    'outcome' : SaleOutcome,
+  });
+  const MintPhase = IDL.Record({
+    'id' : IDL.Nat32,
+    'name' : IDL.Text,
+    'eligibility' : Eligibility,
+    'price' : IDL.Opt(PriceSchedule),
+    'per_account_limit' : IDL.Opt(IDL.Nat32),
+  });
+  const DropStatus = IDL.Record({
+    'countdown' : IDL.Opt(IDL.Nat64),
+    'remaining_supply' : IDL.Opt(IDL.Nat),
+    'phase' : IDL.Opt(MintPhase),
+    'next_switch_at' : IDL.Opt(IDL.Nat64),
+  });
+  const DropWindow = IDL.Record({
+    'phase_id' : IDL.Nat32,
+    'start_at' : IDL.Nat64,
+    'end_at' : IDL.Nat64,
+  });
+  const TokenMetadata = IDL.Record({
+    'name' : IDL.Text,
+    'description' : IDL.Opt(IDL.Text),
+    'image' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
+  const CommitRevealArgs = IDL.Record({
+    'size' : IDL.Nat64,
+    'placeholder' : TokenMetadata,
+    'commitment' : IDL.Vec(IDL.Nat8),
+  });
+  const RevealError = IDL.Variant({
+    'InvalidCommitment' : IDL.Null,
+    'NotCommitted' : IDL.Null,
+    'TooManyEntries' : IDL.Record({ 'size' : IDL.Nat64 }),
+    'NotSoldOut' : IDL.Record({ 'remaining' : IDL.Nat64 }),
+    'MissingEntries' : IDL.Record({ 'uploaded' : IDL.Nat64 }),
+    'CommitmentMismatch' : IDL.Null,
+    'AlreadyCommitted' : IDL.Null,
+    'RandomnessFailed' : IDL.Record({ 'msg' : IDL.Text }),
+    'AlreadyRevealed' : IDL.Null,
+    'InvalidStart' : IDL.Record({ 'uploaded' : IDL.Nat64 }),
+    'Revealing' : IDL.Null,
+  });
+  const Result_17 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : RevealError });
+  const Result_18 = IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : RevealError });
+  const MinterKey = IDL.Variant({
+    'Ed25519' : IDL.Vec(IDL.Nat8),
+    'Secp256k1' : IDL.Vec(IDL.Nat8),
+  });
+  const VoucherError = IDL.Variant({
+    'Paused' : IDL.Null,
+    'MetadataMismatch' : IDL.Null,
+    'TokenExists' : IDL.Null,
+    'PaymentFailed' : IDL.Record({ 'msg' : IDL.Text }),
+    'SoldOut' : IDL.Null,
+    'InvalidSignature' : IDL.Null,
+    'InvalidKey' : IDL.Null,
+    'NoPaymentLedger' : IDL.Null,
+    'NoTreasury' : IDL.Null,
+    'NonceUsed' : IDL.Null,
+    'Expired' : IDL.Null,
+    'NotRecipient' : IDL.Null,
+  });
+  const Voucher = IDL.Record({
+    'token_id' : IDL.Nat,
+    'recipient' : IDL.Opt(ICRCAccount),
+    'metadata_hash' : IDL.Vec(IDL.Nat8),
+    'nonce' : IDL.Nat64,
+    'price' : IDL.Nat,
+    'expires_at' : IDL.Nat64,
+  });
+  const RedeemVoucherArgs = IDL.Record({
+    'signature' : IDL.Vec(IDL.Nat8),
+    'voucher' : Voucher,
+    'metadata' : TokenMetadata,
+    'to_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
+  const Result_19 = IDL.Variant({ 'Ok' : IDL.Nat32, 'Err' : VoucherError });
+  const Result_20 = IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : VoucherError });
+  const AirdropMode = IDL.Variant({
+    'Mint' : IDL.Record({ 'template' : TokenMetadata }),
+    'Transfer' : IDL.Record({ 'from' : ICRCAccount }),
+  });
+  const AirdropStatus = IDL.Variant({
+    'Uploading' : IDL.Null,
+    'Running' : IDL.Null,
+    'Cancelled' : IDL.Null,
+    'Completed' : IDL.Null,
+  });
+  const Airdrop = IDL.Record({
+    'id' : IDL.Nat64,
+    'status' : AirdropStatus,
+    'creator' : IDL.Principal,
+    'total' : IDL.Nat64,
+    'mode' : AirdropMode,
+    'created_at' : IDL.Nat64,
+    'processed' : IDL.Nat64,
+    'failed' : IDL.Nat64,
+    'started_at' : IDL.Opt(IDL.Nat64),
+    'succeeded' : IDL.Nat64,
+    'retries' : IDL.Nat32,
+    'finished_at' : IDL.Opt(IDL.Nat64),
+  });
+  const AirdropRecipient = IDL.Record({
+    'to' : ICRCAccount,
+    'token_id' : IDL.Nat,
+  });
+  const AirdropFailure = IDL.Record({
+    'at' : IDL.Nat64,
+    'recipient' : AirdropRecipient,
+    'index' : IDL.Nat64,
+    'reason' : IDL.Text,
+  });
+  const AirdropError = IDL.Variant({
+    'NotUploading' : IDL.Null,
+    'Finished' : IDL.Null,
+    'NoAirdrop' : IDL.Null,
+    'TooManyRecipients' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'Unauthorized' : IDL.Null,
+    'NotCompleted' : IDL.Null,
+  });
+  const Result_21 = IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : AirdropError });
+  const Result_22 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : AirdropError });
+  const ExportStatus = IDL.Variant({ 'Ready' : IDL.Null, 'Running' : IDL.Null });
+  const SnapshotExport = IDL.Record({
+    'id' : IDL.Nat64,
+    'status' : ExportStatus,
+    'tx_index' : IDL.Nat,
+    'cursor' : IDL.Opt(IDL.Nat),
+    'created_at' : IDL.Nat64,
+    'tokens' : IDL.Nat64,
+    'holders' : IDL.Nat64,
+    'finished_at' : IDL.Opt(IDL.Nat64),
+  });
+  const SnapshotPage = IDL.Record({
+    'owners' : IDL.Vec(IDL.Tuple(IDL.Nat, ICRCAccount)),
+    'cursor' : IDL.Opt(IDL.Nat),
+  });
+  const TokenEventKind = IDL.Variant({
+    'Burn' : IDL.Record({ 'from' : ICRCAccount }),
+    'Mint' : IDL.Record({ 'to' : ICRCAccount }),
+    'Approval' : IDL.Record({
+      'owner' : ICRCAccount,
+      'expires_at' : IDL.Opt(IDL.Nat64),
+      'spender' : ICRCAccount,
+    }),
+    'Transfer' : IDL.Record({
+      'to' : ICRCAccount,
+      'from' : ICRCAccount,
+      'memo' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    }),
+  });
+  const TokenEvent = IDL.Record({
+    'at' : IDL.Nat64,
+    'tx_index' : IDL.Nat,
+    'token_id' : IDL.Nat,
+    'kind' : TokenEventKind,
+    'position' : IDL.Nat64,
+  });
+  const TransferAndCallArgs = IDL.Record({
+    'to' : ICRCAccount,
+    'spender_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'data' : IDL.Vec(IDL.Nat8),
+    'from' : ICRCAccount,
+    'memo' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'token_ids' : IDL.Vec(IDL.Nat),
+    'created_at_time' : IDL.Opt(IDL.Nat64),
+  });
+  const TransferAndCallError = IDL.Variant({
+    'CallFailed' : IDL.Record({ 'msg' : IDL.Text }),
+    'Rejected' : IDL.Record({ 'reason' : IDL.Text }),
+    'NotCanister' : IDL.Null,
+    'Transfer' : TransferError,
+  });
+  const Result_23 = IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : TransferAndCallError });
+  const EventType = IDL.Variant({
+    'Burn' : IDL.Null,
+    'Mint' : IDL.Null,
+    'Approval' : IDL.Null,
+    'Transfer' : IDL.Null,
+  });
+  const SubscribeArgs = IDL.Record({
+    'events' : IDL.Vec(EventType),
+    'account' : IDL.Opt(ICRCAccount),
+    'token_ids' : IDL.Opt(IDL.Vec(IDL.Nat)),
+    'subscriber' : IDL.Opt(IDL.Principal),
+  });
+  const SubscriptionError = IDL.Variant({
+    'NoEvents' : IDL.Null,
+    'NoSubscription' : IDL.Null,
+    'Unauthorized' : IDL.Null,
+    'NotCanister' : IDL.Null,
+    'TooManySubscriptions' : IDL.Record({ 'max' : IDL.Nat64 }),
+    'SubscriberLimitReached' : IDL.Record({ 'max' : IDL.Nat64 }),
+  });
+  const Result_24 = IDL.Variant({ 'Ok' : IDL.Nat64, 'Err' : SubscriptionError });
+  const Result_27 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : SubscriptionError });
+  const Subscription = IDL.Record({
+    'id' : IDL.Nat64,
+    'last_error' : IDL.Opt(IDL.Text),
+    'next_attempt_at' : IDL.Nat64,
+    'attempts' : IDL.Nat32,
+    'dead_lettered' : IDL.Nat64,
+    'created_at' : IDL.Nat64,
+    'events' : IDL.Vec(EventType),
+    'account' : IDL.Opt(ICRCAccount),
+    'delivered' : IDL.Nat64,
+    'token_ids' : IDL.Opt(IDL.Vec(IDL.Nat)),
+    'subscriber' : IDL.Principal,
+  });
+  const TopUp = IDL.Record({
+    'from' : ICRCAccount,
+    'ledger' : IDL.Principal,
+    'amount' : IDL.Nat,
+  });
+  const SetCyclesConfigArgs = IDL.Record({
+    'top_up' : IDL.Opt(TopUp),
+    'low_water_mark' : IDL.Opt(IDL.Nat),
+  });
+  const CyclesError = IDL.Variant({ 'InvalidAmount' : IDL.Null });
+  const Result_25 = IDL.Variant({ 'Ok' : IDL.Null, 'Err' : CyclesError });
+  const TopUpAttempt = IDL.Record({
+    'at' : IDL.Nat64,
+    'error' : IDL.Opt(IDL.Text),
+    'block' : IDL.Opt(IDL.Nat),
+    'amount' : IDL.Nat,
+  });
+  const CyclesState = IDL.Record({
+    'last_check_at' : IDL.Opt(IDL.Nat64),
+    'top_up' : IDL.Opt(TopUp),
+    'low_since' : IDL.Opt(IDL.Nat64),
+    'last_top_up' : IDL.Opt(TopUpAttempt),
+    'low_water_mark' : IDL.Opt(IDL.Nat),
+  });
   return IDL.Service({
+    'http_request' : IDL.Func([HttpRequest], [HttpResponse], ['query']),
+    'icrc7_accept_offer' : IDL.Func([AcceptOfferArgs], [Result_5], []),
+    'icrc7_add_mint_phase' : IDL.Func([AddMintPhaseArgs], [Result_14], []),
+    'icrc7_add_minter_key' : IDL.Func([MinterKey], [Result_19], []),
+    'icrc7_add_to_allowlist' : IDL.Func([IDL.Nat32, IDL.Vec(IDL.Principal)], [Result_15], []),
+    'icrc7_airdrop' : IDL.Func([IDL.Nat64], [IDL.Opt(Airdrop)], ['query']),
+    'icrc7_airdrop_failures' : IDL.Func([IDL.Nat64, IDL.Opt(IDL.Nat64), IDL.Opt(IDL.Nat)], [IDL.Vec(AirdropFailure)], ['query']),
     'icrc7_approve' : IDL.Func([ApprovalArgs], [Result], []),
+    'icrc7_bid' : IDL.Func([BidArgs], [Result_4], []),
+    'icrc7_cancel_airdrop' : IDL.Func([IDL.Nat64], [Result_22], []),
+    'icrc7_check_cycles' : IDL.Func([], [CyclesState], []),
+    'icrc7_commit_reveal' : IDL.Func([CommitRevealArgs], [Result_17], []),
+    'icrc7_create_airdrop' : IDL.Func([AirdropMode], [Result_21], []),
+    'icrc7_create_auction' : IDL.Func([CreateAuctionArgs], [Result_4], []),
+    'icrc7_cycles_balance' : IDL.Func([], [IDL.Nat], ['query']),
+    'icrc7_drop_status' : IDL.Func([], [DropStatus], ['query']),
+    'icrc7_export_snapshot' : IDL.Func([IDL.Opt(IDL.Nat)], [IDL.Nat64], []),
+    'icrc7_is_nonce_used' : IDL.Func([IDL.Nat64], [IDL.Bool], ['query']),
+    'icrc7_mint_price' : IDL.Func([], [IDL.Opt(IDL.Nat)], ['query']),
+    'icrc7_owner_at' : IDL.Func([IDL.Nat, IDL.Nat], [IDL.Opt(ICRCAccount)], ['query']),
+    'icrc7_redeem_voucher' : IDL.Func([RedeemVoucherArgs], [Result_20], []),
+    'icrc7_reveal' : IDL.Func([], [Result_18], []),
+    'icrc7_sale_log' : IDL.Func([IDL.Opt(IDL.Nat64), IDL.Opt(IDL.Nat)], [IDL.Vec(SaleLog)], ['query']),
+    'icrc7_self_mint' : IDL.Func([SelfMintArgs], [Result_16], []),
+    'icrc7_set_active_phase' : IDL.Func([IDL.Opt(IDL.Nat32)], [Result_15], []),
+    'icrc7_set_cycles_config' : IDL.Func([SetCyclesConfigArgs], [Result_25], []),
+    'icrc7_set_drop' : IDL.Func([IDL.Opt(IDL.Vec(DropWindow))], [Result_15], []),
+    'icrc7_set_staking_config' : IDL.Func([StakingConfig], [Result_11], []),
+    'icrc7_set_treasury' : IDL.Func([IDL.Opt(ICRCAccount)], [], []),
+    'icrc7_settle_auction' : IDL.Func([IDL.Nat], [Result_4], []),
+    'icrc7_burn' : IDL.Func([BurnArgs], [BurnResult], []),
+    'icrc7_buy' : IDL.Func([BuyArgs], [Result_5], []),
+    'icrc7_buyout' : IDL.Func([FractionArgs], [Result_4], []),
+    'icrc7_claim_buyout' : IDL.Func([FractionArgs], [Result_5], []),
     'icrc7_balance_of' : IDL.Func([ICRCAccount], [IDL.Nat], ['query']),
     'icrc7_collection_metadata' : IDL.Func([], [CollectionMetadata], ['query']),
+    'icrc7_deposit_swap' : IDL.Func([DepositSwapArgs], [Result_8], []),
     'icrc7_description' : IDL.Func([], [IDL.Opt(IDL.Text)], ['query']),
+    'icrc7_fraction' : IDL.Func([IDL.Nat], [IDL.Opt(Fraction)], ['query']),
+    'icrc7_fractionalize' : IDL.Func([FractionalizeArgs], [Result_4], []),
+    'icrc7_freeze_tokens' : IDL.Func([IDL.Vec(IDL.Nat)], [], []),
+    'icrc7_frozen_tokens' : IDL.Func([], [IDL.Vec(IDL.Nat)], ['query']),
+    'icrc7_grant_role' : IDL.Func([RoleArgs], [], []),
     'icrc7_image' : IDL.Func([], [IDL.Opt(IDL.Text)], ['query']),
+    'icrc7_list' : IDL.Func([ListArgs], [Result_4], []),
+    'icrc7_list_roles' : IDL.Func(
+        [],
+        [IDL.Vec(IDL.Tuple(IDL.Principal, IDL.Vec(Role)))],
+        ['query'],
+      ),
     'icrc7_metadata' : IDL.Func(
         [IDL.Nat],
         [IDL.Vec(IDL.Tuple(IDL.Text, ICRC1MetadataValue))],
         ['query'],
       ),
-    'icrc7_mint' : IDL.Func([MintArgs], [IDL.Nat], []),
+    'icrc7_make_offer' : IDL.Func([MakeOfferArgs], [Result_6], []),
+    'icrc7_migration_status' : IDL.Func([], [MigrationStatus], ['query']),
+    'icrc7_mint' : IDL.Func([MintArgs], [Result_16], []),
     'icrc7_name' : IDL.Func([], [IDL.Text], ['query']),
     'icrc7_owner_of' : IDL.Func([IDL.Nat], [ICRCAccount], ['query']),
+    'icrc7_pause_state' : IDL.Func([], [PauseState], ['query']),
+    'icrc7_propose_swap' : IDL.Func([ProposeSwapArgs], [Result_7], []),
+    'icrc7_rental' : IDL.Func([IDL.Nat], [IDL.Opt(Rental)], ['query']),
+    'icrc7_redeem' : IDL.Func([FractionArgs], [Result_4], []),
+    'icrc7_revoke_role' : IDL.Func([RoleArgs], [], []),
     'icrc7_royalties' : IDL.Func([], [IDL.Opt(IDL.Nat16)], ['query']),
     'icrc7_royalty_recipient' : IDL.Func([], [IDL.Opt(ICRCAccount)], ['query']),
+    'icrc7_set_user' : IDL.Func([SetUserArgs], [Result_10], []),
+    'icrc7_set_pause' : IDL.Func([PauseArgs], [PauseState], []),
+    'icrc7_snapshot' : IDL.Func([IDL.Nat, IDL.Opt(IDL.Nat), IDL.Opt(IDL.Nat)], [SnapshotPage], ['query']),
+    'icrc7_snapshot_export' : IDL.Func([IDL.Nat64], [IDL.Opt(SnapshotExport)], ['query']),
+    'icrc7_fund_rewards' : IDL.Func([FundRewardsArgs], [Result_13], []),
+    'icrc7_reward_budget' : IDL.Func([IDL.Principal], [IDL.Nat], ['query']),
+    'icrc7_stake' : IDL.Func([StakeArgs], [Result_12], []),
+    'icrc7_stake_history' : IDL.Func([IDL.Opt(IDL.Nat), IDL.Opt(IDL.Nat64), IDL.Opt(IDL.Nat)], [IDL.Vec(StakeEvent)], ['query']),
+    'icrc7_start_airdrop' : IDL.Func([IDL.Nat64], [Result_22], []),
+    'icrc7_evict_subscriber' : IDL.Func([IDL.Principal], [IDL.Nat64], []),
+    'icrc7_subscribe' : IDL.Func([SubscribeArgs], [Result_24], []),
+    'icrc7_subscription' : IDL.Func([IDL.Nat64], [IDL.Opt(Subscription)], ['query']),
+    'icrc7_unsubscribe' : IDL.Func([IDL.Nat64], [Result_27], []),
     'icrc7_supply_cap' : IDL.Func([], [IDL.Opt(IDL.Nat)], ['query']),
     'icrc7_supported_standards' : IDL.Func([], [IDL.Vec(Standard)], ['query']),
     'icrc7_symbol' : IDL.Func([], [IDL.Text], ['query']),
+    'icrc7_token_account' : IDL.Func([IDL.Nat], [IDL.Opt(ICRCAccount)], ['query']),
+    'icrc7_token_history' : IDL.Func([IDL.Nat, IDL.Opt(IDL.Nat64), IDL.Opt(IDL.Nat)], [IDL.Vec(TokenEvent)], ['query']),
+    'icrc7_token_offers' : IDL.Func([IDL.Nat], [IDL.Vec(Offer)], ['query']),
+    'icrc7_token_withdraw' : IDL.Func([WithdrawArgs], [Result_9], []),
     'icrc7_tokens_of' : IDL.Func([ICRCAccount], [IDL.Vec(IDL.Nat)], ['query']),
     'icrc7_total_supply' : IDL.Func([], [IDL.Nat], ['query']),
     'icrc7_transfer' : IDL.Func([TransferArgs], [Result_1], []),
+    'icrc7_transfer_and_call' : IDL.Func([TransferAndCallArgs], [Result_23], []),
+    'icrc7_treasury' : IDL.Func([], [IDL.Opt(ICRCAccount)], ['query']),
+    'icrc7_unstake' : IDL.Func([UnstakeArgs], [Result_13], []),
+    'icrc7_upload_airdrop' : IDL.Func([IDL.Nat64, IDL.Vec(AirdropRecipient)], [Result_21], []),
+    'icrc7_upload_reveal' : IDL.Func(
+        [IDL.Vec(TokenMetadata), IDL.Opt(IDL.Nat64)],
+        [Result_18],
+        [],
+      ),
+    'icrc7_user_of' : IDL.Func([IDL.Nat], [ICRCAccount], ['query']),
+    'icrc7_unfreeze_tokens' : IDL.Func([IDL.Vec(IDL.Nat)], [], []),
+    'icrc7_update_collection_config' : IDL.Func(
+        [UpdateCollectionConfigArgs],
+        [Result_3],
+        [],
+      ),
   });
 };
  const init = ({ IDL }) => {
@@ -101,6 +790,28 @@
     'owner' : IDL.Principal,
     'subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
   });
+  const Transferability = IDL.Variant({
+    'Soulbound' : IDL.Null,
+    'BurnOnlyByIssuer' : IDL.Null,
+    'Transferable' : IDL.Null,
+  });
+  const UpdateCollectionConfigArgs = IDL.Record({
+    'supply_cap' : IDL.Opt(IDL.Opt(IDL.Nat)),
+    'tx_window' : IDL.Opt(IDL.Nat64),
+    'permitted_drift' : IDL.Opt(IDL.Nat64),
+    'name' : IDL.Opt(IDL.Text),
+    'description' : IDL.Opt(IDL.Opt(IDL.Text)),
+    'royalties' : IDL.Opt(IDL.Opt(IDL.Nat16)),
+    'royalty_recipient' : IDL.Opt(IDL.Opt(ICRCAccount)),
+    'image' : IDL.Opt(IDL.Opt(IDL.Text)),
+    'transferability' : IDL.Opt(Transferability),
+    'payment_ledger' : IDL.Opt(IDL.Opt(IDL.Principal)),
+  });
+  const Features = IDL.Record({ 'metrics' : IDL.Opt(IDL.Bool) });
+  const UpgradeArgs = IDL.Record({
+    'features' : IDL.Opt(Features),
+    'config' : IDL.Opt(UpdateCollectionConfigArgs),
+  });
   const CollectionConfig = IDL.Record({
     'supply_cap' : IDL.Opt(IDL.Nat),
     'tx_window' : IDL.Nat64,
@@ -111,8 +822,14 @@
     'royalties' : IDL.Opt(IDL.Nat16),
     'royalty_recipient' : IDL.Opt(ICRCAccount),
     'image' : IDL.Opt(IDL.Text),
+    'transferability' : IDL.Opt(Transferability),
     'symbol' : IDL.Text,
+    'payment_ledger' : IDL.Opt(IDL.Principal),
+  });
+  const CollectionArgs = IDL.Variant({
+    'Upgrade' : IDL.Opt(UpgradeArgs),
+    'Init' : CollectionConfig,
   });
-  return [CollectionConfig];
+  return [CollectionArgs];
 };
-module.exports = { idlFactory };
+module.exports = { idlFactory, init };
@@ -6,20 +6,46 @@ const {
 } = require("./../.dfx/local/canisters/factory/factory.did.test.cjs");
 
 const {
-    idlFactory: icrc7_interace
+    idlFactory: icrc7_interace,
+    init: icrc7_init
 } = require("./icrc7.did.test.cjs");
 
-const { getActor } = require("./actor.cjs");
+const {
+    idlFactory: ledger_interface
+} = require("./test_ledger.did.test.cjs");
+
+const {
+    idlFactory: receiver_interface
+} = require("./test_receiver.did.test.cjs");
+
+const {
+    idlFactory: share_ledger_interface
+} = require("./share_ledger.did.test.cjs");
+
+const {
+    idlFactory: baseline_interface,
+    init: baseline_init
+} = require("./icrc7_baseline.did.test.cjs");
+
+const { getAgent, getActor } = require("./actor.cjs");
+const { Actor, CanisterInstallMode } = require("@dfinity/agent");
+const { IDL } = require("@dfinity/candid");
+const { readFileSync } = require("fs");
+const { gzipSync } = require("zlib");
 
 const canister_ids = require("./../.dfx/local/canister_ids.json");
 const { Principal } = require("@dfinity/principal");
 const { encodeIcrcAccount } = require("@dfinity/ledger");
+const { createHash } = require("crypto");
 const factory = canister_ids.factory.local;
+const ledger = canister_ids.test_ledger.local;
+const receiver = canister_ids.test_receiver.local;
 
 let factory_actors = {}
 
 let icrc7_canister = ""
 let icrc7_actors = {}
+let ledger_actors = {}
 
 // identities
 let minter = Ed25519KeyIdentity.generate();
@@ -33,10 +59,65 @@ let user5 = Ed25519KeyIdentity.generate();
 let subaccount = new Uint8Array(32).fill(0);
 
 // accounts
+let user1Account = encodeIcrcAccount({ owner: user1.getPrincipal(), subaccount: [subaccount] });
 let user2Account = encodeIcrcAccount({ owner: user2.getPrincipal(), subaccount: [subaccount] });
 let user3Account = encodeIcrcAccount({ owner: user3.getPrincipal(), subaccount: [subaccount] });
 let user4Account = encodeIcrcAccount({ owner: user4.getPrincipal(), subaccount: [subaccount] });
 
+// fetches a path through a canister's http_request query
+async function httpGet(url, actor = icrc7_actors.user1) {
+    var response = await actor.http_request({
+        'url': url,
+        'method': "GET",
+        'body': [],
+        'headers': []
+    })
+    return { status: response.status_code, body: Buffer.from(response.body).toString() }
+}
+
+// sends cycles to the collection out of the test ledger's own, e.g. for the
+// share ledgers it spawns
+async function fundCycles(amount) {
+    var funder = await getActor(ledger, ledger_interface, user5)
+    var account = { owner: user5.getPrincipal(), subaccount: [] }
+    await ledger_actors.minter.mint(account, amount + 20_000n)
+    await funder.icrc2_approve({
+        'fee': [],
+        'memo': [],
+        'from_subaccount': [],
+        'created_at_time': [],
+        'amount': amount + 10_000n,
+        'expected_allowance': [],
+        'expires_at': [],
+        'spender': account
+    })
+    return await funder.withdraw_from({
+        'to': Principal.fromText(icrc7_canister.toString()),
+        'from': account,
+        'spender_subaccount': [],
+        'created_at_time': [],
+        'amount': amount
+    })
+}
+
+// moves shares on the ICRC-1 ledger of a fractionalized token
+function transferShares(actor, to, amount) {
+    return actor.icrc1_transfer({
+        'to': { owner: to.getPrincipal(), subaccount: [] },
+        'fee': [],
+        'memo': [],
+        'from_subaccount': [],
+        'created_at_time': [],
+        'amount': amount
+    })
+}
+
+// value of a sample in a Prometheus text body, 0 when it is absent
+function sample(body, name) {
+    var line = body.split("\n").find((line) => line.startsWith(name + " "))
+    return line === undefined ? 0n : BigInt(line.split(" ")[1])
+}
+
 test("setup minting actor", async function (t) {
     console.log("=====factory=====");
     factory_actors.minter = await getActor(
@@ -52,9 +133,11 @@ test("Mint, should return an address", async function (t) {
         'name': "Btc Flower",
         'description': [],
         'royalties': [],
-        'image': [],
+        'logo': [],
         'royalties_recipient': [],
-        'symbol': "BTC"
+        'symbol': "BTC",
+        'transferability': [],
+        'payment_ledger': []
     })
     console.log(response.toString())
     icrc7_canister = response
@@ -129,11 +212,12 @@ test("should return error about supply cap after 10 mints", async function (t) {
             },
             'name': "Token",
             'description': [],
-            'image': []
+            'image': [],
+            'transferability': []
         })
-        t.equal(id, i)
+        t.deepEqual(id, { Ok: i - 1n })
     };
-    await icrc7_actors.minter.icrc7_mint({
+    let response = await icrc7_actors.minter.icrc7_mint({
         'id': 11n,
         'to': {
             owner: user1.getPrincipal(),
@@ -141,8 +225,10 @@ test("should return error about supply cap after 10 mints", async function (t) {
         },
         'name': "Token",
         'description': [],
-        'image': []
+        'image': [],
+        'transferability': []
     })
+    t.deepEqual(response, { Err: { SoldOut: null } })
 })
 
 test("transfer and check owner", async function (t) {
@@ -151,7 +237,11 @@ test("transfer and check owner", async function (t) {
             owner: user2.getPrincipal(),
             subaccount: []
         },
-        'from': [],
+        'spender_subaccount': [],
+        'from': {
+            owner: user1.getPrincipal(),
+            subaccount: []
+        },
         'memo': [],
         'is_atomic': [],
         'token_ids': [1n],
@@ -168,7 +258,11 @@ test("multiple token transfer", async function (t) {
             owner: user2.getPrincipal(),
             subaccount: []
         },
-        'from': [],
+        'spender_subaccount': [],
+        'from': {
+            owner: user1.getPrincipal(),
+            subaccount: []
+        },
         'memo': [],
         'is_atomic': [],
         'token_ids': [2n, 3n, 4n,],
@@ -188,10 +282,13 @@ test("multiple token transfer", async function (t) {
 
 test("approve", async function (t) {
     let result = await icrc7_actors.user2.icrc7_approve({
-        to: user3.getPrincipal(),
-        'tokenIds': [],
+        'spender': {
+            owner: user3.getPrincipal(),
+            subaccount: []
+        },
+        'token_ids': [],
         'memo': [],
-        'created_at': [],
+        'created_at_time': [],
         'from_subaccount': [],
         'expires_at': [],
     })
@@ -200,7 +297,11 @@ test("approve", async function (t) {
             owner: user4.getPrincipal(),
             subaccount: []
         },
-        'from': [],
+        'spender_subaccount': [],
+        'from': {
+            owner: user2.getPrincipal(),
+            subaccount: []
+        },
         'memo': [],
         'is_atomic': [],
         'token_ids': [3n, 4n],
@@ -212,4 +313,1328 @@ test("approve", async function (t) {
     var owner = await icrc7_actors.user3.icrc7_owner_of(4n);
     var owner = encodeIcrcAccount(owner)
     t.equal(user4Account, owner)
-})
\ No newline at end of file
+})
+
+test("metrics report supply and per-endpoint counters", async function (t) {
+    var calls = 'icrc7_endpoint_calls_total{endpoint="icrc7_transfer"}'
+    var errors = 'icrc7_endpoint_errors_total{endpoint="icrc7_transfer"}'
+
+    var response = await httpGet("/metrics")
+    t.equal(response.status, 200)
+    var before = response.body
+    t.ok(before.includes("# TYPE icrc7_endpoint_calls_total counter"))
+    t.equal(sample(before, "icrc7_total_supply"), await icrc7_actors.user1.icrc7_total_supply())
+    t.ok(sample(before, 'icrc7_endpoint_calls_total{endpoint="icrc7_mint"}') > 0n)
+
+    // token 1 went to user2, so this fails with Unauthorized
+    var result = await icrc7_actors.user1.icrc7_transfer({
+        'to': {
+            owner: user3.getPrincipal(),
+            subaccount: []
+        },
+        'spender_subaccount': [],
+        'from': {
+            owner: user1.getPrincipal(),
+            subaccount: []
+        },
+        'memo': [],
+        'is_atomic': [],
+        'token_ids': [1n],
+        'created_at_time': []
+    })
+    t.ok(result.Err.Unauthorized !== undefined)
+
+    var after = (await httpGet("/metrics")).body
+    t.equal(sample(after, calls), sample(before, calls) + 1n)
+    t.equal(sample(after, errors), sample(before, errors) + 1n)
+})
+
+test("grant and revoke minter role", async function (t) {
+    await icrc7_actors.minter.icrc7_grant_role({
+        'principal': user5.getPrincipal(),
+        'role': { Minter: null }
+    })
+    var roles = await icrc7_actors.minter.icrc7_list_roles();
+    var user5Roles = roles.find(([principal]) => principal.toText() === user5.getPrincipal().toText())
+    t.deepEqual(user5Roles[1], [{ Minter: null }])
+
+    await icrc7_actors.minter.icrc7_revoke_role({
+        'principal': user5.getPrincipal(),
+        'role': { Minter: null }
+    })
+    var roles = await icrc7_actors.minter.icrc7_list_roles();
+    var user5Roles = roles.find(([principal]) => principal.toText() === user5.getPrincipal().toText())
+    t.equal(user5Roles, undefined)
+})
+
+test("metadata updaters change the metadata but not the settings", async function (t) {
+    await icrc7_actors.minter.icrc7_grant_role({
+        'principal': user5.getPrincipal(),
+        'role': { MetadataUpdater: null }
+    })
+    var update = (changes) => icrc7_actors.user5.icrc7_update_collection_config({
+        'supply_cap': [],
+        'tx_window': [],
+        'permitted_drift': [],
+        'name': [],
+        'description': [],
+        'royalties': [],
+        'royalty_recipient': [],
+        'image': [],
+        'transferability': [],
+        'payment_ledger': [],
+        ...changes
+    })
+
+    var result = await update({ 'description': [["Updated by a metadata updater"]] })
+    t.ok(result.Ok !== undefined)
+    t.deepEqual(await icrc7_actors.user1.icrc7_description(), ["Updated by a metadata updater"])
+
+    var rejected = false
+    try {
+        await update({ 'description': [[]], 'tx_window': [1n] })
+    } catch (e) {
+        rejected = true
+    }
+    t.ok(rejected)
+
+    await icrc7_actors.minter.icrc7_revoke_role({
+        'principal': user5.getPrincipal(),
+        'role': { MetadataUpdater: null }
+    })
+})
+
+test("config updates are validated, logged and clear optional fields", async function (t) {
+    var update = (changes) => icrc7_actors.minter.icrc7_update_collection_config({
+        'supply_cap': [],
+        'tx_window': [],
+        'permitted_drift': [],
+        'name': [],
+        'description': [],
+        'royalties': [],
+        'royalty_recipient': [],
+        'image': [],
+        'transferability': [],
+        'payment_ledger': [],
+        ...changes
+    })
+    var logLength = async () => sample((await httpGet("/metrics")).body, "icrc7_transfer_log_length")
+    var length = await logLength()
+
+    var result = await update({ 'royalties': [[10_001]] })
+    t.deepEqual(result.Err, { InvalidRoyalties: { max_royalties: 10_000 } })
+    result = await update({ 'supply_cap': [[1n]] })
+    t.ok(result.Err.SupplyCapBelowTotalSupply !== undefined)
+    t.equal(await logLength(), length)
+
+    result = await update({ 'description': [["Test collection"]], 'image': [["https://example.com/logo.png"]] })
+    t.ok(result.Ok !== undefined)
+    t.deepEqual(await icrc7_actors.user1.icrc7_description(), ["Test collection"])
+    t.deepEqual(await icrc7_actors.user1.icrc7_image(), ["https://example.com/logo.png"])
+    t.equal(await logLength(), length + 1n)
+
+    var response = await httpGet("/transfer_log?id=" + length)
+    t.equal(response.status, 200)
+    var log = JSON.parse(response.body)
+    t.equal(log.op.ConfigUpdate.by, minter.getPrincipal().toText())
+    t.equal(log.op.ConfigUpdate.changes.description, "Test collection")
+
+    result = await update({ 'image': [[]], 'supply_cap': [[]] })
+    t.ok(result.Ok !== undefined)
+    t.deepEqual(await icrc7_actors.user1.icrc7_image(), [])
+    t.deepEqual(await icrc7_actors.user1.icrc7_supply_cap(), [])
+    t.deepEqual(await icrc7_actors.user1.icrc7_description(), ["Test collection"])
+    t.equal(await logLength(), length + 2n)
+
+    result = await update({ 'royalties': [[250]] })
+    t.ok(result.Ok !== undefined)
+    t.deepEqual(await icrc7_actors.user1.icrc7_royalties(), [250])
+    result = await update({ 'royalties': [[]] })
+    t.ok(result.Ok !== undefined)
+    t.deepEqual(await icrc7_actors.user1.icrc7_royalties(), [])
+})
+
+test("pause and freeze stop tokens from moving, burns are logged", async function (t) {
+    var account = { owner: user1.getPrincipal(), subaccount: [] }
+    var mint = (id) => icrc7_actors.minter.icrc7_mint({
+        'id': id,
+        'to': account,
+        'name': "Token",
+        'description': [],
+        'image': [],
+        'transferability': []
+    })
+    var transfer = (token_ids) => icrc7_actors.user1.icrc7_transfer({
+        'to': { owner: user3.getPrincipal(), subaccount: [] },
+        'spender_subaccount': [],
+        'from': account,
+        'memo': [],
+        'is_atomic': [],
+        'token_ids': token_ids,
+        'created_at_time': []
+    })
+    var burn = (token_ids) => icrc7_actors.user1.icrc7_burn({ 'from_subaccount': [], 'token_ids': token_ids })
+
+    t.ok((await mint(5_000n)).Ok !== undefined)
+    t.ok((await mint(5_001n)).Ok !== undefined)
+
+    var state = await icrc7_actors.minter.icrc7_set_pause({
+        'mints': [true],
+        'transfers': [true],
+        'burns': [true],
+        'approvals': []
+    })
+    t.deepEqual(state, { mints: true, transfers: true, burns: true, approvals: false })
+    t.deepEqual(await mint(5_002n), { Err: { Paused: null } })
+    t.deepEqual(await transfer([5_000n]), { Err: { Paused: null } })
+    t.deepEqual(await burn([5_001n]), { Err: { Paused: null } })
+    await icrc7_actors.minter.icrc7_set_pause({
+        'mints': [false],
+        'transfers': [false],
+        'burns': [false],
+        'approvals': []
+    })
+
+    await icrc7_actors.minter.icrc7_freeze_tokens([5_000n])
+    t.deepEqual(await icrc7_actors.user1.icrc7_frozen_tokens(), [5_000n])
+    t.deepEqual(await transfer([5_000n]), { Err: { TokenFrozen: { tokens_ids: [5_000n] } } })
+    t.deepEqual(await burn([5_000n]), { Err: { TokenFrozen: { tokens_ids: [5_000n] } } })
+    await icrc7_actors.minter.icrc7_unfreeze_tokens([5_000n])
+    t.ok((await transfer([5_000n])).Ok !== undefined)
+
+    var supply = await icrc7_actors.user1.icrc7_total_supply()
+    var length = sample((await httpGet("/metrics")).body, "icrc7_transfer_log_length")
+    var burned = await burn([5_001n])
+    t.ok(burned.Ok !== undefined)
+    t.equal(await icrc7_actors.user1.icrc7_total_supply(), supply - 1n)
+
+    var log = JSON.parse((await httpGet("/transfer_log?id=" + length)).body)
+    t.equal(log.op.Burn.id, 5_001)
+    t.equal(log.op.Burn.from.owner, user1.getPrincipal().toText())
+    t.equal(BigInt(log.tx_index), burned.Ok)
+})
+
+test("soulbound token cannot be transferred", async function (t) {
+    await icrc7_actors.minter.icrc7_update_collection_config({
+        'supply_cap': [[20n]],
+        'tx_window': [],
+        'permitted_drift': [],
+        'name': [],
+        'description': [],
+        'royalties': [],
+        'royalty_recipient': [],
+        'image': [],
+        'transferability': [],
+        'payment_ledger': []
+    })
+    await icrc7_actors.minter.icrc7_mint({
+        'id': 12n,
+        'to': {
+            owner: user1.getPrincipal(),
+            subaccount: []
+        },
+        'name': "Badge",
+        'description': [],
+        'image': [],
+        'transferability': [{ Soulbound: null }]
+    })
+    let result = await icrc7_actors.user1.icrc7_transfer({
+        'to': {
+            owner: user2.getPrincipal(),
+            subaccount: []
+        },
+        'spender_subaccount': [],
+        'from': {
+            owner: user1.getPrincipal(),
+            subaccount: []
+        },
+        'memo': [],
+        'is_atomic': [],
+        'token_ids': [12n],
+        'created_at_time': []
+    })
+    t.deepEqual(result, { Err: { NonTransferable: { tokens_ids: [12n] } } })
+    var owner = await icrc7_actors.user1.icrc7_owner_of(12n);
+    var owner = encodeIcrcAccount(owner)
+    t.equal(user1Account, owner)
+})
+
+test("list and buy with royalty payout", async function (t) {
+    ledger_actors.user2 = await getActor(ledger, ledger_interface, user2)
+    ledger_actors.minter = await getActor(ledger, ledger_interface, minter)
+
+    await icrc7_actors.minter.icrc7_update_collection_config({
+        'supply_cap': [],
+        'tx_window': [],
+        'permitted_drift': [],
+        'name': [],
+        'description': [],
+        'royalties': [[500]],
+        'royalty_recipient': [[{ owner: user5.getPrincipal(), subaccount: [] }]],
+        'image': [],
+        'transferability': [],
+        'payment_ledger': [[Principal.fromText(ledger)]]
+    })
+    await icrc7_actors.minter.icrc7_mint({
+        'id': 13n,
+        'to': {
+            owner: user1.getPrincipal(),
+            subaccount: []
+        },
+        'name': "Token",
+        'description': [],
+        'image': [],
+        'transferability': []
+    })
+
+    let listed = await icrc7_actors.user1.icrc7_list({
+        'token_id': 13n,
+        'from_subaccount': [],
+        'price': 1_000_000n
+    })
+    t.deepEqual(listed, { Ok: null })
+
+    await ledger_actors.minter.mint({ owner: user2.getPrincipal(), subaccount: [] }, 10_000_000n)
+    await ledger_actors.user2.icrc2_approve({
+        'fee': [],
+        'memo': [],
+        'from_subaccount': [],
+        'created_at_time': [],
+        'amount': 1_010_000n,
+        'expected_allowance': [],
+        'expires_at': [],
+        'spender': { owner: Principal.fromText(icrc7_canister.toString()), subaccount: [] }
+    })
+
+    let bought = await icrc7_actors.user2.icrc7_buy({
+        'token_id': 13n,
+        'from_subaccount': [],
+        'price': 1_000_000n
+    })
+    t.ok(bought.Ok !== undefined)
+
+    var owner = await icrc7_actors.user2.icrc7_owner_of(13n);
+    var owner = encodeIcrcAccount(owner)
+    t.equal(user2Account, owner)
+
+    // 5% royalty, each payout pays the 10_000 ledger fee out of its share
+    var balance = await ledger_actors.user2.icrc1_balance_of({ owner: user1.getPrincipal(), subaccount: [] })
+    t.equal(balance, 940_000n)
+    var balance = await ledger_actors.user2.icrc1_balance_of({ owner: user5.getPrincipal(), subaccount: [] })
+    t.equal(balance, 40_000n)
+})
+
+test("english auction refunds the outbid bidder and settles", async function (t) {
+    ledger_actors.user3 = await getActor(ledger, ledger_interface, user3)
+    let spender = { owner: Principal.fromText(icrc7_canister.toString()), subaccount: [] }
+
+    await icrc7_actors.minter.icrc7_mint({
+        'id': 14n,
+        'to': {
+            owner: user1.getPrincipal(),
+            subaccount: []
+        },
+        'name': "Token",
+        'description': [],
+        'image': [],
+        'transferability': []
+    })
+    let created = await icrc7_actors.user1.icrc7_create_auction({
+        'token_id': 14n,
+        'from_subaccount': [],
+        'kind': { English: { reserve_price: 100_000n, min_increment: 10_000n, extension: 2n ** 64n - 1n } },
+        'duration': 5_000_000_000n
+    })
+    t.deepEqual(created, { Err: { InvalidAuction: null } })
+    created = await icrc7_actors.user1.icrc7_create_auction({
+        'token_id': 14n,
+        'from_subaccount': [],
+        'kind': { English: { reserve_price: 100_000n, min_increment: 10_000n, extension: 0n } },
+        'duration': 5_000_000_000n
+    })
+    t.deepEqual(created, { Ok: null })
+
+    await ledger_actors.minter.mint({ owner: user3.getPrincipal(), subaccount: [] }, 10_000_000n)
+    for (const actor of [ledger_actors.user2, ledger_actors.user3]) {
+        await actor.icrc2_approve({
+            'fee': [],
+            'memo': [],
+            'from_subaccount': [],
+            'created_at_time': [],
+            'amount': 1_000_000n,
+            'expected_allowance': [],
+            'expires_at': [],
+            'spender': spender
+        })
+    }
+
+    var before = await ledger_actors.user2.icrc1_balance_of({ owner: user2.getPrincipal(), subaccount: [] })
+    var bid = await icrc7_actors.user2.icrc7_bid({ 'token_id': 14n, 'from_subaccount': [], 'amount': 100_000n })
+    t.deepEqual(bid, { Ok: null })
+    var bid = await icrc7_actors.user3.icrc7_bid({ 'token_id': 14n, 'from_subaccount': [], 'amount': 105_000n })
+    t.deepEqual(bid, { Err: { BidTooLow: { min_bid: 110_000n } } })
+    var bid = await icrc7_actors.user3.icrc7_bid({ 'token_id': 14n, 'from_subaccount': [], 'amount': 200_000n })
+    t.deepEqual(bid, { Ok: null })
+
+    // the escrowed bid comes back minus the fees of both ledger transfers
+    var after = await ledger_actors.user2.icrc1_balance_of({ owner: user2.getPrincipal(), subaccount: [] })
+    t.equal(before - after, 20_000n)
+
+    await new Promise((resolve) => setTimeout(resolve, 6_000))
+    await icrc7_actors.user1.icrc7_settle_auction(14n)
+
+    var owner = await icrc7_actors.user1.icrc7_owner_of(14n);
+    var owner = encodeIcrcAccount(owner)
+    t.equal(user3Account, owner)
+})
+
+test("offers are accepted by the owner and dropped on transfer", async function (t) {
+    var offer = await icrc7_actors.user3.icrc7_make_offer({
+        'token_id': [13n],
+        'from_subaccount': [],
+        'amount': 300_000n,
+        'expires_at': []
+    })
+    var accepted = await icrc7_actors.user2.icrc7_accept_offer({
+        'token_id': 13n,
+        'from_subaccount': [],
+        'offer_id': offer.Ok
+    })
+    t.ok(accepted.Ok !== undefined)
+    var owner = await icrc7_actors.user2.icrc7_owner_of(13n);
+    var owner = encodeIcrcAccount(owner)
+    t.equal(user3Account, owner)
+
+    await icrc7_actors.user2.icrc7_make_offer({
+        'token_id': [14n],
+        'from_subaccount': [],
+        'amount': 100_000n,
+        'expires_at': []
+    })
+    var offers = await icrc7_actors.user2.icrc7_token_offers(14n)
+    t.equal(offers.length, 1)
+
+    await icrc7_actors.user3.icrc7_transfer({
+        'to': {
+            owner: user4.getPrincipal(),
+            subaccount: []
+        },
+        'spender_subaccount': [],
+        'from': {
+            owner: user3.getPrincipal(),
+            subaccount: []
+        },
+        'memo': [],
+        'is_atomic': [],
+        'token_ids': [14n],
+        'created_at_time': []
+    })
+    var offers = await icrc7_actors.user2.icrc7_token_offers(14n)
+    t.equal(offers.length, 0)
+})
+
+test("swap settles once both sides are escrowed", async function (t) {
+    let icrc7 = Principal.fromText(icrc7_canister.toString())
+    let proposed = await icrc7_actors.user4.icrc7_propose_swap({
+        'from_subaccount': [],
+        'counterparty': { owner: user2.getPrincipal(), subaccount: [] },
+        'give': [{ Nft: { collection: icrc7, token_id: 14n } }],
+        'take': [{ Fungible: { ledger: Principal.fromText(ledger), amount: 150_000n } }],
+        'expires_at': BigInt(Date.now() + 600_000) * 1_000_000n
+    })
+    let swap_id = proposed.Ok
+
+    var deposit = await icrc7_actors.user4.icrc7_deposit_swap({ 'swap_id': swap_id, 'from_subaccount': [] })
+    t.deepEqual(deposit, { Ok: { Open: null } })
+
+    await ledger_actors.user2.icrc2_approve({
+        'fee': [],
+        'memo': [],
+        'from_subaccount': [],
+        'created_at_time': [],
+        'amount': 160_000n,
+        'expected_allowance': [],
+        'expires_at': [],
+        'spender': { owner: icrc7, subaccount: [] }
+    })
+    var deposit = await icrc7_actors.user2.icrc7_deposit_swap({ 'swap_id': swap_id, 'from_subaccount': [] })
+    t.deepEqual(deposit, { Ok: { Settled: null } })
+
+    var owner = await icrc7_actors.user2.icrc7_owner_of(14n);
+    var owner = encodeIcrcAccount(owner)
+    t.equal(user2Account, owner)
+
+    // the fee of the transfer out of escrow comes out of the amount
+    var balance = await ledger_actors.user2.icrc1_balance_of({ owner: user4.getPrincipal(), subaccount: [] })
+    t.equal(balance, 140_000n)
+})
+
+test("token-bound account travels with the token", async function (t) {
+    let account = await icrc7_actors.user2.icrc7_token_account(14n)
+    account = account[0]
+    await ledger_actors.minter.mint(account, 100_000n)
+
+    await icrc7_actors.user2.icrc7_transfer({
+        'to': {
+            owner: user3.getPrincipal(),
+            subaccount: []
+        },
+        'spender_subaccount': [],
+        'from': {
+            owner: user2.getPrincipal(),
+            subaccount: []
+        },
+        'memo': [],
+        'is_atomic': [],
+        'token_ids': [14n],
+        'created_at_time': []
+    })
+
+    var withdrawn = await icrc7_actors.user2.icrc7_token_withdraw({
+        'token_id': 14n,
+        'from_subaccount': [],
+        'ledger': [],
+        'to': { owner: user2.getPrincipal(), subaccount: [] },
+        'amount': 50_000n
+    })
+    t.deepEqual(withdrawn, { Err: { Unauthorized: { tokens_ids: [14n] } } })
+
+    var withdrawn = await icrc7_actors.user3.icrc7_token_withdraw({
+        'token_id': 14n,
+        'from_subaccount': [],
+        'ledger': [Principal.fromText(ledger)],
+        'to': { owner: user3.getPrincipal(), subaccount: [] },
+        'amount': 50_000n
+    })
+    t.deepEqual(withdrawn, { Ok: null })
+
+    // the fee is paid on top by the token account
+    var balance = await ledger_actors.user2.icrc1_balance_of(account)
+    t.equal(balance, 40_000n)
+})
+
+test("rental ends when the token moves", async function (t) {
+    var granted = await icrc7_actors.user3.icrc7_set_user({
+        'token_id': 14n,
+        'from_subaccount': [],
+        'user': { owner: user4.getPrincipal(), subaccount: [] },
+        'expires_at': BigInt(Date.now() + 600_000) * 1_000_000n
+    })
+    t.deepEqual(granted, { Ok: null })
+
+    // a running rental cannot be cut short
+    var shortened = await icrc7_actors.user3.icrc7_set_user({
+        'token_id': 14n,
+        'from_subaccount': [],
+        'user': { owner: user4.getPrincipal(), subaccount: [] },
+        'expires_at': BigInt(Date.now() + 300_000) * 1_000_000n
+    })
+    t.ok(shortened.Err.RentalActive !== undefined)
+
+    var user = await icrc7_actors.user3.icrc7_user_of(14n)
+    t.equal(encodeIcrcAccount(user), user4Account)
+
+    await icrc7_actors.user3.icrc7_transfer({
+        'to': {
+            owner: user2.getPrincipal(),
+            subaccount: []
+        },
+        'spender_subaccount': [],
+        'from': {
+            owner: user3.getPrincipal(),
+            subaccount: []
+        },
+        'memo': [],
+        'is_atomic': [],
+        'token_ids': [14n],
+        'created_at_time': []
+    })
+
+    var user = await icrc7_actors.user3.icrc7_user_of(14n)
+    t.equal(encodeIcrcAccount(user), user2Account)
+    var rental = await icrc7_actors.user3.icrc7_rental(14n)
+    t.deepEqual(rental, [])
+})
+
+test("fractionalized token is redeemed by burning every share", async function (t) {
+    var funded = await fundCycles(1_000_000_000_000n)
+    t.ok(funded.Ok !== undefined)
+
+    var fractionalize = () => icrc7_actors.user2.icrc7_fractionalize({
+        'token_id': 14n,
+        'from_subaccount': [],
+        'shares': 100n,
+        'reserve_price': []
+    })
+    // a new share ledger costs cycles, only fractionalizers spend the collection's
+    t.deepEqual(await fractionalize(), { Err: { CyclesRequired: { cycles: 200_000_000_000n } } })
+    for (const user of [user1, user2]) {
+        await icrc7_actors.minter.icrc7_grant_role({
+            'principal': user.getPrincipal(),
+            'role': { Fractionalizer: null }
+        })
+    }
+
+    var fractionalized = await fractionalize()
+    t.deepEqual(fractionalized, { Ok: null })
+
+    var transfer = await icrc7_actors.user2.icrc7_transfer({
+        'to': {
+            owner: user3.getPrincipal(),
+            subaccount: []
+        },
+        'spender_subaccount': [],
+        'from': {
+            owner: user2.getPrincipal(),
+            subaccount: []
+        },
+        'memo': [],
+        'is_atomic': [],
+        'token_ids': [14n],
+        'created_at_time': []
+    })
+    t.ok(transfer.Err !== undefined)
+
+    // the shares live on their own ICRC-1 ledger
+    var fraction = (await icrc7_actors.user2.icrc7_fraction(14n))[0]
+    var shares_user2 = await getActor(fraction.share_ledger, share_ledger_interface, user2)
+    var shares_user3 = await getActor(fraction.share_ledger, share_ledger_interface, user3)
+    var metadata = await shares_user2.icrc1_metadata()
+    t.deepEqual(metadata.find(([key]) => key == "icrc7:token_id")[1], { Nat: 14n })
+    t.equal(await shares_user2.icrc1_total_supply(), 100n)
+
+    var sent = await transferShares(shares_user2, user3, 40n)
+    t.ok(sent.Ok !== undefined)
+    t.equal(await shares_user3.icrc1_balance_of({ owner: user3.getPrincipal(), subaccount: [] }), 40n)
+
+    var redeemed = await icrc7_actors.user2.icrc7_redeem({ 'token_id': 14n, 'from_subaccount': [] })
+    t.deepEqual(redeemed, { Err: { InsufficientShares: { balance: 60n } } })
+
+    await transferShares(shares_user3, user2, 40n)
+    var redeemed = await icrc7_actors.user2.icrc7_redeem({ 'token_id': 14n, 'from_subaccount': [] })
+    t.deepEqual(redeemed, { Ok: null })
+    t.equal(await shares_user2.icrc1_total_supply(), 0n)
+
+    var owner = await icrc7_actors.user2.icrc7_owner_of(14n);
+    var owner = encodeIcrcAccount(owner)
+    t.equal(user2Account, owner)
+})
+
+test("staked token is locked and earns rewards", async function (t) {
+    let icrc7 = Principal.fromText(icrc7_canister.toString())
+    await icrc7_actors.minter.icrc7_set_staking_config({
+        'reward_ledger': [Principal.fromText(ledger)],
+        'reward_per_period': 20_000n,
+        'period': 1_000_000_000n,
+        'min_duration': 0n,
+        'max_duration': 3_600_000_000_000n
+    })
+    // rewards come out of a funded budget, not the canister's own account
+    await ledger_actors.minter.mint({ owner: minter.getPrincipal(), subaccount: [] }, 1_000_000n)
+    await ledger_actors.minter.icrc2_approve({
+        'fee': [],
+        'memo': [],
+        'from_subaccount': [],
+        'created_at_time': [],
+        'amount': 100_010n,
+        'expected_allowance': [],
+        'expires_at': [],
+        'spender': { owner: icrc7, subaccount: [] }
+    })
+    var funded = await icrc7_actors.minter.icrc7_fund_rewards({ 'from_subaccount': [], 'amount': 100_000n })
+    t.deepEqual(funded, { Ok: 100_000n })
+
+    var overflowing = await icrc7_actors.user2.icrc7_stake({
+        'token_id': 14n,
+        'from_subaccount': [],
+        'duration': 2n ** 64n - 1n
+    })
+    t.deepEqual(overflowing, { Err: { InvalidDuration: null } })
+
+    var staked = await icrc7_actors.user2.icrc7_stake({
+        'token_id': 14n,
+        'from_subaccount': [],
+        'duration': 3_000_000_000n
+    })
+    t.ok(staked.Ok !== undefined)
+
+    var approved = await icrc7_actors.user2.icrc7_approve({
+        'spender': { owner: user3.getPrincipal(), subaccount: [] },
+        'from_subaccount': [],
+        'token_ids': [[14n]],
+        'created_at_time': [],
+        'expires_at': [],
+        'memo': []
+    })
+    t.deepEqual(approved, { Err: { TokenLocked: { tokens_ids: [14n] } } })
+
+    var unstaked = await icrc7_actors.user2.icrc7_unstake({ 'token_id': 14n, 'from_subaccount': [] })
+    t.ok(unstaked.Err.StillLocked !== undefined)
+
+    await new Promise((resolve) => setTimeout(resolve, 4_000))
+
+    // three whole periods, the 10_000 ledger fee comes out of the payout
+    var before = await ledger_actors.user2.icrc1_balance_of({ owner: user2.getPrincipal(), subaccount: [] })
+    var unstaked = await icrc7_actors.user2.icrc7_unstake({ 'token_id': 14n, 'from_subaccount': [] })
+    t.deepEqual(unstaked, { Ok: 60_000n })
+    var after = await ledger_actors.user2.icrc1_balance_of({ owner: user2.getPrincipal(), subaccount: [] })
+    t.equal(after - before, 50_000n)
+    t.equal(await icrc7_actors.user2.icrc7_reward_budget(Principal.fromText(ledger)), 40_000n)
+
+    var history = await icrc7_actors.user2.icrc7_stake_history([14n], [], [])
+    t.deepEqual(history.map((event) => Object.keys(event.kind)[0]), ['Claimed', 'Unlocked', 'Locked'])
+})
+
+test("allowlisted accounts self-mint up to the phase limit", async function (t) {
+    var phase = await icrc7_actors.minter.icrc7_add_mint_phase({
+        'name': "Presale",
+        'eligibility': { Allowlist: null },
+        'price': [],
+        'per_account_limit': [1]
+    })
+    await icrc7_actors.minter.icrc7_add_to_allowlist(phase.Ok, [user4.getPrincipal()])
+    await icrc7_actors.minter.icrc7_set_active_phase([phase.Ok])
+
+    var minted = await icrc7_actors.user4.icrc7_self_mint({ 'to_subaccount': [], 'proof': [], 'from_subaccount': [], 'max_price': [] })
+    t.ok(minted.Ok !== undefined)
+    var owner = await icrc7_actors.user4.icrc7_owner_of(minted.Ok);
+    t.equal(encodeIcrcAccount(owner), user4Account)
+
+    var minted = await icrc7_actors.user4.icrc7_self_mint({ 'to_subaccount': [], 'proof': [], 'from_subaccount': [], 'max_price': [] })
+    t.deepEqual(minted, { Err: { LimitReached: { limit: 1 } } })
+    var minted = await icrc7_actors.user5.icrc7_self_mint({ 'to_subaccount': [], 'proof': [], 'from_subaccount': [], 'max_price': [] })
+    t.deepEqual(minted, { Err: { NotEligible: null } })
+
+    await icrc7_actors.minter.icrc7_set_active_phase([])
+})
+
+test("public phase sells at a fixed price into the treasury", async function (t) {
+    var treasury = { owner: user5.getPrincipal(), subaccount: [] }
+    await icrc7_actors.minter.icrc7_set_treasury([treasury])
+    var phase = await icrc7_actors.minter.icrc7_add_mint_phase({
+        'name': "Public",
+        'eligibility': { Public: null },
+        'price': [{ Fixed: 100_000n }],
+        'per_account_limit': []
+    })
+    await icrc7_actors.minter.icrc7_set_active_phase([phase.Ok])
+    t.deepEqual(await icrc7_actors.user3.icrc7_mint_price(), [100_000n])
+
+    var overflowing = await icrc7_actors.minter.icrc7_add_mint_phase({
+        'name': "Dutch",
+        'eligibility': { Public: null },
+        'price': [{ Dutch: { 'start_price': 2n, 'end_price': 1n, 'start_at': 2n ** 64n - 1n, 'duration': 2n } }],
+        'per_account_limit': []
+    })
+    t.deepEqual(overflowing, { Err: { InvalidPhase: null } })
+
+    await ledger_actors.user3.icrc2_approve({
+        'fee': [],
+        'memo': [],
+        'from_subaccount': [],
+        'created_at_time': [],
+        'amount': 200_000n,
+        'expected_allowance': [],
+        'expires_at': [],
+        'spender': { owner: icrc7, subaccount: [] }
+    })
+    var before = await ledger_actors.user3.icrc1_balance_of(treasury)
+    var minted = await icrc7_actors.user3.icrc7_self_mint({ 'to_subaccount': [], 'proof': [], 'from_subaccount': [], 'max_price': [100_000n] })
+    t.ok(minted.Ok !== undefined)
+    var after = await ledger_actors.user3.icrc1_balance_of(treasury)
+    t.ok(after > before)
+
+    var log = await icrc7_actors.user3.icrc7_sale_log([], [1n])
+    t.deepEqual(log[0].outcome, { Minted: { token_id: minted.Ok } })
+
+    await icrc7_actors.minter.icrc7_set_active_phase([])
+})
+
+test("drop phases open and close on their own", async function (t) {
+    var phase = await icrc7_actors.minter.icrc7_add_mint_phase({
+        'name': "Drop",
+        'eligibility': { Public: null },
+        'price': [],
+        'per_account_limit': [1]
+    })
+    var now = BigInt(Date.now()) * 1_000_000n
+    var set = await icrc7_actors.minter.icrc7_set_drop([[
+        { 'phase_id': phase.Ok, 'start_at': now - 60_000_000_000n, 'end_at': now + 5_000_000_000n }
+    ]])
+    t.deepEqual(set, { Ok: null })
+
+    var status = await icrc7_actors.user5.icrc7_drop_status()
+    t.equal(status.phase[0].name, "Drop")
+    t.ok(status.countdown[0] > 0n)
+    var manual = await icrc7_actors.minter.icrc7_set_active_phase([])
+    t.deepEqual(manual, { Err: { DropScheduled: null } })
+
+    var minted = await icrc7_actors.user5.icrc7_self_mint({ 'to_subaccount': [], 'proof': [], 'from_subaccount': [], 'max_price': [] })
+    t.ok(minted.Ok !== undefined)
+
+    await new Promise((resolve) => setTimeout(resolve, 6_000))
+
+    var status = await icrc7_actors.user5.icrc7_drop_status()
+    t.deepEqual(status.phase, [])
+    t.deepEqual(status.countdown, [])
+    var minted = await icrc7_actors.user5.icrc7_self_mint({ 'to_subaccount': [], 'proof': [], 'from_subaccount': [], 'max_price': [] })
+    t.deepEqual(minted, { Err: { NoActivePhase: null } })
+
+    await icrc7_actors.minter.icrc7_set_drop([])
+})
+
+const sha256 = (bytes) => createHash("sha256").update(bytes).digest()
+const beBytes = (value, size) => Buffer.from(value.toString(16).padStart(size * 2, "0"), "hex")
+const field = (bytes) => Buffer.concat([beBytes(bytes.length, 8), Buffer.from(bytes)])
+const entryHash = (entry) => sha256(Buffer.concat([
+    field(Buffer.from(entry.name)),
+    field(Buffer.from(entry.description[0] || "")),
+    field(entry.image[0] || []),
+]))
+
+test("hidden metadata is revealed against its commitment", async function (t) {
+    var entries = [
+        { 'name': "Gold", 'description': ["rare"], 'image': [] },
+        { 'name': "Silver", 'description': [], 'image': [[1, 2, 3]] },
+    ]
+    var commitment = sha256(Buffer.concat(entries.map(entryHash)))
+
+    var committed = await icrc7_actors.minter.icrc7_commit_reveal({
+        'size': 2n,
+        'placeholder': { 'name': "Hidden", 'description': [], 'image': [] },
+        'commitment': [...commitment]
+    })
+    t.deepEqual(committed, { Ok: null })
+    var phase = await icrc7_actors.minter.icrc7_add_mint_phase({
+        'name': "Mystery",
+        'eligibility': { Public: null },
+        'price': [],
+        'per_account_limit': []
+    })
+    await icrc7_actors.minter.icrc7_set_active_phase([phase.Ok])
+
+    var revealed = await icrc7_actors.minter.icrc7_reveal()
+    t.deepEqual(revealed, { Err: { NotSoldOut: { remaining: 2n } } })
+
+    var first = await icrc7_actors.user2.icrc7_self_mint({ 'to_subaccount': [], 'proof': [], 'from_subaccount': [], 'max_price': [] })
+    var second = await icrc7_actors.user2.icrc7_self_mint({ 'to_subaccount': [], 'proof': [], 'from_subaccount': [], 'max_price': [] })
+    var metadata = await icrc7_actors.user2.icrc7_metadata(first.Ok)
+    t.equal(metadata.find(([key]) => key == "Name")[1].Text, `Hidden #${first.Ok}`)
+    var third = await icrc7_actors.user2.icrc7_self_mint({ 'to_subaccount': [], 'proof': [], 'from_subaccount': [], 'max_price': [] })
+    t.deepEqual(third, { Err: { SoldOut: null } })
+
+    var uploaded = await icrc7_actors.minter.icrc7_upload_reveal([entries[1], entries[0]], [])
+    t.deepEqual(uploaded, { Ok: 2n })
+    var revealed = await icrc7_actors.minter.icrc7_reveal()
+    t.deepEqual(revealed, { Err: { CommitmentMismatch: null } })
+
+    // uploaded entries are overwritten until the reveal
+    var uploaded = await icrc7_actors.minter.icrc7_upload_reveal(entries, [3n])
+    t.deepEqual(uploaded, { Err: { InvalidStart: { uploaded: 2n } } })
+    var uploaded = await icrc7_actors.minter.icrc7_upload_reveal(entries, [0n])
+    t.deepEqual(uploaded, { Ok: 2n })
+    var revealed = await icrc7_actors.minter.icrc7_reveal()
+    t.ok(revealed.Ok !== undefined)
+
+    var names = []
+    for (const token of [first.Ok, second.Ok]) {
+        var metadata = await icrc7_actors.user2.icrc7_metadata(token)
+        names.push(metadata.find(([key]) => key == "Name")[1].Text)
+    }
+    t.deepEqual(names.sort(), ["Gold", "Silver"])
+
+    await icrc7_actors.minter.icrc7_set_active_phase([])
+})
+
+test("vouchers signed by a minter key mint once", async function (t) {
+    await icrc7_actors.minter.icrc7_update_collection_config({
+        'supply_cap': [[1_000n]],
+        'tx_window': [],
+        'permitted_drift': [],
+        'name': [],
+        'description': [],
+        'royalties': [],
+        'royalty_recipient': [],
+        'image': [],
+        'transferability': [],
+        'payment_ledger': []
+    })
+    var signer = Ed25519KeyIdentity.generate()
+    var key = await icrc7_actors.minter.icrc7_add_minter_key({ Ed25519: [...new Uint8Array(signer.getPublicKey().toRaw())] })
+    t.ok(key.Ok !== undefined)
+
+    var metadata = { 'name': "Lazy #9000", 'description': [], 'image': [] }
+    var voucher = {
+        'token_id': 9_000n,
+        'recipient': [],
+        'metadata_hash': [...entryHash(metadata)],
+        'nonce': 1n,
+        'price': 0n,
+        'expires_at': BigInt(Date.now() + 600_000) * 1_000_000n,
+    }
+    var canister = Principal.fromText(icrc7_canister.toString()).toUint8Array()
+    var digest = sha256(Buffer.concat([
+        Buffer.from("icrc7-voucher"),
+        Buffer.from([canister.length]),
+        Buffer.from(canister),
+        beBytes(voucher.token_id, 16),
+        Buffer.from(voucher.metadata_hash),
+        Buffer.from([0]),
+        beBytes(voucher.price, 16),
+        beBytes(voucher.expires_at, 8),
+        beBytes(voucher.nonce, 8),
+    ]))
+    var signature = [...new Uint8Array(await signer.sign(digest))]
+    var redeem = (metadata) => icrc7_actors.user3.icrc7_redeem_voucher({
+        'voucher': voucher,
+        'signature': signature,
+        'metadata': metadata,
+        'to_subaccount': [],
+        'from_subaccount': []
+    })
+
+    var tampered = await redeem({ ...metadata, 'name': "Lazy #1" })
+    t.deepEqual(tampered, { Err: { MetadataMismatch: null } })
+    var redeemed = await redeem(metadata)
+    t.deepEqual(redeemed, { Ok: 9_000n })
+    var owner = await icrc7_actors.user3.icrc7_owner_of(9_000n)
+    t.equal(owner.owner.toText(), user3.getPrincipal().toText())
+    var replayed = await redeem(metadata)
+    t.deepEqual(replayed, { Err: { NonceUsed: null } })
+})
+
+test("airdrop job mints to every uploaded recipient", async function (t) {
+    var template = { 'name': "Airdrop", 'description': [], 'image': [] }
+    var job = await icrc7_actors.minter.icrc7_create_airdrop({ Mint: { 'template': template } })
+    t.ok(job.Ok !== undefined)
+
+    var uploaded = await icrc7_actors.minter.icrc7_upload_airdrop(job.Ok, [
+        { 'to': { owner: user4.getPrincipal(), subaccount: [] }, 'token_id': 9_100n },
+        { 'to': { owner: user5.getPrincipal(), subaccount: [] }, 'token_id': 9_101n },
+    ])
+    t.deepEqual(uploaded, { Ok: 2n })
+    var uploaded = await icrc7_actors.minter.icrc7_upload_airdrop(job.Ok, [
+        { 'to': { owner: user5.getPrincipal(), subaccount: [] }, 'token_id': 9_000n },
+    ])
+    t.deepEqual(uploaded, { Ok: 3n })
+    var started = await icrc7_actors.minter.icrc7_start_airdrop(job.Ok)
+    t.deepEqual(started, { Ok: null })
+
+    await new Promise((resolve) => setTimeout(resolve, 3_000))
+
+    var airdrop = await icrc7_actors.minter.icrc7_airdrop(job.Ok)
+    t.deepEqual(airdrop[0].status, { Completed: null })
+    t.equal(airdrop[0].succeeded, 2n)
+    t.equal(airdrop[0].failed, 1n)
+    var failures = await icrc7_actors.minter.icrc7_airdrop_failures(job.Ok, [], [])
+    t.deepEqual(failures.map((failure) => [failure.index, failure.reason]), [[2n, "Id Exist"]])
+    var owner = await icrc7_actors.user5.icrc7_owner_of(9_101n)
+    t.equal(owner.owner.toText(), user5.getPrincipal().toText())
+
+    var cancelled = await icrc7_actors.minter.icrc7_cancel_airdrop(job.Ok)
+    t.deepEqual(cancelled, { Err: { Finished: null } })
+})
+
+test("snapshot shows owners as of an earlier transaction", async function (t) {
+    var transfer = await icrc7_actors.user5.icrc7_transfer({
+        'to': {
+            owner: user4.getPrincipal(),
+            subaccount: []
+        },
+        'spender_subaccount': [],
+        'from': {
+            owner: user5.getPrincipal(),
+            subaccount: []
+        },
+        'memo': [],
+        'is_atomic': [],
+        'token_ids': [9_101n],
+        'created_at_time': []
+    })
+    t.ok(transfer.Ok !== undefined)
+
+    var before = await icrc7_actors.user1.icrc7_owner_at(9_101n, transfer.Ok - 1n)
+    t.equal(before[0].owner.toText(), user5.getPrincipal().toText())
+    var after = await icrc7_actors.user1.icrc7_owner_at(9_101n, transfer.Ok)
+    t.equal(after[0].owner.toText(), user4.getPrincipal().toText())
+    var snapshot = await icrc7_actors.user1.icrc7_snapshot(transfer.Ok - 1n, [9_100n], [1n])
+    t.deepEqual(snapshot.owners.map(([id, owner]) => [id, owner.owner.toText()]), [[9_101n, user5.getPrincipal().toText()]])
+    t.deepEqual(snapshot.cursor, [9_101n])
+
+    var id = await icrc7_actors.minter.icrc7_export_snapshot([transfer.Ok - 1n])
+
+    await new Promise((resolve) => setTimeout(resolve, 3_000))
+
+    var exported = await icrc7_actors.user1.icrc7_snapshot_export(id)
+    t.deepEqual(exported[0].status, { Ready: null })
+    t.ok(exported[0].tokens > 0n)
+})
+
+test("token history lists events newest first", async function (t) {
+    var history = await icrc7_actors.user1.icrc7_token_history(9_101n, [], [])
+    t.deepEqual(history.map((event) => Object.keys(event.kind)[0]), ["Transfer", "Mint"])
+    t.equal(history[0].kind.Transfer.from.owner.toText(), user5.getPrincipal().toText())
+    t.equal(history[0].kind.Transfer.to.owner.toText(), user4.getPrincipal().toText())
+    t.equal(history[1].kind.Mint.to.owner.toText(), user5.getPrincipal().toText())
+
+    var page = await icrc7_actors.user1.icrc7_token_history(9_101n, [history[0].position], [1n])
+    t.deepEqual(page.map((event) => event.position), [history[1].position])
+})
+
+test("last owner withdraws from a burned token's account", async function (t) {
+    await icrc7_actors.minter.icrc7_mint({
+        'id': 9_300n,
+        'to': { owner: user1.getPrincipal(), subaccount: [] },
+        'name': "Token",
+        'description': [],
+        'image': [],
+        'transferability': []
+    })
+    var account = (await icrc7_actors.user1.icrc7_token_account(9_300n))[0]
+    await ledger_actors.minter.mint(account, 100_000n)
+
+    var burned = await icrc7_actors.user1.icrc7_burn({ 'from_subaccount': [], 'token_ids': [9_300n] })
+    t.ok(burned.Ok !== undefined)
+    t.deepEqual(await icrc7_actors.user1.icrc7_token_account(9_300n), [account])
+
+    var withdraw = (actor, to) => actor.icrc7_token_withdraw({
+        'token_id': 9_300n,
+        'from_subaccount': [],
+        'ledger': [],
+        'to': { owner: to, subaccount: [] },
+        'amount': 50_000n
+    })
+    t.deepEqual(await withdraw(icrc7_actors.user2, user2.getPrincipal()), { Err: { Unauthorized: { tokens_ids: [9_300n] } } })
+    t.deepEqual(await withdraw(icrc7_actors.user1, user1.getPrincipal()), { Ok: null })
+
+    var balance = await ledger_actors.user2.icrc1_balance_of(account)
+    t.equal(balance, 40_000n)
+})
+
+test("bought out token is fractionalized again once every share is claimed", async function (t) {
+    await icrc7_actors.minter.icrc7_mint({
+        'id': 9_301n,
+        'to': { owner: user1.getPrincipal(), subaccount: [] },
+        'name': "Token",
+        'description': [],
+        'image': [],
+        'transferability': []
+    })
+    var fractionalize = (actor) => actor.icrc7_fractionalize({
+        'token_id': 9_301n,
+        'from_subaccount': [],
+        'shares': 10n,
+        'reserve_price': [100_000n]
+    })
+    t.deepEqual(await fractionalize(icrc7_actors.user1), { Ok: null })
+    var share_ledger = (await icrc7_actors.user1.icrc7_fraction(9_301n))[0].share_ledger
+    var shares_user1 = await getActor(share_ledger, share_ledger_interface, user1)
+    await transferShares(shares_user1, user3, 4n)
+
+    await ledger_actors.user2.icrc2_approve({
+        'fee': [],
+        'memo': [],
+        'from_subaccount': [],
+        'created_at_time': [],
+        'amount': 110_000n,
+        'expected_allowance': [],
+        'expires_at': [],
+        'spender': { owner: Principal.fromText(icrc7_canister.toString()), subaccount: [] }
+    })
+    var bought = await icrc7_actors.user2.icrc7_buyout({ 'token_id': 9_301n, 'from_subaccount': [] })
+    t.deepEqual(bought, { Ok: null })
+    var owner = await icrc7_actors.user2.icrc7_owner_of(9_301n)
+    t.equal(owner.owner.toText(), user2.getPrincipal().toText())
+
+    // the vault still owes the share holders their part of the price
+    t.deepEqual(await fractionalize(icrc7_actors.user2), { Err: { SharesOutstanding: { outstanding: 10n } } })
+
+    var claim = (actor) => actor.icrc7_claim_buyout({ 'token_id': 9_301n, 'from_subaccount': [] })
+    t.deepEqual(await claim(icrc7_actors.user1), { Ok: 60_000n })
+    t.deepEqual(await claim(icrc7_actors.user3), { Ok: 40_000n })
+
+    t.deepEqual(await fractionalize(icrc7_actors.user2), { Ok: null })
+    var fraction = (await icrc7_actors.user2.icrc7_fraction(9_301n))[0]
+    t.equal(fraction.issuer.owner.toText(), user2.getPrincipal().toText())
+    t.equal(fraction.outstanding, 10n)
+    t.deepEqual(fraction.status, { Active: null })
+
+    // the share ledger of the token is reinstalled rather than spawned again
+    t.equal(fraction.share_ledger.toText(), share_ledger.toText())
+    t.equal(await shares_user1.icrc1_balance_of({ owner: user2.getPrincipal(), subaccount: [] }), 10n)
+})
+
+test("transfer and call reaches the receiving canister", async function (t) {
+    var send = (token_id, to, data) => icrc7_actors.user4.icrc7_transfer_and_call({
+        'to': {
+            owner: to,
+            subaccount: []
+        },
+        'from': {
+            owner: user4.getPrincipal(),
+            subaccount: []
+        },
+        'memo': [],
+        'spender_subaccount': [],
+        'token_ids': [token_id],
+        'created_at_time': [],
+        'data': [...Buffer.from(data)]
+    })
+
+    var not_canister = await send(9_100n, user5.getPrincipal(), "")
+    t.deepEqual(not_canister, { Err: { NotCanister: null } })
+
+    var rejected = await send(9_100n, Principal.fromText(receiver), "reject")
+    t.deepEqual(rejected, { Err: { Rejected: { reason: "Rejected" } } })
+    var owner = await icrc7_actors.user4.icrc7_owner_of(9_100n)
+    t.equal(owner.owner.toText(), user4.getPrincipal().toText())
+
+    var accepted = await send(9_101n, Principal.fromText(receiver), "")
+    t.ok(accepted.Ok !== undefined)
+    var owner = await icrc7_actors.user4.icrc7_owner_of(9_101n)
+    t.equal(owner.owner.toText(), receiver)
+
+    // a spender sends on behalf of the owner, the hook is told who owned the tokens
+    await icrc7_actors.user4.icrc7_approve({
+        'spender': { owner: user5.getPrincipal(), subaccount: [] },
+        'token_ids': [[9_100n]],
+        'memo': [],
+        'created_at_time': [],
+        'from_subaccount': [],
+        'expires_at': [],
+    })
+    var spend = (from) => icrc7_actors.user5.icrc7_transfer_and_call({
+        'to': { owner: Principal.fromText(receiver), subaccount: [] },
+        'from': { owner: from, subaccount: [] },
+        'memo': [],
+        'spender_subaccount': [],
+        'token_ids': [9_100n],
+        'created_at_time': [],
+        'data': []
+    })
+    var unauthorized = await spend(user5.getPrincipal())
+    t.deepEqual(unauthorized, { Err: { Transfer: { Unauthorized: { tokens_ids: [9_100n] } } } })
+    var spent = await spend(user4.getPrincipal())
+    t.ok(spent.Ok !== undefined)
+
+    var receiver_actor = await getActor(receiver, receiver_interface, user1)
+    var received = await receiver_actor.received()
+    var [from, token_ids] = received[received.length - 1]
+    t.equal(from.owner.toText(), user4.getPrincipal().toText())
+    t.deepEqual(token_ids, [9_100n])
+})
+
+test("subscribed canister is notified of matching events", async function (t) {
+    var subscription = await icrc7_actors.user1.icrc7_subscribe({
+        'subscriber': [],
+        'events': [{ Mint: null }],
+        'token_ids': [],
+        'account': []
+    })
+    t.deepEqual(subscription, { Err: { NotCanister: null } })
+
+    var subscribe_receiver = () => icrc7_actors.minter.icrc7_subscribe({
+        'subscriber': [Principal.fromText(receiver)],
+        'events': [{ Mint: null }],
+        'token_ids': [[9_200n]],
+        'account': []
+    })
+    t.deepEqual(await subscribe_receiver(), { Err: { Unauthorized: null } })
+    await icrc7_actors.minter.icrc7_grant_role({
+        'principal': Principal.fromText(receiver),
+        'role': { Subscriber: null }
+    })
+
+    var subscription = await subscribe_receiver()
+    t.ok(subscription.Ok !== undefined)
+
+    for (const id of [9_200n, 9_201n]) {
+        await icrc7_actors.minter.icrc7_mint({
+            'id': id,
+            'to': {
+                owner: user1.getPrincipal(),
+                subaccount: []
+            },
+            'name': "Token",
+            'description': [],
+            'image': [],
+            'transferability': []
+        })
+    }
+
+    await new Promise((resolve) => setTimeout(resolve, 3_000))
+
+    var receiver_actor = await getActor(receiver, receiver_interface, user1)
+    var events = await receiver_actor.events()
+    t.deepEqual(events.map((event) => event.token_id), [9_200n])
+    var delivered = await icrc7_actors.user1.icrc7_subscription(subscription.Ok)
+    t.equal(delivered[0].delivered, 1n)
+
+    var subscribe = () => icrc7_actors.minter.icrc7_subscribe({
+        'subscriber': [Principal.fromText(receiver)],
+        'events': [{ Burn: null }],
+        'token_ids': [[0n]],
+        'account': []
+    })
+    var extra = []
+    for (var i = 1; i < 10; i++) {
+        extra.push((await subscribe()).Ok)
+    }
+    t.deepEqual(await subscribe(), { Err: { SubscriberLimitReached: { max: 10n } } })
+    for (const id of extra) {
+        t.deepEqual(await icrc7_actors.minter.icrc7_unsubscribe(id), { Ok: null })
+    }
+
+    // ids are not reused after the newest subscription is dropped
+    var next = await subscribe()
+    t.ok(next.Ok > extra[extra.length - 1])
+
+    t.equal(await icrc7_actors.minter.icrc7_evict_subscriber(Principal.fromText(receiver)), 2n)
+    t.deepEqual(await icrc7_actors.user1.icrc7_subscription(subscription.Ok), [])
+})
+
+test("low cycles balance raises an alert and tops up from the cycles ledger", async function (t) {
+    var balance = await icrc7_actors.minter.icrc7_cycles_balance()
+    t.ok(balance > 0n)
+
+    var payer = await getActor(ledger, ledger_interface, user3)
+    var account = { owner: user3.getPrincipal(), subaccount: [] }
+    await ledger_actors.minter.mint(account, 10_000_000_000n)
+    await payer.icrc2_approve({
+        'fee': [],
+        'memo': [],
+        'from_subaccount': [],
+        'created_at_time': [],
+        'amount': 1_000_010_000n,
+        'expected_allowance': [],
+        'expires_at': [],
+        'spender': { owner: Principal.fromText(icrc7_canister.toString()), subaccount: [] }
+    })
+
+    var config = await icrc7_actors.minter.icrc7_set_cycles_config({
+        'low_water_mark': [balance * 1_000n],
+        'top_up': [{ 'ledger': Principal.fromText(ledger), 'from': account, 'amount': 1_000_000_000n }]
+    })
+    t.deepEqual(config, { Ok: null })
+
+    var state = await icrc7_actors.minter.icrc7_check_cycles()
+    t.equal(state.low_since.length, 1)
+    t.deepEqual(state.last_top_up[0].error, [])
+    t.equal(state.last_top_up[0].amount, 1_000_000_000n)
+    var paid = await payer.icrc1_balance_of(account)
+    t.equal(paid, 10_000_000_000n - 1_000_010_000n)
+})
+
+test("upgrade from the first release migrates its stable state", async function (t) {
+    var agent = getAgent(minter)
+    await agent.fetchRootKey()
+    var canisterId = await Actor.createCanister({ agent })
+
+    await Actor.install({
+        module: gzipSync(readFileSync(process.env.ICRC7_BASELINE_WASM)),
+        arg: IDL.encode(baseline_init({ IDL }), [{
+            'supply_cap': [],
+            'tx_window': 0n,
+            'permitted_drift': 0n,
+            'name': "Baseline",
+            'description': [],
+            'minting_authority': minter.getPrincipal(),
+            'royalties': [],
+            'royalty_recipient': [],
+            'image': [],
+            'symbol': "BASE"
+        }])
+    }, { canisterId, agent })
+
+    var baseline = {
+        minter: await getActor(canisterId, baseline_interface, minter),
+        user1: await getActor(canisterId, baseline_interface, user1)
+    }
+    for (var id of [1n, 2n]) {
+        await baseline.minter.icrc7_mint({
+            'id': id,
+            'to': { owner: user1.getPrincipal(), subaccount: [] },
+            'name': "Baseline #" + id,
+            'description': [],
+            'image': []
+        })
+    }
+    var transfer = await baseline.user1.icrc7_transfer({
+        'to': { owner: user2.getPrincipal(), subaccount: [] },
+        'spender_subaccount': [],
+        'from': { owner: user1.getPrincipal(), subaccount: [] },
+        'memo': [[1, 2, 3]],
+        'is_atomic': [],
+        'token_ids': [1n],
+        'created_at_time': []
+    })
+    t.ok(transfer.Ok !== undefined)
+
+    await Actor.install({
+        module: gzipSync(readFileSync("./target/wasm32-unknown-unknown/release/icrc7.wasm")),
+        mode: CanisterInstallMode.Upgrade,
+        arg: IDL.encode(icrc7_init({ IDL }), [{ Upgrade: [] }])
+    }, { canisterId, agent })
+
+    var upgraded = {
+        minter: await getActor(canisterId, icrc7_interace, minter),
+        user2: await getActor(canisterId, icrc7_interace, user2)
+    }
+    var status = await upgraded.minter.icrc7_migration_status()
+    for (var attempt = 0; attempt < 10 && status.schema_version < status.latest_version; attempt++) {
+        await new Promise((resolve) => setTimeout(resolve, 1_000))
+        status = await upgraded.minter.icrc7_migration_status()
+    }
+    t.equal(status.schema_version, status.latest_version)
+    t.deepEqual(status.cursor, [])
+
+    t.equal(await upgraded.minter.icrc7_name(), "Baseline")
+    t.equal(await upgraded.minter.icrc7_total_supply(), 2n)
+    var owner = await upgraded.minter.icrc7_owner_of(1n)
+    t.equal(owner.owner.toText(), user2.getPrincipal().toText())
+    var metadata = await upgraded.minter.icrc7_metadata(2n)
+    t.deepEqual(metadata.find(([key]) => key === "Transferability")[1], { Text: "Transferable" })
+
+    // the legacy entry keeps its position and layout, without a transaction index
+    var log = JSON.parse((await httpGet("/transfer_log?id=0", upgraded.minter)).body)
+    t.equal(log.tx_index, null)
+    t.deepEqual(log.memo, [1, 2, 3])
+    t.equal(log.op.Transfer.id, 1)
+    t.equal(log.op.Transfer.to.owner, user2.getPrincipal().toText())
+
+    // replayed from the log, the legacy transfer is placed at the earliest transaction it can have been
+    var snapshot = await upgraded.minter.icrc7_snapshot(0n, [], [])
+    t.deepEqual(snapshot.owners.map(([id, owner]) => [id, owner.owner.toText()]), [
+        [1n, user2.getPrincipal().toText()],
+        [2n, user1.getPrincipal().toText()]
+    ])
+    t.deepEqual(snapshot.cursor, [])
+
+    transfer = await upgraded.user2.icrc7_transfer({
+        'to': { owner: user3.getPrincipal(), subaccount: [] },
+        'spender_subaccount': [],
+        'from': { owner: user2.getPrincipal(), subaccount: [] },
+        'memo': [],
+        'is_atomic': [],
+        'token_ids': [1n],
+        'created_at_time': []
+    })
+    t.ok(transfer.Ok !== undefined)
+    log = JSON.parse((await httpGet("/transfer_log?id=1", upgraded.minter)).body)
+    t.equal(log.op.Transfer.to.owner, user3.getPrincipal().toText())
+    t.equal(BigInt(log.tx_index), transfer.Ok)
+    var before = await upgraded.minter.icrc7_owner_at(1n, transfer.Ok - 1n)
+    t.equal(before[0].owner.toText(), user2.getPrincipal().toText())
+    var after = await upgraded.minter.icrc7_owner_at(1n, transfer.Ok)
+    t.equal(after[0].owner.toText(), user3.getPrincipal().toText())
+
+    // the legacy transfer is backfilled below the events recorded since the upgrade
+    var history = await upgraded.minter.icrc7_token_history(1n, [], [])
+    t.deepEqual(history.map((event) => event.position), [1n, 0n])
+    t.equal(history[0].kind.Transfer.to.owner.toText(), user3.getPrincipal().toText())
+    t.equal(history[1].kind.Transfer.from.owner.toText(), user1.getPrincipal().toText())
+    t.equal(history[1].kind.Transfer.to.owner.toText(), user2.getPrincipal().toText())
+    t.deepEqual([...history[1].kind.Transfer.memo[0]], [1, 2, 3])
+    // mints of the first release were not logged
+    t.deepEqual(await upgraded.minter.icrc7_token_history(2n, [], []), [])
+})
@@ -0,0 +1,38 @@
+const idlFactory = ({ IDL }) => {
+  const ICRCAccount = IDL.Record({
+    'owner' : IDL.Principal,
+    'subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
+  const ICRC1MetadataValue = IDL.Variant({
+    'Int' : IDL.Int,
+    'Nat' : IDL.Nat,
+    'Blob' : IDL.Vec(IDL.Nat8),
+    'Text' : IDL.Text,
+  });
+  const ICRC1TransferArgs = IDL.Record({
+    'to' : ICRCAccount,
+    'fee' : IDL.Opt(IDL.Nat),
+    'memo' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'created_at_time' : IDL.Opt(IDL.Nat64),
+    'amount' : IDL.Nat,
+  });
+  const ICRC1TransferError = IDL.Variant({
+    'GenericError' : IDL.Record({ 'message' : IDL.Text, 'error_code' : IDL.Nat }),
+    'TemporarilyUnavailable' : IDL.Null,
+    'BadBurn' : IDL.Record({ 'min_burn_amount' : IDL.Nat }),
+    'Duplicate' : IDL.Record({ 'duplicate_of' : IDL.Nat }),
+    'BadFee' : IDL.Record({ 'expected_fee' : IDL.Nat }),
+    'CreatedInFuture' : IDL.Record({ 'ledger_time' : IDL.Nat64 }),
+    'TooOld' : IDL.Null,
+    'InsufficientFunds' : IDL.Record({ 'balance' : IDL.Nat }),
+  });
+  const Result = IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : ICRC1TransferError });
+  return IDL.Service({
+    'icrc1_balance_of' : IDL.Func([ICRCAccount], [IDL.Nat], ['query']),
+    'icrc1_metadata' : IDL.Func([], [IDL.Vec(IDL.Tuple(IDL.Text, ICRC1MetadataValue))], ['query']),
+    'icrc1_total_supply' : IDL.Func([], [IDL.Nat], ['query']),
+    'icrc1_transfer' : IDL.Func([ICRC1TransferArgs], [Result], []),
+  });
+};
+module.exports = { idlFactory };
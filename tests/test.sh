@@ -6,4 +6,17 @@ cp ./src/declarations/icrc7/icrc7.did.js ./icrc7.did.test.cjs
 sed -i '' 's/export//g' ./icrc7.did.test.cjs
 echo "module.exports = { idlFactory };" >> ./icrc7.did.test.cjs
 
+# the upgrade test installs the first release of icrc7 and upgrades it to this build,
+# pinned so that history rewrites or shallow clones do not change what it upgrades from
+baseline_ref=${ICRC7_BASELINE_REF:-f85b47d6d4cec6bd14b508750d86c0efde9e0d97}
+baseline_dir=$(mktemp -d)
+git worktree add --detach "$baseline_dir" "$baseline_ref"
+cargo build --manifest-path="$baseline_dir/Cargo.toml" \
+    --target wasm32-unknown-unknown \
+    --release \
+    --package icrc7 \
+    --target-dir ./target/baseline
+git worktree remove --force "$baseline_dir"
+export ICRC7_BASELINE_WASM=./target/baseline/wasm32-unknown-unknown/release/icrc7.wasm
+
 tape ./tests/icrc7.test.cjs
\ No newline at end of file
@@ -0,0 +1,69 @@
+ const idlFactory = ({ IDL }) => {
+  const ICRCAccount = IDL.Record({
+    'owner' : IDL.Principal,
+    'subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
+  const ICRC1TransferArgs = IDL.Record({
+    'to' : ICRCAccount,
+    'fee' : IDL.Opt(IDL.Nat),
+    'memo' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'created_at_time' : IDL.Opt(IDL.Nat64),
+    'amount' : IDL.Nat,
+  });
+  const ICRC1TransferError = IDL.Variant({
+    'GenericError' : IDL.Record({ 'message' : IDL.Text, 'error_code' : IDL.Nat }),
+    'TemporarilyUnavailable' : IDL.Null,
+    'BadBurn' : IDL.Record({ 'min_burn_amount' : IDL.Nat }),
+    'Duplicate' : IDL.Record({ 'duplicate_of' : IDL.Nat }),
+    'BadFee' : IDL.Record({ 'expected_fee' : IDL.Nat }),
+    'CreatedInFuture' : IDL.Record({ 'ledger_time' : IDL.Nat64 }),
+    'TooOld' : IDL.Null,
+    'InsufficientFunds' : IDL.Record({ 'balance' : IDL.Nat }),
+  });
+  const Result = IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : ICRC1TransferError });
+  const ICRC2ApproveArgs = IDL.Record({
+    'fee' : IDL.Opt(IDL.Nat),
+    'memo' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'from_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+    'created_at_time' : IDL.Opt(IDL.Nat64),
+    'amount' : IDL.Nat,
+    'expected_allowance' : IDL.Opt(IDL.Nat),
+    'expires_at' : IDL.Opt(IDL.Nat64),
+    'spender' : ICRCAccount,
+  });
+  const ICRC2ApproveError = IDL.Variant({
+    'GenericError' : IDL.Record({ 'message' : IDL.Text, 'error_code' : IDL.Nat }),
+    'TemporarilyUnavailable' : IDL.Null,
+    'Duplicate' : IDL.Record({ 'duplicate_of' : IDL.Nat }),
+    'BadFee' : IDL.Record({ 'expected_fee' : IDL.Nat }),
+    'AllowanceChanged' : IDL.Record({ 'current_allowance' : IDL.Nat }),
+    'CreatedInFuture' : IDL.Record({ 'ledger_time' : IDL.Nat64 }),
+    'TooOld' : IDL.Null,
+    'Expired' : IDL.Record({ 'ledger_time' : IDL.Nat64 }),
+    'InsufficientFunds' : IDL.Record({ 'balance' : IDL.Nat }),
+  });
+  const Result_1 = IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : ICRC2ApproveError });
+  const WithdrawFromArgs = IDL.Record({
+    'to' : IDL.Principal,
+    'from' : ICRCAccount,
+    'created_at_time' : IDL.Opt(IDL.Nat64),
+    'amount' : IDL.Nat,
+    'spender_subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
+  const WithdrawFromError = IDL.Variant({
+    'InsufficientFunds' : IDL.Record({ 'balance' : IDL.Nat }),
+    'InsufficientAllowance' : IDL.Record({ 'allowance' : IDL.Nat }),
+    'FailedToWithdrawFrom' : IDL.Record({ 'rejection_reason' : IDL.Text }),
+  });
+  const Result_2 = IDL.Variant({ 'Ok' : IDL.Nat, 'Err' : WithdrawFromError });
+  return IDL.Service({
+    'icrc1_balance_of' : IDL.Func([ICRCAccount], [IDL.Nat], ['query']),
+    'icrc1_fee' : IDL.Func([], [IDL.Nat], ['query']),
+    'icrc1_transfer' : IDL.Func([ICRC1TransferArgs], [Result], []),
+    'icrc2_approve' : IDL.Func([ICRC2ApproveArgs], [Result_1], []),
+    'mint' : IDL.Func([ICRCAccount, IDL.Nat], [IDL.Nat], []),
+    'withdraw_from' : IDL.Func([WithdrawFromArgs], [Result_2], []),
+  });
+};
+module.exports = { idlFactory };
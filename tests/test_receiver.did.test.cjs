@@ -0,0 +1,16 @@
+ const idlFactory = ({ IDL }) => {
+  const Event = IDL.Record({ 'token_id' : IDL.Nat, 'position' : IDL.Nat64 });
+  const ICRCAccount = IDL.Record({
+    'owner' : IDL.Principal,
+    'subaccount' : IDL.Opt(IDL.Vec(IDL.Nat8)),
+  });
+  return IDL.Service({
+    'events' : IDL.Func([], [IDL.Vec(Event)], ['query']),
+    'received' : IDL.Func(
+        [],
+        [IDL.Vec(IDL.Tuple(ICRCAccount, IDL.Vec(IDL.Nat)))],
+        ['query'],
+      ),
+  });
+};
+module.exports = { idlFactory };